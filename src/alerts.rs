@@ -0,0 +1,513 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::{Body, Response};
+use hyper::http::StatusCode;
+
+use crate::encoding::{respond, Format};
+use crate::model::{Alert, AlertState, Snapshot};
+
+/// How long resolved alerts are still listed by `/alerts`.
+const RESOLVED_RETENTION: u64 = 15 * 60;
+
+/// Threshold rule evaluated against every snapshot of the background sampler, written
+/// `[NAME:] METRIC OP THRESHOLD [for DURATION]`, e.g. `low_memory: memory.available < 5% for 2m`.
+///
+/// Metrics are `memory.{available,free,used,total}` and `swap.{free,used,total}` in bytes,
+/// `disk[DISK].{available,used,total}` in bytes, `temperature[COMPONENT]` in °C,
+/// `cpu[CPU].usage` in percent and `load.{one,five,fifteen}`, `*` selecting every disk,
+/// component or CPU. Disks are selected by device name or mount point. Thresholds are
+/// numbers, byte sizes such as `10GiB` or `500MB`, percentages of the total memory, swap or disk
+/// space, or multiples of the number of CPUs such as `cpus*2`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    name: String,
+    metric: Metric,
+    comparison: Comparison,
+    threshold: Threshold,
+    /// How long the condition must hold before the alert fires
+    duration: Duration,
+    expression: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Any,
+    Name(String),
+}
+
+impl Selector {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Selector::Any => true,
+            Selector::Name(selected) => selected == name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Metric {
+    Memory(&'static str),
+    Swap(&'static str),
+    Disk(Selector, &'static str),
+    Temperature(Selector),
+    Cpu(Selector),
+    Load(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Threshold {
+    Value(f64),
+    /// Percentage of the metric's total
+    Percent(f64),
+    /// Multiple of the number of CPUs
+    Cpus(f64),
+}
+
+/// Value of a metric for one of the instances it selects.
+struct Sample {
+    instance: Option<String>,
+    value: f64,
+    total: Option<f64>,
+}
+
+// `field` among `fields`, as a static name
+fn field(fields: &[&'static str], field: &str, metric: &str) -> Result<&'static str, String> {
+    fields.iter().copied().find(|f| *f == field)
+        .ok_or_else(|| format!("Unknown field `{}` of `{}`, expected one of {}", field, metric, fields.join(", ")))
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Metric, String> {
+        let (name, selector, attribute) = match value.split_once('[') {
+            Some((name, rest)) => {
+                let (selected, attribute) = rest.split_once(']').ok_or_else(|| format!("Missing `]` in `{}`", value))?;
+                let selector = match selected {
+                    "*" => Selector::Any,
+                    selected => Selector::Name(selected.to_string()),
+                };
+                (name, Some(selector), attribute.strip_prefix('.').unwrap_or(attribute))
+            }
+            None => match value.split_once('.') {
+                Some((name, attribute)) => (name, None, attribute),
+                None => (value, None, ""),
+            },
+        };
+        match (name, selector) {
+            ("memory", None) => Ok(Metric::Memory(field(&["available", "free", "used", "total"], attribute, name)?)),
+            ("swap", None) => Ok(Metric::Swap(field(&["free", "used", "total"], attribute, name)?)),
+            ("load", None) => Ok(Metric::Load(field(&["one", "five", "fifteen"], attribute, name)?)),
+            ("disk", Some(selector)) => Ok(Metric::Disk(selector, field(&["available", "used", "total"], attribute, name)?)),
+            ("temperature", Some(selector)) if attribute.is_empty() => Ok(Metric::Temperature(selector)),
+            ("cpu", Some(selector)) => field(&["usage"], attribute, name).map(|_| Metric::Cpu(selector)),
+            ("disk" | "temperature" | "cpu", _) => Err(format!("`{}` needs a selector such as `{}[*]`", value, name)),
+            _ => Err(format!("Unknown metric `{}`", value)),
+        }
+    }
+}
+
+impl Metric {
+    fn in_bytes(&self) -> bool {
+        matches!(self, Metric::Memory(_) | Metric::Swap(_) | Metric::Disk(..))
+    }
+
+    fn has_total(&self) -> bool {
+        self.in_bytes()
+    }
+
+    fn samples(&self, snapshot: &Snapshot) -> Vec<Sample> {
+        let sample = |value: u64, total: u64| Sample { instance: None, value: value as f64, total: Some(total as f64) };
+        match self {
+            Metric::Memory(field) => snapshot.memory.iter().map(|memory| {
+                let value = match *field {
+                    "available" => memory.available_memory,
+                    "free" => memory.free_memory,
+                    "used" => memory.used_memory,
+                    _ => memory.total_memory,
+                };
+                sample(value, memory.total_memory)
+            }).collect(),
+            Metric::Swap(field) => snapshot.memory.iter().map(|memory| {
+                let value = match *field {
+                    "free" => memory.free_swap,
+                    "used" => memory.used_swap,
+                    _ => memory.total_swap,
+                };
+                sample(value, memory.total_swap)
+            }).collect(),
+            Metric::Disk(selector, field) => snapshot.disks.iter().flatten()
+                .filter(|disk| selector.matches(&disk.device_name) || selector.matches(&disk.mount_point))
+                .map(|disk| {
+                    let value = match *field {
+                        "available" => disk.available_space,
+                        "used" => disk.total_space.saturating_sub(disk.available_space),
+                        _ => disk.total_space,
+                    };
+                    Sample { instance: Some(disk.mount_point.clone()), ..sample(value, disk.total_space) }
+                })
+                .collect(),
+            Metric::Temperature(selector) => snapshot.temperatures.iter().flatten()
+                .filter(|temperature| selector.matches(&temperature.name))
+                .map(|temperature| Sample { instance: Some(temperature.name.clone()), value: temperature.temperature, total: None })
+                .collect(),
+            Metric::Cpu(selector) => snapshot.cpus.iter().flatten()
+                .filter(|cpu| selector.matches(&cpu.cpu_num))
+                .map(|cpu| Sample { instance: Some(cpu.cpu_num.clone()), value: cpu.percent, total: None })
+                .collect(),
+            Metric::Load(field) => snapshot.load_average.iter().map(|load| {
+                let value = match *field {
+                    "one" => load.one,
+                    "five" => load.five,
+                    _ => load.fifteen,
+                };
+                Sample { instance: None, value, total: None }
+            }).collect(),
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Less => value < threshold,
+            Comparison::LessOrEqual => value <= threshold,
+            Comparison::Greater => value > threshold,
+            Comparison::GreaterOrEqual => value >= threshold,
+        }
+    }
+}
+
+// Multiplier of a byte size suffix, decimal or binary
+fn byte_unit(unit: &str) -> Option<f64> {
+    let units = [("B", 1.0), ("KB", 1e3), ("MB", 1e6), ("GB", 1e9), ("TB", 1e12)];
+    let binary = [("KiB", 1024.0), ("MiB", 1024.0 * 1024.0), ("GiB", 1024.0 * 1024.0 * 1024.0), ("TiB", 1024.0_f64.powi(4))];
+    units.iter().chain(&binary).find(|(suffix, _)| *suffix == unit).map(|(_, multiplier)| *multiplier)
+}
+
+impl Threshold {
+    fn parse(value: &str, metric: &Metric) -> Result<Threshold, String> {
+        let number = |number: &str| number.trim().parse::<f64>().map_err(|_| format!("Invalid threshold `{}`", value));
+        if let Some((left, right)) = value.split_once('*') {
+            return match (left.trim(), right.trim()) {
+                ("cpus", factor) | (factor, "cpus") => Ok(Threshold::Cpus(number(factor)?)),
+                _ => Err(format!("Invalid threshold `{}`, multiply `cpus` only", value)),
+            };
+        }
+        if value == "cpus" {
+            return Ok(Threshold::Cpus(1.0));
+        }
+        if let Some(percent) = value.strip_suffix('%') {
+            if !metric.has_total() {
+                return Err(format!("Percentages are of the total memory, swap or disk space, not of `{}`", value));
+            }
+            return Ok(Threshold::Percent(number(percent)?));
+        }
+        let split = value.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(value.len());
+        let (count, unit) = value.split_at(split);
+        match unit {
+            "" => Ok(Threshold::Value(number(count)?)),
+            unit if metric.in_bytes() => match byte_unit(unit) {
+                Some(multiplier) => Ok(Threshold::Value(number(count)? * multiplier)),
+                None => Err(format!("Unknown unit `{}` in `{}`", unit, value)),
+            },
+            _ => Err(format!("Sizes such as `{}` only apply to memory, swap and disks", value)),
+        }
+    }
+
+    fn resolve(self, sample: &Sample, cpus: usize) -> f64 {
+        match self {
+            Threshold::Value(value) => value,
+            Threshold::Percent(percent) => sample.total.unwrap_or_default() * percent / 100.0,
+            Threshold::Cpus(factor) => cpus as f64 * factor,
+        }
+    }
+}
+
+// `90`, `30s`, `2m`, `1h` or `1d`
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration `{}`, expected seconds or a number with s, m, h or d", value);
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (count, unit) = value.split_at(split);
+    let unit = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(invalid()),
+    };
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    Ok(Duration::from_secs(count.saturating_mul(unit)))
+}
+
+impl FromStr for AlertRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<AlertRule, String> {
+        // A name is a leading word followed by a colon, selectors such as `disk[C:]` being no names
+        let (name, expression) = match rule.split_once(':') {
+            Some((name, expression)) if !name.trim().is_empty()
+                && name.trim().chars().all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c)) => (Some(name.trim()), expression.trim()),
+            _ => (None, rule.trim()),
+        };
+        let (condition, duration) = match expression.rsplit_once(" for ") {
+            Some((condition, duration)) => (condition.trim(), parse_duration(duration.trim())?),
+            None => (expression, Duration::ZERO),
+        };
+
+        let at = condition.find(['<', '>']).ok_or_else(|| format!("Missing comparison in `{}`, expected <, <=, > or >=", condition))?;
+        let (metric, rest) = condition.split_at(at);
+        let (comparison, threshold) = match rest.split_at(1) {
+            ("<", rest) => match rest.strip_prefix('=') {
+                Some(threshold) => (Comparison::LessOrEqual, threshold),
+                None => (Comparison::Less, rest),
+            },
+            (_, rest) => match rest.strip_prefix('=') {
+                Some(threshold) => (Comparison::GreaterOrEqual, threshold),
+                None => (Comparison::Greater, rest),
+            },
+        };
+        let metric: Metric = metric.trim().parse()?;
+        let threshold = Threshold::parse(threshold.trim(), &metric)?;
+
+        Ok(AlertRule {
+            name: name.unwrap_or(expression).to_string(),
+            metric,
+            comparison,
+            threshold,
+            duration,
+            expression: expression.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.expression)
+    }
+}
+
+/// Alerts of every rule, kept up to date by the background sampler.
+pub(crate) struct Alerting {
+    rules: Vec<AlertRule>,
+    /// By index of the rule and instance
+    alerts: BTreeMap<(usize, Option<String>), Alert>,
+}
+
+impl Alerting {
+    pub(crate) fn new(rules: Vec<AlertRule>) -> Alerting {
+        Alerting { rules, alerts: BTreeMap::new() }
+    }
+
+    /// Evaluates every rule against `snapshot`. Conditions that start to hold are pending until
+    /// they have held for the rule's duration, then firing until they no longer hold.
+    pub(crate) fn evaluate(&mut self, snapshot: &Snapshot) {
+        let now = snapshot.timestamp;
+        let cpus = snapshot.cpus.as_ref().map_or(0, Vec::len);
+        let mut holding = BTreeSet::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            for sample in rule.metric.samples(snapshot) {
+                let threshold = rule.threshold.resolve(&sample, cpus);
+                if !rule.comparison.holds(sample.value, threshold) {
+                    continue;
+                }
+                let key = (i, sample.instance);
+                let alert = self.alerts.entry(key.clone()).or_insert_with(|| Alert {
+                    rule: rule.name.clone(),
+                    expression: rule.expression.clone(),
+                    instance: key.1.clone(),
+                    state: AlertState::Pending,
+                    value: sample.value,
+                    threshold,
+                    active_since: now,
+                    fired_at: None,
+                    resolved_at: None,
+                });
+                if alert.state == AlertState::Resolved {
+                    *alert = Alert { state: AlertState::Pending, active_since: now, fired_at: None, resolved_at: None, ..alert.clone() };
+                }
+                alert.value = sample.value;
+                alert.threshold = threshold;
+                if alert.state == AlertState::Pending && now.saturating_sub(alert.active_since) >= rule.duration.as_secs() {
+                    alert.state = AlertState::Firing;
+                    alert.fired_at = Some(now);
+                }
+                holding.insert(key);
+            }
+        }
+
+        self.alerts.retain(|key, alert| {
+            if holding.contains(key) {
+                return true;
+            }
+            match alert.state {
+                AlertState::Pending => false,
+                AlertState::Firing => {
+                    alert.state = AlertState::Resolved;
+                    alert.resolved_at = Some(now);
+                    true
+                }
+                AlertState::Resolved => alert.resolved_at.is_some_and(|resolved| now.saturating_sub(resolved) < RESOLVED_RETENTION),
+            }
+        });
+    }
+
+    /// Pending and firing alerts, and those resolved recently.
+    pub(crate) fn alerts(&self) -> Vec<Alert> {
+        self.alerts.values().cloned().collect()
+    }
+}
+
+pub(crate) async fn handle_alerts(alerting: Arc<Mutex<Alerting>>, format: Format) -> Result<Response<Body>, hyper::Error> {
+    let alerts = alerting.lock().unwrap().alerts();
+    Ok(respond(format, StatusCode::OK, &alerts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::collector::{fixture, FixtureCollector};
+    use crate::config::Config;
+    use crate::model::DiskInfo;
+    use crate::AppState;
+
+    fn snapshot(timestamp: u64, available_memory: u64) -> Snapshot {
+        let mut snapshot: Snapshot = serde_json::from_str(fixture::HOST).unwrap();
+        snapshot.timestamp = timestamp;
+        snapshot.memory.as_mut().unwrap().available_memory = available_memory;
+        snapshot
+    }
+
+    fn rule(rule: &str) -> AlertRule {
+        rule.parse().expect("Invalid rule")
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let low_memory = rule("low_memory: memory.available < 5% for 2m");
+        assert_eq!(low_memory.name, "low_memory");
+        assert_eq!(low_memory.expression, "memory.available < 5% for 2m");
+        assert_eq!(low_memory.metric, Metric::Memory("available"));
+        assert_eq!((low_memory.comparison, low_memory.threshold), (Comparison::Less, Threshold::Percent(5.0)));
+        assert_eq!(low_memory.duration, Duration::from_secs(120));
+
+        let disk = rule("disk[/dev/sda1].available <= 10GiB");
+        assert_eq!(disk.name, "disk[/dev/sda1].available <= 10GiB");
+        assert_eq!(disk.metric, Metric::Disk(Selector::Name("/dev/sda1".to_string()), "available"));
+        assert_eq!((disk.comparison, disk.threshold), (Comparison::LessOrEqual, Threshold::Value(10737418240.0)));
+        assert_eq!(rule("disk[C:\\].used > 500MB").metric, Metric::Disk(Selector::Name("C:\\".to_string()), "used"));
+        // Disks are selected by device name or mount point, and known by their mount point, as a
+        // device may be mounted at several
+        for selected in ["/dev/nvme0n1p1", "/boot/efi"] {
+            let samples = rule(&format!("disk[{}].available < 1GiB", selected)).metric.samples(&snapshot(1000, 0));
+            assert_eq!(samples.iter().map(|sample| sample.instance.as_deref()).collect::<Vec<_>>(), [Some("/boot/efi")]);
+        }
+        let mut bind_mounted = snapshot(1000, 0);
+        let disks = bind_mounted.disks.as_mut().unwrap();
+        disks.push(DiskInfo { mount_point: "/var/lib/containers".to_string(), ..disks[0].clone() });
+        let samples = rule("disk[/dev/nvme0n1p2].available < 1GiB").metric.samples(&bind_mounted);
+        assert_eq!(samples.iter().map(|sample| sample.instance.as_deref()).collect::<Vec<_>>(), [Some("/"), Some("/var/lib/containers")]);
+
+        assert_eq!(rule("hot: temperature[*] > 85").metric, Metric::Temperature(Selector::Any));
+        assert_eq!(rule("load.five > cpus*2").threshold, Threshold::Cpus(2.0));
+        assert_eq!(rule("load.one >= 1.5 * cpus for 90").duration, Duration::from_secs(90));
+        assert_eq!(rule("busy: cpu[cpu0].usage > 95 for 30s").metric, Metric::Cpu(Selector::Name("cpu0".to_string())));
+
+        for invalid in [
+            "memory.available",
+            "memory.cached < 5%",
+            "disk.available < 1GB",
+            "temperature[*] > 85%",
+            "load.five > 2GB",
+            "swap.used > lots",
+            "memory.used > 90% for ever",
+            "processes > 100",
+        ] {
+            assert!(invalid.parse::<AlertRule>().is_err(), "{} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn test_alert_states() {
+        let total = 17179869184;
+        let mut alerting = Alerting::new(vec![
+            rule("low_memory: memory.available < 5% for 2m"),
+            rule("hot: temperature[*] > 50"),
+            rule("load.five > cpus*2"),
+        ]);
+        let state = |alerting: &Alerting| -> Vec<(String, Option<String>, AlertState)> {
+            alerting.alerts().into_iter().map(|alert| (alert.rule, alert.instance, alert.state)).collect()
+        };
+        let hot = |state| ("hot".to_string(), Some("coretemp Package id 0".to_string()), state);
+        let low_memory = |state| ("low_memory".to_string(), None, state);
+
+        // Rules without a duration fire at once
+        alerting.evaluate(&snapshot(1000, total / 2));
+        assert_eq!(state(&alerting), [hot(AlertState::Firing)]);
+
+        alerting.evaluate(&snapshot(1060, total / 100));
+        assert_eq!(state(&alerting), [low_memory(AlertState::Pending), hot(AlertState::Firing)]);
+        alerting.evaluate(&snapshot(1120, total / 100));
+        assert_eq!(state(&alerting), [low_memory(AlertState::Pending), hot(AlertState::Firing)]);
+        alerting.evaluate(&snapshot(1180, total / 100));
+        assert_eq!(state(&alerting), [low_memory(AlertState::Firing), hot(AlertState::Firing)]);
+
+        let alert = &alerting.alerts()[0];
+        assert_eq!((alert.active_since, alert.fired_at, alert.value), (1060, Some(1180), (total / 100) as f64));
+        assert_eq!(alert.threshold, total as f64 * 0.05);
+
+        alerting.evaluate(&snapshot(1240, total / 2));
+        assert_eq!(state(&alerting), [low_memory(AlertState::Resolved), hot(AlertState::Firing)]);
+        assert_eq!(alerting.alerts()[0].resolved_at, Some(1240));
+
+        // A pending alert whose condition stops holding is dropped without being resolved
+        alerting.evaluate(&snapshot(1300, total / 100));
+        assert_eq!(state(&alerting), [low_memory(AlertState::Pending), hot(AlertState::Firing)]);
+        assert_eq!(alerting.alerts()[0].active_since, 1300);
+        alerting.evaluate(&snapshot(1360, total / 2));
+        assert_eq!(state(&alerting), [hot(AlertState::Firing)]);
+
+        let mut cool = snapshot(1420, total / 2);
+        cool.temperatures = Some(Vec::new());
+        alerting.evaluate(&cool);
+        assert_eq!(state(&alerting), [hot(AlertState::Resolved)]);
+        cool.timestamp += RESOLVED_RETENTION;
+        alerting.evaluate(&cool);
+        assert_eq!(state(&alerting), []);
+    }
+
+    #[tokio::test]
+    async fn test_alerts_endpoint() {
+        let config = Config { alert_rules: vec![rule("nvme: temperature[nvme Composite] >= 38.5")], ..Config::default() };
+        let state = AppState::new(Box::new(FixtureCollector::from_json(fixture::HOST).unwrap()), config);
+        let (status, alerts) = fixture::get(&state, "/alerts").await;
+        assert_eq!((status, alerts), (StatusCode::OK, json!([])));
+
+        state.alerts.lock().unwrap().evaluate(&snapshot(1000, 0));
+        let (_, alerts) = fixture::get(&state, "/v1/alerts").await;
+        assert_eq!(alerts, json!([{
+            "rule": "nvme",
+            "expression": "temperature[nvme Composite] >= 38.5",
+            "instance": "nvme Composite",
+            "state": "firing",
+            "value": 38.5,
+            "threshold": 38.5,
+            "active_since": 1000,
+            "fired_at": 1000,
+            "resolved_at": null,
+        }]));
+    }
+}
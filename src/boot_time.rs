@@ -3,51 +3,55 @@ use std::sync::{Arc, Mutex};
 use hyper::{Body, Response};
 use hyper::http::StatusCode;
 
-use serde_json::json;
 use sysinfo::{System, SystemExt};
 
-pub(crate) async fn handle_boot_time(system: Arc<Mutex<System>>) -> Result<Response<Body>, hyper::Error> {
+use crate::conditional::stamp;
+use crate::encoding::{respond, Format};
+use crate::model::BootTime;
+use crate::sampler::Sampled;
+
+/// Units of the `BootTime` fields, reported by the `/v2` API.
+pub(crate) const UNITS: &[(&str, &str)] = &[
+    ("boot_time", "unix_seconds"),
+];
+
+/// Reads the boot time from sysinfo, for its collector.
+pub(crate) fn read(system: &System) -> BootTime {
+    BootTime {
+        boot_time: system.boot_time(),
+    }
+}
+
+pub(crate) fn collect(system: &mut Sampled) -> BootTime {
+    system.refresh("system");
+    BootTime {
+        boot_time: system.boot_time(),
+    }
+}
+
+pub(crate) async fn handle_boot_time(system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
     let mut system = system.lock().unwrap();
-    system.refresh_system();
-    let boot_time = json!({
-        "boot_time": system.boot_time(),
-    });
-
-    let response = match Response::builder()
-        .header("Content-Type", "application/json")
-        .body(Body::from(json!(boot_time).to_string())) {
-        Ok(res) => res,
-        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap(),
-    };
-    Ok(response)
+    let boot_time = collect(&mut system);
+
+    Ok(stamp(respond(format, StatusCode::OK, &boot_time), system.refreshed("system")))
 }
 
 
 #[cfg(test)]
 mod tests {
-    use std::convert::Infallible;
     use super::*;
+    use sysinfo::System;
     use std::net::SocketAddr;
-    use hyper::Server;
-    use hyper::service::{make_service_fn, service_fn};
-    use reqwest;
-    use tokio;
-    use crate::handle_request;
-
-    const TEST_SERVER_ADDR: &str = "127.0.0.1:8081"; // Use a different port for testing
+    use crate::config::Config;
+    use crate::AppState;
+    use serde_json::json;
+    use crate::collector::fixture;
 
     #[tokio::test]
     async fn test_boot_time_endpoint() {
-        // Start the server in a background task
-        tokio::spawn(async {
-            let addr: SocketAddr = TEST_SERVER_ADDR.parse().expect("Invalid socket address");
-            boot_time_test_server(addr).await;
-        });
+        let addr = boot_time_test_server();
 
-        // Give the server a moment to start
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-        let response_json: serde_json::Value = reqwest::get(&format!("http://{}/boot_time", TEST_SERVER_ADDR))
+        let response_json: serde_json::Value = reqwest::get(&format!("http://{}/boot_time", addr))
             .await
             .expect("Failed to send request")
             .json()
@@ -69,20 +73,14 @@ mod tests {
 
     }
 
-    async fn boot_time_test_server(addr: SocketAddr) {
-        let system = Arc::new(Mutex::new(System::new_all()));
-
-        let test_service_load_avg = make_service_fn(move |_| {
-            let system = system.clone();
-            async {
-                Ok::<_, Infallible>(service_fn(move |req| handle_request(req, system.clone())))
-            }
-        });
-
-        let server = Server::bind(&addr).serve(test_service_load_avg);
+    #[tokio::test]
+    async fn test_boot_time_fixture() {
+        let (status, response) = fixture::get(&fixture::state(), "/boot_time").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, json!({"boot_time": 1699990000}));
+    }
 
-        if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
-        }
+    fn boot_time_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
     }
 }
@@ -0,0 +1,250 @@
+use sysinfo::{Pid, PidExt, ProcessExt, System, SystemExt};
+
+use crate::model::{CpuInfo, DiskInfo, LoadAverage, MemoryInfo, NetworkInfo, Snapshot, SystemInfo, Temperature, UserInfo};
+use crate::{boot_time, cpus, disks, hostinfo, load_avg, memory, networks, temperatures, users};
+
+/// Resource usage of the server's own process, reported by `/self`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessUsage {
+    /// Seconds since the process started
+    pub run_time: u64,
+    /// Resident set size in bytes
+    pub memory: u64,
+    pub cpu_usage: f64,
+}
+
+/// Source of the samples served by the handlers, [`System`] reading them from the host.
+///
+/// Handlers only read a subsystem after refreshing it, so implementations may take the
+/// values at refresh time or whenever they are read.
+pub trait Collector: Send {
+    /// Refreshes `subsystem`, one of the sampler's subsystems or `self`.
+    fn refresh(&mut self, subsystem: &str);
+
+    fn host_name(&self) -> Option<String>;
+    fn memory(&self) -> MemoryInfo;
+    fn cpus(&self) -> Vec<CpuInfo>;
+    fn temperatures(&self) -> Vec<Temperature>;
+    fn system_info(&self) -> SystemInfo;
+    fn disks(&self) -> Vec<DiskInfo>;
+    fn users(&self) -> Vec<UserInfo>;
+    fn networks(&self) -> Vec<NetworkInfo>;
+    fn load_average(&self) -> LoadAverage;
+    /// Seconds since the Unix epoch
+    fn boot_time(&self) -> u64;
+    /// Usage of the server's own process, refreshed as `self`
+    fn own_process(&self) -> Option<ProcessUsage>;
+}
+
+impl Collector for System {
+    fn refresh(&mut self, subsystem: &str) {
+        match subsystem {
+            "memory" => self.refresh_memory(),
+            "cpus" => self.refresh_cpu(),
+            "temperatures" => self.refresh_components(),
+            "disks" => self.refresh_disks(),
+            "networks" => self.refresh_networks_list(),
+            "users" => self.refresh_users_list(),
+            "system" => self.refresh_system(),
+            "self" => {
+                self.refresh_process(Pid::from_u32(std::process::id()));
+            }
+            _ => unreachable!("unknown subsystem {}", subsystem),
+        }
+    }
+
+    fn host_name(&self) -> Option<String> {
+        SystemExt::host_name(self)
+    }
+
+    fn memory(&self) -> MemoryInfo {
+        memory::read(self)
+    }
+
+    fn cpus(&self) -> Vec<CpuInfo> {
+        cpus::read(self)
+    }
+
+    fn temperatures(&self) -> Vec<Temperature> {
+        temperatures::read(self)
+    }
+
+    fn system_info(&self) -> SystemInfo {
+        hostinfo::read(self)
+    }
+
+    fn disks(&self) -> Vec<DiskInfo> {
+        disks::read(self)
+    }
+
+    fn users(&self) -> Vec<UserInfo> {
+        users::read(self)
+    }
+
+    fn networks(&self) -> Vec<NetworkInfo> {
+        networks::read(self)
+    }
+
+    fn load_average(&self) -> LoadAverage {
+        load_avg::read(self)
+    }
+
+    fn boot_time(&self) -> u64 {
+        boot_time::read(self).boot_time
+    }
+
+    fn own_process(&self) -> Option<ProcessUsage> {
+        self.process(Pid::from_u32(std::process::id())).map(|process| ProcessUsage {
+            run_time: process.run_time(),
+            memory: process.memory(),
+            cpu_usage: process.cpu_usage() as f64,
+        })
+    }
+}
+
+/// Collector serving fixed values, such as a snapshot captured from a real host. Parts
+/// missing from the snapshot are reported empty.
+pub struct FixtureCollector {
+    snapshot: Snapshot,
+}
+
+impl FixtureCollector {
+    pub fn new(snapshot: Snapshot) -> FixtureCollector {
+        FixtureCollector { snapshot }
+    }
+
+    /// Parses a snapshot in the JSON of `/snapshot`.
+    pub fn from_json(json: &str) -> serde_json::Result<FixtureCollector> {
+        serde_json::from_str(json).map(FixtureCollector::new)
+    }
+}
+
+impl Collector for FixtureCollector {
+    // The values never change
+    fn refresh(&mut self, _subsystem: &str) {}
+
+    fn host_name(&self) -> Option<String> {
+        Some(self.snapshot.hostname.clone())
+    }
+
+    fn memory(&self) -> MemoryInfo {
+        self.snapshot.memory.clone().unwrap_or_default()
+    }
+
+    fn cpus(&self) -> Vec<CpuInfo> {
+        self.snapshot.cpus.clone().unwrap_or_default()
+    }
+
+    fn temperatures(&self) -> Vec<Temperature> {
+        self.snapshot.temperatures.clone().unwrap_or_default()
+    }
+
+    fn system_info(&self) -> SystemInfo {
+        self.snapshot.sysinfo.clone().unwrap_or_default()
+    }
+
+    fn disks(&self) -> Vec<DiskInfo> {
+        self.snapshot.disks.clone().unwrap_or_default()
+    }
+
+    fn users(&self) -> Vec<UserInfo> {
+        self.snapshot.users.clone().unwrap_or_default()
+    }
+
+    fn networks(&self) -> Vec<NetworkInfo> {
+        self.snapshot.networks.clone().unwrap_or_default()
+    }
+
+    fn load_average(&self) -> LoadAverage {
+        self.snapshot.load_average.clone().unwrap_or_default()
+    }
+
+    fn boot_time(&self) -> u64 {
+        self.snapshot.boot_time.unwrap_or_default()
+    }
+
+    fn own_process(&self) -> Option<ProcessUsage> {
+        None
+    }
+}
+
+/// In-process requests against the fixture host, for tests that need exact values, and a server
+/// on a free port for tests that need a real connection.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+
+    use hyper::{Body, Request, Response, Server};
+    use hyper::http::StatusCode;
+    use hyper::server::conn::AddrStream;
+    use hyper::service::{make_service_fn, service_fn};
+    use serde_json::Value;
+
+    use super::FixtureCollector;
+    use crate::config::Config;
+    use crate::rate_limit::ClientAddr;
+    use crate::{handle_request, AppState};
+
+    pub(crate) const HOST: &str = include_str!("fixtures/host.json");
+
+    pub(crate) fn state() -> AppState {
+        AppState::new(Box::new(FixtureCollector::from_json(HOST).expect("Invalid fixture")), Config::default())
+    }
+
+    /// Response to `req`, as the server would send it.
+    pub(crate) async fn send(state: &AppState, req: Request<Body>) -> Response<Body> {
+        handle_request(req, state.clone()).await.expect("Failed to handle request")
+    }
+
+    /// Status and JSON body of a GET of `path`.
+    pub(crate) async fn get(state: &AppState, path: &str) -> (StatusCode, Value) {
+        let req = Request::get(path).header("accept", "application/json").body(Body::empty()).unwrap();
+        let response = send(state, req).await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.expect("Failed to read response");
+        (status, serde_json::from_slice(&body).expect("Failed to parse response as JSON"))
+    }
+
+    /// Serves `state` on a free port of the loopback interface, returning its address once it
+    /// accepts connections.
+    pub(crate) fn serve(state: AppState) -> SocketAddr {
+        let service = make_service_fn(move |conn: &AddrStream| {
+            let state = state.clone();
+            let client_addr = ClientAddr(conn.remote_addr());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |mut req| {
+                    req.extensions_mut().insert(client_addr);
+                    handle_request(req, state.clone())
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(service);
+        let addr = server.local_addr();
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                eprintln!("server error: {}", e);
+            }
+        });
+        addr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_collector() {
+        let collector = FixtureCollector::from_json(fixture::HOST).expect("Invalid fixture");
+        assert_eq!(collector.host_name().as_deref(), Some("fixture-host"));
+        assert_eq!(collector.cpus().len(), 4);
+        assert_eq!(collector.boot_time(), 1_699_990_000);
+
+        // Parts missing from the snapshot are empty
+        let collector = FixtureCollector::new(Snapshot { hostname: "bare".to_string(), ..Snapshot::default() });
+        assert!(collector.disks().is_empty());
+        assert_eq!(collector.memory(), MemoryInfo::default());
+        assert!(collector.own_process().is_none());
+    }
+}
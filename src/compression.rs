@@ -0,0 +1,182 @@
+use std::io::Write;
+
+use hyper::{Body, Request, Response};
+use hyper::header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, VARY};
+use hyper::http::StatusCode;
+
+// Bodies smaller than this are sent as is, compressing them saves next to nothing
+const MIN_SIZE: usize = 256;
+
+/// Content codings responses can be compressed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Coding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+// Supported codings by their `Accept-Encoding` name, in order of preference
+const CODINGS: [(&str, Coding); 3] = [("br", Coding::Brotli), ("zstd", Coding::Zstd), ("gzip", Coding::Gzip)];
+
+impl Coding {
+    fn name(self) -> &'static str {
+        CODINGS.iter().find(|(_, coding)| *coding == self).map(|(name, _)| *name).unwrap()
+    }
+
+    /// Picks the coding of the response to `req` from its `Accept-Encoding`, the one with
+    /// the highest quality and our preference among equals. `None` leaves the body as is.
+    pub(crate) fn negotiate(req: &Request<Body>) -> Option<Coding> {
+        let accept = req.headers().get(ACCEPT_ENCODING).and_then(|accept| accept.to_str().ok())?;
+
+        let qualities: Vec<(&str, f32)> = accept.split(',').filter_map(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next().filter(|name| !name.is_empty())?;
+            let quality = parts.filter_map(|param| param.strip_prefix("q=")).next()
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            Some((name, quality))
+        }).collect();
+        // Codings that are not listed get the quality of `*`, if any
+        let wildcard = qualities.iter().find(|(name, _)| *name == "*").map(|(_, quality)| *quality);
+
+        CODINGS.iter()
+            .filter_map(|(name, coding)| {
+                let quality = qualities.iter().find(|(candidate, _)| candidate.eq_ignore_ascii_case(name))
+                    .map(|(_, quality)| *quality)
+                    .or(wildcard)?;
+                Some((quality, *coding))
+            })
+            .filter(|(quality, _)| *quality > 0.0)
+            // `max_by` keeps the last of equal elements, so walk the preferences backwards
+            .rev()
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, coding)| coding)
+    }
+
+    fn encode(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Coding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                encoder.write_all(body)?;
+                Ok(encoder.into_inner())
+            }
+            Coding::Zstd => zstd::encode_all(body, 3),
+            Coding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Compresses the body of `response` with `coding`, leaving bodiless, small and
+/// already encoded responses alone.
+pub(crate) async fn compress(coding: Option<Coding>, response: Response<Body>) -> Result<Response<Body>, hyper::Error> {
+    if matches!(response.status(), StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED)
+        || response.headers().contains_key(CONTENT_ENCODING) {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    // The representation depends on Accept-Encoding whether or not it ends up compressed
+    parts.headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+    let coding = match coding {
+        Some(coding) => coding,
+        None => return Ok(Response::from_parts(parts, body)),
+    };
+
+    let body = hyper::body::to_bytes(body).await?;
+    if body.len() < MIN_SIZE {
+        return Ok(Response::from_parts(parts, Body::from(body)));
+    }
+    match coding.encode(&body) {
+        Ok(encoded) => {
+            parts.headers.insert(CONTENT_ENCODING, HeaderValue::from_static(coding.name()));
+            parts.headers.remove(CONTENT_LENGTH);
+            Ok(Response::from_parts(parts, Body::from(encoded)))
+        }
+        Err(e) => {
+            eprintln!("failed to compress response with {}: {}", coding.name(), e);
+            Ok(Response::from_parts(parts, Body::from(body)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use super::*;
+    use std::net::SocketAddr;
+    use sysinfo::{System, SystemExt};
+    use crate::collector::fixture;
+    use crate::config::Config;
+    use crate::AppState;
+
+    fn negotiate(accept_encoding: &str) -> Option<Coding> {
+        let req = Request::builder().header(ACCEPT_ENCODING, accept_encoding).body(Body::empty()).unwrap();
+        Coding::negotiate(&req)
+    }
+
+    fn decode(coding: &str, body: &[u8]) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        match coding {
+            "br" => { brotli::Decompressor::new(body, 4096).read_to_end(&mut decoded).expect("Invalid brotli body"); }
+            "zstd" => decoded = zstd::decode_all(body).expect("Invalid zstd body"),
+            "gzip" => { flate2::read::GzDecoder::new(body).read_to_end(&mut decoded).expect("Invalid gzip body"); }
+            _ => panic!("Unexpected coding {}", coding),
+        }
+        decoded
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip"), Some(Coding::Gzip));
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Coding::Brotli));
+        assert_eq!(negotiate("gzip;q=1.0, br;q=0.5, zstd;q=0.8"), Some(Coding::Gzip));
+        assert_eq!(negotiate("ZSTD, gzip"), Some(Coding::Zstd));
+        assert_eq!(negotiate("*"), Some(Coding::Brotli));
+        assert_eq!(negotiate("br;q=0, *;q=0.1"), Some(Coding::Zstd));
+        assert_eq!(negotiate("deflate, identity"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let addr = compression_test_server();
+
+        // The OpenAPI document does not change, so its encodings can be compared
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/openapi.json", addr);
+        let plain = client.get(&url).send().await.expect("Failed to send request");
+        assert!(plain.headers().get("content-encoding").is_none());
+        assert_eq!(plain.headers()["vary"], "accept-encoding");
+        let plain = plain.bytes().await.expect("Failed to read response");
+
+        for coding in ["br", "zstd", "gzip"] {
+            let response = client.get(&url)
+                .header("accept-encoding", coding)
+                .send()
+                .await
+                .expect("Failed to send request");
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            assert_eq!(response.headers()["content-encoding"], coding);
+            let body = response.bytes().await.expect("Failed to read response");
+            assert!(body.len() < plain.len());
+            assert_eq!(decode(coding, &body), plain);
+        }
+
+        // Small bodies are not worth compressing
+        let response = client.get(format!("http://{}/healthz", addr))
+            .header("accept-encoding", "gzip")
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert!(response.headers().get("content-encoding").is_none());
+    }
+
+    fn compression_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
+    }
+}
@@ -0,0 +1,178 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper::{Body, Request, Response};
+use hyper::header::{HeaderValue, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use hyper::http::StatusCode;
+
+/// When the data of a response was sampled, attached to the responses of sampled resources.
+#[derive(Clone, Copy)]
+pub(crate) struct SampledAt(pub(crate) SystemTime);
+
+/// Marks `response` as holding data sampled at `sampled`, making it conditional.
+pub(crate) fn stamp(mut response: Response<Body>, sampled: Option<SystemTime>) -> Response<Body> {
+    if let Some(sampled) = sampled {
+        response.extensions_mut().insert(SampledAt(sampled));
+    }
+    response
+}
+
+/// The validators a client sent, captured before the request is routed.
+pub(crate) struct Preconditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<SystemTime>,
+}
+
+impl Preconditions {
+    pub(crate) fn from_request(req: &Request<Body>) -> Preconditions {
+        let header = |name| req.headers().get(name).and_then(|value: &HeaderValue| value.to_str().ok());
+        Preconditions {
+            if_none_match: header(IF_NONE_MATCH).map(str::to_string),
+            if_modified_since: header(IF_MODIFIED_SINCE).and_then(|date| httpdate::parse_http_date(date).ok()),
+        }
+    }
+
+    // Whether the client's copy of a representation with these validators is current
+    fn is_fresh(&self, etag: &str, last_modified: SystemTime) -> bool {
+        // If-None-Match takes precedence over If-Modified-Since
+        match &self.if_none_match {
+            Some(tags) => tags.split(',').map(str::trim).any(|tag| tag == "*" || opaque(tag) == opaque(etag)),
+            None => self.if_modified_since.is_some_and(|since| last_modified <= since),
+        }
+    }
+}
+
+// The tag without its weakness indicator, as compared by If-None-Match
+fn opaque(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+// Changes with every resample, and between the formats a sample is served in
+fn etag(sampled: Duration, content_type: &[u8]) -> String {
+    // FNV-1a
+    let format = content_type.iter().fold(0xcbf29ce484222325_u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3));
+    format!("W/\"{:x}-{:x}\"", sampled.as_nanos(), format as u32)
+}
+
+/// Adds `ETag` and `Last-Modified` to a successful response of a sampled resource,
+/// and turns it into `304 Not Modified` when the client already has that sample.
+pub(crate) fn apply(preconditions: &Preconditions, mut response: Response<Body>) -> Response<Body> {
+    let sampled = match response.extensions().get::<SampledAt>() {
+        Some(SampledAt(sampled)) if response.status() == StatusCode::OK => sampled.duration_since(UNIX_EPOCH).unwrap_or_default(),
+        _ => return response,
+    };
+    let content_type = response.headers().get(CONTENT_TYPE).map(HeaderValue::as_bytes).unwrap_or_default();
+    let etag = etag(sampled, content_type);
+    // HTTP dates have a resolution of one second
+    let last_modified = UNIX_EPOCH + Duration::from_secs(sampled.as_secs());
+
+    let headers = response.headers_mut();
+    headers.insert(ETAG, HeaderValue::from_str(&etag).unwrap());
+    headers.insert(LAST_MODIFIED, HeaderValue::from_str(&httpdate::fmt_http_date(last_modified)).unwrap());
+
+    if preconditions.is_fresh(&etag, last_modified) {
+        let mut not_modified = Response::new(Body::empty());
+        *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+        for name in [ETAG, LAST_MODIFIED] {
+            if let Some(value) = response.headers_mut().remove(&name) {
+                not_modified.headers_mut().insert(name, value);
+            }
+        }
+        return not_modified;
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use sysinfo::{System, SystemExt};
+    use crate::collector::fixture;
+    use crate::config::Config;
+    use crate::AppState;
+
+    fn sampled_response(sampled: SystemTime) -> Response<Body> {
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        stamp(response, Some(sampled))
+    }
+
+    fn preconditions(name: &str, value: &str) -> Preconditions {
+        let req = Request::builder().header(name, value).body(Body::empty()).unwrap();
+        Preconditions::from_request(&req)
+    }
+
+    #[test]
+    fn test_apply() {
+        let sampled = UNIX_EPOCH + Duration::from_millis(1_700_000_000_250);
+        let none = preconditions("x-none", "");
+
+        let response = apply(&none, sampled_response(sampled));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[LAST_MODIFIED], "Tue, 14 Nov 2023 22:13:20 GMT");
+        let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/\""));
+
+        // The same sample is not modified, by tag or by date
+        let response = apply(&preconditions("if-none-match", &etag), sampled_response(sampled));
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag.as_str());
+        let response = apply(&preconditions("if-none-match", &format!("\"other\", {}", opaque(&etag))), sampled_response(sampled));
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        let response = apply(&preconditions("if-modified-since", "Tue, 14 Nov 2023 22:13:20 GMT"), sampled_response(sampled));
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // A resample is modified, and so is another format of the same sample
+        let resampled = sampled + Duration::from_secs(5);
+        let response = apply(&preconditions("if-none-match", &etag), sampled_response(resampled));
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = apply(&preconditions("if-modified-since", "Tue, 14 Nov 2023 22:13:20 GMT"), sampled_response(resampled));
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut csv = sampled_response(sampled);
+        csv.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("text/csv"));
+        assert_eq!(apply(&preconditions("if-none-match", &etag), csv).status(), StatusCode::OK);
+
+        // Responses that are not samples carry no validators
+        let response = apply(&none, Response::new(Body::empty()));
+        assert!(response.headers().get(ETAG).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests() {
+        let addr = conditional_test_server();
+
+        let client = reqwest::Client::new();
+        for path in ["/memory", "/v2/disks", "/snapshot?include=memory"] {
+            let response = client.get(format!("http://{}{}", addr, path))
+                .send()
+                .await
+                .expect("Failed to send request");
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let etag = response.headers().get("etag").expect("No ETag").clone();
+            let last_modified = response.headers().get("last-modified").expect("No Last-Modified").to_str().unwrap();
+            assert!(httpdate::parse_http_date(last_modified).is_ok());
+
+            // Within the minimum refresh interval the same sample is served again
+            let response = client.get(format!("http://{}{}", addr, path))
+                .header("if-none-match", etag.clone())
+                .send()
+                .await
+                .expect("Failed to send request");
+            assert_eq!(response.status(), reqwest::StatusCode::NOT_MODIFIED);
+            assert_eq!(response.headers()["etag"], etag);
+            assert!(response.bytes().await.expect("Failed to read response").is_empty());
+        }
+
+        let response = client.get(format!("http://{}/healthz", addr))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert!(response.headers().get("etag").is_none());
+    }
+
+    fn conditional_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
+    }
+}
@@ -0,0 +1,694 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::{App, Arg, ArgMatches, Error, ErrorKind};
+
+use crate::alerts::AlertRule;
+use crate::recording::Replay;
+use crate::sampler::SUBSYSTEMS;
+use crate::webhooks::Webhook;
+
+// Default address
+const DEFAULT_ADDR: &str = "127.0.0.1:5000";
+
+/// Server settings, taken from the command line by the binary.
+#[derive(Clone)]
+pub struct Config {
+    pub addr: SocketAddr,
+    /// How often the background sampler refreshes every subsystem
+    pub sample_interval: Duration,
+    /// Maximum age of a subsystem sample before `/readyz` reports it as stale
+    pub ready_max_age: Duration,
+    /// How long requests are served a subsystem's cached sample before it is refreshed again
+    pub min_refresh_interval: MinRefreshInterval,
+    /// Requests allowed per client, unlimited when `None`
+    pub rate_limit: Option<RateLimit>,
+    /// Cross-origin requests allowed from browsers, none when `None`
+    pub cors: Option<Cors>,
+    /// Directory the host's `/proc`, `/sys` and `/etc` are read below instead of through
+    /// sysinfo, e.g. `/host` in a container. Linux only
+    pub host_root: Option<PathBuf>,
+    /// File the background sampler's snapshots are recorded to
+    pub record: Option<PathBuf>,
+    /// Recording served instead of the host's samples
+    pub replay: Option<Replay>,
+    /// How long the background sampler's snapshots are kept for `/diff`
+    pub history: Duration,
+    /// Rules evaluated against every snapshot of the background sampler, for `/alerts`
+    pub alert_rules: Vec<AlertRule>,
+    /// URLs notified when alerts fire or are resolved
+    pub webhooks: Vec<Webhook>,
+    /// File undelivered webhook notifications are kept in across restarts, in memory when `None`
+    pub webhook_outbox: Option<PathBuf>,
+    /// Where to push every sample as InfluxDB line protocol, if anywhere
+    pub influx: Option<Influx>,
+    /// Where to send every sample over Graphite's plaintext protocol, if anywhere
+    pub graphite: Option<Graphite>,
+    /// Where to send every sample as StatsD gauges, if anywhere
+    pub statsd: Option<Statsd>,
+    /// Where to export every sample over OTLP, if anywhere
+    pub otlp: Option<Otlp>,
+    /// Where to publish every sample over MQTT, if anywhere
+    pub mqtt: Option<Mqtt>,
+}
+
+/// Minimum intervals between on-demand refreshes, overridable per subsystem.
+#[derive(Clone)]
+pub struct MinRefreshInterval {
+    pub default: Duration,
+    pub subsystems: BTreeMap<String, Duration>,
+}
+
+impl MinRefreshInterval {
+    pub fn of(&self, subsystem: &str) -> Duration {
+        self.subsystems.get(subsystem).copied().unwrap_or(self.default)
+    }
+}
+
+/// Token bucket settings of the per-client rate limiter.
+#[derive(Clone, Copy)]
+pub struct RateLimit {
+    /// Tokens added to a client's bucket every second
+    pub per_second: u32,
+    /// Size of the bucket, the number of requests a client can make in a burst
+    pub burst: u32,
+}
+
+/// Export of every sample to InfluxDB or Telegraf as line protocol.
+#[derive(Clone)]
+pub struct Influx {
+    /// Write URL such as `http://influxdb:8086/api/v2/write?org=o&bucket=b`, or `udp://host:port`
+    pub url: String,
+    /// Sent as `Authorization: Token ...` over HTTP
+    pub token: Option<String>,
+    /// Whether to gzip HTTP requests
+    pub gzip: bool,
+    /// Lines per HTTP request
+    pub batch_size: usize,
+    /// Requests kept for retrying while the endpoint fails, the oldest dropped first
+    pub queue: usize,
+}
+
+impl Influx {
+    pub fn new(url: &str) -> Influx {
+        Influx { url: url.to_string(), token: None, gzip: false, batch_size: 5000, queue: 100 }
+    }
+}
+
+/// Export of every sample to Graphite over its plaintext protocol.
+#[derive(Clone)]
+pub struct Graphite {
+    /// `host:port` of Graphite's plaintext TCP listener
+    pub addr: String,
+    /// Prepended to every metric path, before the host name
+    pub prefix: String,
+}
+
+/// Export of every sample to StatsD as gauges.
+#[derive(Clone)]
+pub struct Statsd {
+    /// `host:port` of StatsD's UDP listener
+    pub addr: String,
+    /// Prepended to every gauge name, before the host name
+    pub prefix: String,
+}
+
+/// Export of every sample to an OpenTelemetry collector over OTLP.
+#[derive(Clone)]
+pub struct Otlp {
+    /// Collector endpoint, such as `http://collector:4317` over gRPC or `http://collector:4318` over
+    /// HTTP, where `/v1/metrics` is appended unless the endpoint ends with it
+    pub endpoint: String,
+    pub protocol: OtlpProtocol,
+    /// Sent with every export, such as an API key
+    pub headers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    Grpc,
+    HttpProtobuf,
+}
+
+/// Publishing of every sample to an MQTT broker.
+#[derive(Clone)]
+pub struct Mqtt {
+    pub host: String,
+    pub port: u16,
+    /// Whether to connect over TLS, for `mqtts://` URLs
+    pub tls: bool,
+    /// PEM certificates of the CAs trusted over TLS instead of the system's
+    pub ca: Option<Vec<u8>>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// First level of every topic
+    pub topic_prefix: String,
+    /// Quality of service, 0, 1 or 2
+    pub qos: u8,
+}
+
+impl Mqtt {
+    /// Publishing to the broker at `url`, `mqtt://HOST[:PORT]` or `mqtts://HOST[:PORT]` over TLS,
+    /// with QoS 1 on topics below `sysinfo`.
+    pub fn new(url: &str) -> Result<Mqtt, String> {
+        let invalid = || format!("'{}' is not an mqtt:// or mqtts:// URL", url);
+        let url = url::Url::parse(url).map_err(|_| invalid())?;
+        let tls = match url.scheme() {
+            "mqtt" => false,
+            "mqtts" => true,
+            _ => return Err(invalid()),
+        };
+        let host = url.host_str().filter(|host| !host.is_empty()).ok_or_else(invalid)?;
+        Ok(Mqtt {
+            host: host.trim_start_matches('[').trim_end_matches(']').to_string(),
+            port: url.port().unwrap_or(if tls { 8883 } else { 1883 }),
+            tls,
+            ca: None,
+            username: None,
+            password: None,
+            topic_prefix: "sysinfo".to_string(),
+            qos: 1,
+        })
+    }
+}
+
+/// CORS policy for browser clients on other origins.
+#[derive(Clone)]
+pub struct Cors {
+    /// Allowed origins, `*` allowing any
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    /// Request headers allowed in addition to the CORS-safelisted ones, lowercase
+    pub headers: Vec<String>,
+    /// How long browsers may cache a preflight response
+    pub max_age: Duration,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors {
+            origins: Vec::new(),
+            methods: vec!["GET".to_string()],
+            headers: ["authorization", "if-none-match", "if-modified-since"].map(str::to_string).to_vec(),
+            max_age: Duration::from_secs(600),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            addr: SocketAddr::new(IpAddr::V4([127, 0, 0, 1].into()), 5000),
+            sample_interval: Duration::from_secs(5),
+            ready_max_age: Duration::from_secs(30),
+            min_refresh_interval: MinRefreshInterval {
+                default: Duration::from_secs(1),
+                subsystems: BTreeMap::new(),
+            },
+            rate_limit: None,
+            cors: None,
+            host_root: None,
+            record: None,
+            replay: None,
+            history: Duration::from_secs(3600),
+            alert_rules: Vec::new(),
+            webhooks: Vec::new(),
+            webhook_outbox: None,
+            influx: None,
+            graphite: None,
+            statsd: None,
+            otlp: None,
+            mqtt: None,
+        }
+    }
+}
+
+fn is_seconds(value: String) -> Result<(), String> {
+    match value.parse::<u64>() {
+        Ok(secs) if secs > 0 => Ok(()),
+        _ => Err(format!("'{}' is not a positive number of seconds", value)),
+    }
+}
+
+fn is_count(value: String) -> Result<(), String> {
+    match value.parse::<u32>() {
+        Ok(count) if count > 0 => Ok(()),
+        _ => Err(format!("'{}' is not a positive number", value)),
+    }
+}
+
+fn is_host_root(value: String) -> Result<(), String> {
+    if !cfg!(target_os = "linux") {
+        return Err("only supported on Linux".to_string());
+    }
+    if !Path::new(&value).join("proc").is_dir() {
+        return Err(format!("'{}' has no proc directory", value));
+    }
+    Ok(())
+}
+
+fn is_speed(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(()),
+        _ => Err(format!("'{}' is not a positive factor", value)),
+    }
+}
+
+// `[SUBSYSTEM=]SECONDS`, the subsystems being those refreshed by the sampler and `self`
+fn parse_refresh_interval(value: &str) -> Result<(Option<&str>, Duration), String> {
+    let (subsystem, secs) = match value.split_once('=') {
+        Some((subsystem, secs)) if subsystem == "self" || SUBSYSTEMS.contains(&subsystem) => (Some(subsystem), secs),
+        Some((subsystem, _)) => return Err(format!("Unknown subsystem `{}`, expected self or one of {}", subsystem, SUBSYSTEMS.join(", "))),
+        None => (None, value),
+    };
+    match secs.parse::<u64>() {
+        Ok(secs) => Ok((subsystem, Duration::from_secs(secs))),
+        Err(_) => Err(format!("'{}' is not a number of seconds", secs)),
+    }
+}
+
+fn is_refresh_interval(value: String) -> Result<(), String> {
+    parse_refresh_interval(&value).map(|_| ())
+}
+
+fn is_influx_url(value: String) -> Result<(), String> {
+    crate::influx::Target::parse(&value).map(|_| ())
+}
+
+fn is_host_port(value: String) -> Result<(), String> {
+    match value.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+        _ => Err(format!("'{}' is not a HOST:PORT address", value)),
+    }
+}
+
+fn is_http_url(value: String) -> Result<(), String> {
+    match value.parse::<hyper::Uri>() {
+        Ok(uri) if matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some() => Ok(()),
+        _ => Err(format!("'{}' is not an http or https URL", value)),
+    }
+}
+
+// `NAME=VALUE`, NAME being a valid header name
+fn parse_header(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if hyper::header::HeaderName::from_bytes(name.trim().as_bytes()).is_ok() => {
+            Ok((name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        }
+        _ => Err(format!("'{}' is not a NAME=VALUE header", value)),
+    }
+}
+
+fn is_header(value: String) -> Result<(), String> {
+    parse_header(&value).map(|_| ())
+}
+
+fn is_webhook(value: String) -> Result<(), String> {
+    value.parse::<Webhook>().map(|_| ())
+}
+
+fn is_alert_rule(value: String) -> Result<(), String> {
+    value.parse::<AlertRule>().map(|_| ())
+}
+
+// One rule per line, skipping blank lines and `#` comments
+fn read_alert_rules(path: &Path) -> Result<Vec<AlertRule>, String> {
+    let rules = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    rules.lines().enumerate()
+        .map(|(i, line)| (i, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| line.parse().map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+// Comma separated values of the repeatable option `name`
+fn list(matches: &ArgMatches, name: &str) -> Option<Vec<String>> {
+    let values = matches.values_of(name)?;
+    Some(values.flat_map(|value| value.split(',')).map(str::trim).filter(|value| !value.is_empty()).map(str::to_string).collect())
+}
+
+fn seconds(matches: &ArgMatches, name: &str) -> Duration {
+    // Presence and format are guaranteed by the default value and validator
+    Duration::from_secs(matches.value_of(name).unwrap().parse().unwrap())
+}
+
+impl Config {
+    fn app() -> App<'static, 'static> {
+        App::new(env!("CARGO_PKG_NAME"))
+            .version(env!("CARGO_PKG_VERSION"))
+            .arg(Arg::with_name("address")
+                .help("Address to listen on")
+                .default_value(DEFAULT_ADDR)
+                .index(1))
+            .arg(Arg::with_name("sample-interval")
+                .long("sample-interval")
+                .value_name("SECONDS")
+                .help("Interval of the background sampler")
+                .default_value("5")
+                .validator(is_seconds))
+            .arg(Arg::with_name("ready-max-age")
+                .long("ready-max-age")
+                .value_name("SECONDS")
+                .help("Maximum sample age before /readyz reports not ready")
+                .default_value("30")
+                .validator(is_seconds))
+            .arg(Arg::with_name("min-refresh-interval")
+                .long("min-refresh-interval")
+                .value_name("[SUBSYSTEM=]SECONDS")
+                .help("Minimum interval between refreshes of a subsystem for requests, which are served the cached sample \
+                       in between (default 1, 0 refreshes on every request). Repeat to set it per subsystem")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(is_refresh_interval))
+            .arg(Arg::with_name("rate-limit")
+                .long("rate-limit")
+                .value_name("REQUESTS")
+                .help("Requests per second allowed per client IP, unlimited by default")
+                .validator(is_count))
+            .arg(Arg::with_name("rate-limit-burst")
+                .long("rate-limit-burst")
+                .value_name("REQUESTS")
+                .help("Requests a client can make in a burst (default: the rate limit)")
+                .requires("rate-limit")
+                .validator(is_count))
+            .arg(Arg::with_name("cors-origin")
+                .long("cors-origin")
+                .value_name("ORIGIN")
+                .help("Origin allowed to make cross-origin requests, `*` for any. Repeat or separate with commas")
+                .multiple(true)
+                .number_of_values(1))
+            .arg(Arg::with_name("cors-methods")
+                .long("cors-methods")
+                .value_name("METHODS")
+                .help("Methods allowed in cross-origin requests (default: GET)")
+                .requires("cors-origin"))
+            .arg(Arg::with_name("cors-headers")
+                .long("cors-headers")
+                .value_name("HEADERS")
+                .help("Request headers allowed in cross-origin requests (default: authorization, if-none-match, if-modified-since)")
+                .requires("cors-origin"))
+            .arg(Arg::with_name("cors-max-age")
+                .long("cors-max-age")
+                .value_name("SECONDS")
+                .help("How long browsers may cache preflight responses (default: 600)")
+                .requires("cors-origin")
+                .validator(is_seconds))
+            .arg(Arg::with_name("host-root")
+                .long("host-root")
+                .value_name("DIR")
+                .help("Directory the host's /proc, /sys and /etc are mounted below, e.g. /host in a container. \
+                       Every subsystem is then read from those files rather than through sysinfo (Linux only)")
+                .validator(is_host_root))
+            .arg(Arg::with_name("record")
+                .long("record")
+                .value_name("FILE")
+                .help("Records every snapshot of the background sampler to FILE, one JSON document per line"))
+            .arg(Arg::with_name("replay")
+                .long("replay")
+                .value_name("FILE")
+                .help("Serves the snapshots recorded in FILE instead of the host's, starting over at the end")
+                .conflicts_with_all(&["record", "host-root"]))
+            .arg(Arg::with_name("replay-speed")
+                .long("replay-speed")
+                .value_name("FACTOR")
+                .help("How much faster than recorded to replay (default: 1)")
+                .requires("replay")
+                .validator(is_speed))
+            .arg(Arg::with_name("history")
+                .long("history")
+                .value_name("SECONDS")
+                .help("How long the background sampler's snapshots are kept for /diff")
+                .default_value("3600")
+                .validator(is_seconds))
+            .arg(Arg::with_name("alert")
+                .long("alert")
+                .value_name("RULE")
+                .help("Alert rule such as 'low_memory: memory.available < 5% for 2m', evaluated against every sample \
+                       of the background sampler. Repeat for more rules")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(is_alert_rule))
+            .arg(Arg::with_name("alert-rules")
+                .long("alert-rules")
+                .value_name("FILE")
+                .help("File of alert rules, one per line, `#` starting a comment"))
+            .arg(Arg::with_name("webhook")
+                .long("webhook")
+                .value_name("[FORMAT=]URL")
+                .help("URL notified when alerts fire or are resolved, FORMAT being json (the default), slack or \
+                       alertmanager. Repeat for more webhooks")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(is_webhook))
+            .arg(Arg::with_name("webhook-outbox")
+                .long("webhook-outbox")
+                .value_name("FILE")
+                .help("File undelivered notifications are kept in, to be retried after a restart")
+                .requires("webhook"))
+            .arg(Arg::with_name("influx")
+                .long("influx")
+                .value_name("URL")
+                .help("Pushes every sample as InfluxDB line protocol to URL, an InfluxDB or Telegraf write URL such as \
+                       http://influxdb:8086/api/v2/write?org=ORG&bucket=BUCKET, or udp://HOST:PORT")
+                .validator(is_influx_url))
+            .arg(Arg::with_name("influx-token")
+                .long("influx-token")
+                .value_name("TOKEN")
+                .help("Token sent with every InfluxDB request")
+                .requires("influx"))
+            .arg(Arg::with_name("influx-gzip")
+                .long("influx-gzip")
+                .help("Compresses InfluxDB requests with gzip")
+                .requires("influx"))
+            .arg(Arg::with_name("influx-batch-size")
+                .long("influx-batch-size")
+                .value_name("LINES")
+                .help("Lines per InfluxDB request (default: 5000)")
+                .requires("influx")
+                .validator(is_count))
+            .arg(Arg::with_name("influx-queue")
+                .long("influx-queue")
+                .value_name("REQUESTS")
+                .help("Requests kept for retrying while InfluxDB fails, dropping the oldest (default: 100)")
+                .requires("influx")
+                .validator(is_count))
+            .arg(Arg::with_name("graphite")
+                .long("graphite")
+                .value_name("HOST:PORT")
+                .help("Sends every sample to Graphite's plaintext TCP listener, as PREFIX.HOSTNAME.memory.used_memory and so on")
+                .validator(is_host_port))
+            .arg(Arg::with_name("graphite-prefix")
+                .long("graphite-prefix")
+                .value_name("PREFIX")
+                .help("Prefix of the Graphite metric paths, none by default")
+                .requires("graphite"))
+            .arg(Arg::with_name("statsd")
+                .long("statsd")
+                .value_name("HOST:PORT")
+                .help("Sends every sample to StatsD over UDP as gauges, named as in Graphite")
+                .validator(is_host_port))
+            .arg(Arg::with_name("statsd-prefix")
+                .long("statsd-prefix")
+                .value_name("PREFIX")
+                .help("Prefix of the StatsD gauge names, none by default")
+                .requires("statsd"))
+            .arg(Arg::with_name("otlp")
+                .long("otlp")
+                .value_name("URL")
+                .help("Exports every sample over OTLP to the OpenTelemetry collector at URL, such as http://collector:4318")
+                .validator(is_http_url))
+            .arg(Arg::with_name("otlp-protocol")
+                .long("otlp-protocol")
+                .value_name("PROTOCOL")
+                .help("OTLP transport (default: http/protobuf)")
+                .possible_values(&["grpc", "http/protobuf"])
+                .requires("otlp"))
+            .arg(Arg::with_name("otlp-header")
+                .long("otlp-header")
+                .value_name("NAME=VALUE")
+                .help("Header sent with every OTLP export, such as an API key. Repeat for more headers")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("otlp")
+                .validator(is_header))
+            .arg(Arg::with_name("mqtt")
+                .long("mqtt")
+                .value_name("URL")
+                .help("Publishes every sample to the MQTT broker at URL, mqtt://HOST[:PORT] or mqtts://HOST[:PORT] over TLS")
+                .validator(|url| Mqtt::new(&url).map(|_| ())))
+            .arg(Arg::with_name("mqtt-topic-prefix")
+                .long("mqtt-topic-prefix")
+                .value_name("PREFIX")
+                .help("First level of the MQTT topics, followed by the host name and the resource (default: sysinfo)")
+                .requires("mqtt"))
+            .arg(Arg::with_name("mqtt-qos")
+                .long("mqtt-qos")
+                .value_name("QOS")
+                .help("Quality of service of the MQTT messages (default: 1)")
+                .possible_values(&["0", "1", "2"])
+                .requires("mqtt"))
+            .arg(Arg::with_name("mqtt-username")
+                .long("mqtt-username")
+                .value_name("USERNAME")
+                .help("User name to connect to the MQTT broker with")
+                .requires("mqtt"))
+            .arg(Arg::with_name("mqtt-password")
+                .long("mqtt-password")
+                .value_name("PASSWORD")
+                .help("Password to connect to the MQTT broker with")
+                .requires("mqtt-username"))
+            .arg(Arg::with_name("mqtt-ca")
+                .long("mqtt-ca")
+                .value_name("FILE")
+                .help("PEM certificates of the CAs trusted for mqtts:// instead of the system's")
+                .requires("mqtt"))
+    }
+
+    pub fn from_args() -> Config {
+        Config::from_matches(Config::app().get_matches())
+    }
+
+    fn from_matches(matches: ArgMatches) -> Config {
+        let addr = match SocketAddr::from_str(matches.value_of("address").unwrap()) {
+            Ok(a) => a,
+            Err(_) => {
+                eprintln!("Invalid address format. Falling back to default: {}", DEFAULT_ADDR);
+                Config::default().addr
+            }
+        };
+
+        let mut min_refresh_interval = Config::default().min_refresh_interval;
+        for value in matches.values_of("min-refresh-interval").into_iter().flatten() {
+            match parse_refresh_interval(value).unwrap() {
+                (Some(subsystem), interval) => { min_refresh_interval.subsystems.insert(subsystem.to_string(), interval); }
+                (None, interval) => min_refresh_interval.default = interval,
+            }
+        }
+
+        // Validated as positive numbers
+        let count = |name| matches.value_of(name).map(|count: &str| count.parse::<u32>().unwrap());
+        let rate_limit = count("rate-limit").map(|per_second| RateLimit {
+            per_second,
+            burst: count("rate-limit-burst").unwrap_or(per_second),
+        });
+
+        let cors = list(&matches, "cors-origin").map(|origins| {
+            let default = Cors::default();
+            Cors {
+                origins,
+                methods: list(&matches, "cors-methods")
+                    .map(|methods| methods.iter().map(|method| method.to_ascii_uppercase()).collect())
+                    .unwrap_or(default.methods),
+                headers: list(&matches, "cors-headers")
+                    .map(|headers| headers.iter().map(|header| header.to_ascii_lowercase()).collect())
+                    .unwrap_or(default.headers),
+                max_age: matches.value_of("cors-max-age").map_or(default.max_age, |_| seconds(&matches, "cors-max-age")),
+            }
+        });
+
+        // Validated when given on the command line
+        let mut alert_rules: Vec<AlertRule> = matches.values_of("alert").into_iter().flatten()
+            .map(|rule| rule.parse().unwrap())
+            .collect();
+        if let Some(path) = matches.value_of("alert-rules") {
+            alert_rules.extend(read_alert_rules(Path::new(path)).unwrap_or_else(|e| {
+                Error::with_description(&format!("Cannot read alert rules from '{}': {}", path, e), ErrorKind::InvalidValue).exit()
+            }));
+        }
+
+        Config {
+            addr,
+            sample_interval: seconds(&matches, "sample-interval"),
+            ready_max_age: seconds(&matches, "ready-max-age"),
+            min_refresh_interval,
+            rate_limit,
+            cors,
+            host_root: matches.value_of("host-root").map(PathBuf::from),
+            record: matches.value_of("record").map(PathBuf::from),
+            replay: matches.value_of("replay").map(|path| {
+                let speed = matches.value_of("replay-speed").map_or(1.0, |speed| speed.parse().unwrap());
+                Replay::open(Path::new(path), speed).unwrap_or_else(|e| {
+                    Error::with_description(&format!("Cannot replay '{}': {}", path, e), ErrorKind::InvalidValue).exit()
+                })
+            }),
+            history: seconds(&matches, "history"),
+            alert_rules,
+            // Validated
+            webhooks: matches.values_of("webhook").into_iter().flatten().map(|webhook| webhook.parse().unwrap()).collect(),
+            webhook_outbox: matches.value_of("webhook-outbox").map(PathBuf::from),
+            influx: matches.value_of("influx").map(|url| {
+                let default = Influx::new(url);
+                Influx {
+                    token: matches.value_of("influx-token").map(str::to_string),
+                    gzip: matches.is_present("influx-gzip"),
+                    batch_size: count("influx-batch-size").map_or(default.batch_size, |lines| lines as usize),
+                    queue: count("influx-queue").map_or(default.queue, |requests| requests as usize),
+                    ..default
+                }
+            }),
+            graphite: matches.value_of("graphite").map(|addr| Graphite {
+                addr: addr.to_string(),
+                prefix: matches.value_of("graphite-prefix").unwrap_or_default().to_string(),
+            }),
+            statsd: matches.value_of("statsd").map(|addr| Statsd {
+                addr: addr.to_string(),
+                prefix: matches.value_of("statsd-prefix").unwrap_or_default().to_string(),
+            }),
+            otlp: matches.value_of("otlp").map(|endpoint| Otlp {
+                endpoint: endpoint.to_string(),
+                protocol: match matches.value_of("otlp-protocol") {
+                    Some("grpc") => OtlpProtocol::Grpc,
+                    _ => OtlpProtocol::HttpProtobuf,
+                },
+                // Validated
+                headers: matches.values_of("otlp-header").into_iter().flatten().map(|header| parse_header(header).unwrap()).collect(),
+            }),
+            mqtt: matches.value_of("mqtt").map(|url| {
+                // Validated
+                let default = Mqtt::new(url).unwrap();
+                Mqtt {
+                    ca: matches.value_of("mqtt-ca").map(|path| std::fs::read(path).unwrap_or_else(|e| {
+                        Error::with_description(&format!("Cannot read MQTT CA certificates from '{}': {}", path, e), ErrorKind::InvalidValue).exit()
+                    })),
+                    username: matches.value_of("mqtt-username").map(str::to_string),
+                    password: matches.value_of("mqtt-password").map(str::to_string),
+                    topic_prefix: matches.value_of("mqtt-topic-prefix").map_or(default.topic_prefix.clone(), |prefix| prefix.trim_matches('/').to_string()),
+                    qos: matches.value_of("mqtt-qos").map_or(default.qos, |qos| qos.parse().unwrap()),
+                    ..default
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, Error> {
+        let args = std::iter::once(env!("CARGO_PKG_NAME")).chain(args.iter().copied());
+        Config::app().get_matches_from_safe(args).map(Config::from_matches)
+    }
+
+    #[test]
+    fn test_config_args() {
+        let config = parse(&[]).expect("No argument is required");
+        assert_eq!(config.addr, Config::default().addr);
+        assert!(config.otlp.is_none());
+
+        let otlp = parse(&["--otlp", "http://collector:4318"]).unwrap().otlp.unwrap();
+        assert_eq!(otlp.protocol, OtlpProtocol::HttpProtobuf);
+        let otlp = parse(&["--otlp", "http://collector:4317", "--otlp-protocol", "grpc"]).unwrap().otlp.unwrap();
+        assert_eq!(otlp.protocol, OtlpProtocol::Grpc);
+        assert!(parse(&["--otlp-protocol", "grpc"]).is_err());
+
+        let mqtt = parse(&["--mqtt", "mqtt://broker"]).unwrap().mqtt.unwrap();
+        assert_eq!((&mqtt.topic_prefix[..], mqtt.qos), ("sysinfo", 1));
+        let mqtt = parse(&["--mqtt", "mqtt://broker", "--mqtt-topic-prefix", "edge/", "--mqtt-qos", "0"]).unwrap().mqtt.unwrap();
+        assert_eq!((&mqtt.topic_prefix[..], mqtt.qos), ("edge", 0));
+        assert!(parse(&["--mqtt-qos", "2"]).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use hyper::{Body, Method, Request, Response};
+use hyper::header::{
+    HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS,
+    ACCESS_CONTROL_REQUEST_METHOD, ALLOW, ORIGIN, VARY,
+};
+use hyper::http::StatusCode;
+
+use crate::config::Cors;
+use crate::encoding::error_response;
+
+// Response headers scripts on other origins may read besides the CORS-safelisted ones
+const EXPOSED_HEADERS: &str = "etag, last-modified, retry-after";
+
+// Methods the server answers on any path
+const ALLOWED_METHODS: &str = "GET, OPTIONS";
+
+// The value of Access-Control-Allow-Origin for `origin`, when it is allowed
+fn allow_origin(cors: &Cors, origin: &str) -> Option<HeaderValue> {
+    if cors.origins.iter().any(|allowed| allowed == "*") {
+        Some(HeaderValue::from_static("*"))
+    } else if cors.origins.iter().any(|allowed| allowed == origin) {
+        HeaderValue::from_str(origin).ok()
+    } else {
+        None
+    }
+}
+
+/// Answers `OPTIONS` requests: CORS preflights are checked against the policy,
+/// other `OPTIONS` requests are told the allowed methods.
+pub(crate) fn handle_options(cors: Option<&Cors>, req: &Request<Body>) -> Response<Body> {
+    let header = |name| req.headers().get(name).and_then(|value: &HeaderValue| value.to_str().ok());
+    let (origin, method) = match (header(ORIGIN), header(ACCESS_CONTROL_REQUEST_METHOD)) {
+        (Some(origin), Some(method)) => (origin, method),
+        _ => {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NO_CONTENT;
+            response.headers_mut().insert(ALLOW, HeaderValue::from_static(ALLOWED_METHODS));
+            return response;
+        }
+    };
+
+    let cors = match cors {
+        Some(cors) => cors,
+        None => return error_response(StatusCode::FORBIDDEN, "Cross-origin requests are not enabled".to_string()),
+    };
+    let allow_origin = match allow_origin(cors, origin) {
+        Some(allow_origin) => allow_origin,
+        None => return error_response(StatusCode::FORBIDDEN, format!("Origin `{}` is not allowed", origin)),
+    };
+    if !cors.methods.iter().any(|allowed| allowed.eq_ignore_ascii_case(method)) {
+        return error_response(StatusCode::FORBIDDEN, format!("Method `{}` is not allowed", method));
+    }
+    let requested_headers = header(ACCESS_CONTROL_REQUEST_HEADERS).unwrap_or_default();
+    let denied = requested_headers.split(',').map(str::trim).filter(|name| !name.is_empty())
+        .find(|name| !cors.headers.iter().any(|allowed| allowed.eq_ignore_ascii_case(name)));
+    if let Some(name) = denied {
+        return error_response(StatusCode::FORBIDDEN, format!("Header `{}` is not allowed", name));
+    }
+
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::NO_CONTENT;
+    let headers = response.headers_mut();
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+    headers.insert(ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_str(&cors.methods.join(", ")).unwrap());
+    if !cors.headers.is_empty() {
+        headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_str(&cors.headers.join(", ")).unwrap());
+    }
+    headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(cors.max_age.as_secs()));
+    headers.insert(VARY, HeaderValue::from_static("origin"));
+    response
+}
+
+/// Lets scripts from allowed origins read `response`.
+pub(crate) fn apply(cors: Option<&Cors>, method: &Method, origin: Option<&HeaderValue>, mut response: Response<Body>) -> Response<Body> {
+    // Preflights carry their own headers
+    let cors = match cors {
+        Some(cors) if method != Method::OPTIONS => cors,
+        _ => return response,
+    };
+    // Allowing a single origin makes the response depend on the Origin header
+    if !cors.origins.iter().any(|allowed| allowed == "*") {
+        response.headers_mut().append(VARY, HeaderValue::from_static("origin"));
+    }
+    if let Some(allow_origin) = origin.and_then(|origin| origin.to_str().ok()).and_then(|origin| allow_origin(cors, origin)) {
+        let headers = response.headers_mut();
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, HeaderValue::from_static(EXPOSED_HEADERS));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use sysinfo::{System, SystemExt};
+    use crate::collector::fixture;
+    use crate::config::Config;
+    use crate::AppState;
+
+    fn preflight(url: &str, origin: &str, method: &str, headers: &str) -> reqwest::RequestBuilder {
+        reqwest::Client::new().request(reqwest::Method::OPTIONS, url)
+            .header("origin", origin)
+            .header("access-control-request-method", method)
+            .header("access-control-request-headers", headers)
+    }
+
+    #[tokio::test]
+    async fn test_cors() {
+        let cors = Cors { origins: vec!["https://dashboard.example".to_string()], ..Cors::default() };
+        let addr = cors_test_server(Some(cors));
+
+        let url = format!("http://{}/v2/memory", addr);
+        let response = preflight(&url, "https://dashboard.example", "GET", "Authorization, If-None-Match")
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://dashboard.example");
+        assert_eq!(response.headers()["access-control-allow-methods"], "GET");
+        assert_eq!(response.headers()["access-control-allow-headers"], "authorization, if-none-match, if-modified-since");
+        assert_eq!(response.headers()["access-control-max-age"], "600");
+
+        for (origin, method, headers) in [
+            ("https://elsewhere.example", "GET", ""),
+            ("https://dashboard.example", "DELETE", ""),
+            ("https://dashboard.example", "GET", "x-custom"),
+        ] {
+            let response = preflight(&url, origin, method, headers).send().await.expect("Failed to send request");
+            assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+            assert!(response.headers().get("access-control-allow-origin").is_none());
+        }
+
+        let response = reqwest::Client::new().get(&url)
+            .header("origin", "https://dashboard.example")
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://dashboard.example");
+        assert!(response.headers()["access-control-expose-headers"].to_str().unwrap().contains("etag"));
+        assert_eq!(response.headers()["vary"], "origin");
+
+        let response = reqwest::Client::new().get(&url)
+            .header("origin", "https://elsewhere.example")
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert!(response.headers().get("access-control-allow-origin").is_none());
+
+        // Plain OPTIONS requests list the allowed methods
+        let response = reqwest::Client::new().request(reqwest::Method::OPTIONS, &url)
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["allow"], ALLOWED_METHODS);
+    }
+
+    #[tokio::test]
+    async fn test_cors_any_origin() {
+        let cors = Cors { origins: vec!["*".to_string()], ..Cors::default() };
+        let addr = cors_test_server(Some(cors));
+
+        let url = format!("http://{}/memory", addr);
+        let response = preflight(&url, "https://anywhere.example", "GET", "").send().await.expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["access-control-allow-origin"], "*");
+
+        let response = reqwest::Client::new().get(&url)
+            .header("origin", "https://anywhere.example")
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.headers()["access-control-allow-origin"], "*");
+    }
+
+    fn cors_test_server(cors: Option<Cors>) -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config { cors, ..Config::default() }))
+    }
+}
@@ -1,54 +1,56 @@
 use std::sync::{Arc, Mutex};
 use hyper::{Body, Response};
 use hyper::http::StatusCode;
-use serde_json::json;
-use sysinfo::{System, SystemExt, CpuExt};
+use sysinfo::{SystemExt, CpuExt, System};
+
+use crate::conditional::stamp;
+use crate::encoding::{respond, Format};
+use crate::model::{CpuInfo, Cpus};
+use crate::sampler::Sampled;
+
+/// Units of the `CpuInfo` fields, reported by the `/v2` API.
+pub(crate) const UNITS: &[(&str, &str)] = &[
+    ("frequency", "MHz"),
+    ("percent", "percent"),
+];
+
+/// Reads the CPU usage from sysinfo, for its collector.
+pub(crate) fn read(system: &System) -> Vec<CpuInfo> {
+    system.cpus().iter().enumerate().map(|(i, proc)| {
+        CpuInfo {
+            cpu_num: format!("cpu{}", i),
+            percent: proc.cpu_usage() as f64,
+            frequency: proc.frequency(),
+        }
+    }).collect()
+}
+
+pub(crate) fn collect(system: &mut Sampled) -> Vec<CpuInfo> {
+    system.refresh("cpus");
+    system.cpus()
+}
 
-pub(crate) async fn handle_cpus(system: Arc<Mutex<System>>) -> Result<Response<Body>, hyper::Error> {
+pub(crate) async fn handle_cpus(system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
     let mut system = system.lock().unwrap();
-    system.refresh_cpu();
-    let cpu_info: Vec<_> = system.cpus().iter().enumerate().map(|(i, proc)| {
-        json!({
-            "cpu_num": format!("cpu{}", i),
-            "percent": proc.cpu_usage(),
-            "frequency": proc.frequency() as u64
-        })
-    }).collect();
-    let body = json!({ "cpu_info": cpu_info });
-    let response = match Response::builder()
-        .header("Content-Type", "application/json")
-        .body(Body::from(body.to_string())) {
-        Ok(res) => res,
-        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap(),
-    };
-    Ok(response)
+    let body = Cpus { cpu_info: collect(&mut system) };
+    Ok(stamp(respond(format, StatusCode::OK, &body), system.refreshed("cpus")))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::convert::Infallible;
     use super::*;
+    use sysinfo::System;
     use std::net::SocketAddr;
-    use hyper::Server;
-    use hyper::service::{make_service_fn, service_fn};
-    use reqwest;
-    use tokio;
-    use crate::handle_request;
-
-    const TEST_SERVER_ADDR: &str = "127.0.0.1:8082"; // Use a different port for testing
+    use crate::config::Config;
+    use crate::AppState;
+    use serde_json::json;
+    use crate::collector::fixture;
 
     #[tokio::test]
     async fn test_cpus_endpoint() {
-        // Start the server in a background task
-        tokio::spawn(async {
-            let addr: SocketAddr = TEST_SERVER_ADDR.parse().expect("Invalid socket address");
-            cpus_test_server(addr).await;
-        });
-
-        // Give the server a moment to start
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        let addr = cpus_test_server();
 
-        let response: serde_json::Value = reqwest::get(&format!("http://{}/cpus", TEST_SERVER_ADDR))
+        let response: serde_json::Value = reqwest::get(&format!("http://{}/cpus", addr))
             .await
             .expect("Failed to send request")
             .json()
@@ -70,27 +72,26 @@ mod tests {
             assert!(cpu_obj["cpu_num"].is_string());
 
             let percent = cpu_obj.get("percent").and_then(|v| v.as_f64()).expect("`percent` is not a float");
-            assert!(percent >= 0.0 && percent <= 100.0);
+            assert!((0.0..=100.0).contains(&percent));
 
             let frequency = cpu_obj.get("frequency").and_then(|v| v.as_u64()).expect("`frequency` is not an integer");
             assert!(frequency > 0);
         }
     }
 
-    async fn cpus_test_server(addr: SocketAddr) {
-        let system = Arc::new(Mutex::new(System::new_all()));
-
-        let test_service_load_avg = make_service_fn(move |_| {
-            let system = system.clone();
-            async {
-                Ok::<_, Infallible>(service_fn(move |req| handle_request(req, system.clone())))
-            }
-        });
-
-        let server = Server::bind(&addr).serve(test_service_load_avg);
+    #[tokio::test]
+    async fn test_cpus_fixture() {
+        let (status, response) = fixture::get(&fixture::state(), "/cpus").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, json!({"cpu_info": [
+            {"cpu_num": "cpu0", "frequency": 2400, "percent": 12.5},
+            {"cpu_num": "cpu1", "frequency": 2400, "percent": 50.0},
+            {"cpu_num": "cpu2", "frequency": 3600, "percent": 100.0},
+            {"cpu_num": "cpu3", "frequency": 800, "percent": 0.0},
+        ]}));
+    }
 
-        if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
-        }
+    fn cpus_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
     }
 }
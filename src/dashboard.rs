@@ -0,0 +1,55 @@
+use hyper::{Body, Response};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::http::StatusCode;
+
+// Self-contained page, polling `/snapshot` from the same server
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+pub(crate) async fn handle_dashboard() -> Result<Response<Body>, hyper::Error> {
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(Body::from(DASHBOARD))
+        .unwrap();
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use sysinfo::{System, SystemExt};
+    use crate::collector::fixture;
+    use crate::config::Config;
+    use crate::AppState;
+
+    #[tokio::test]
+    async fn test_dashboard_endpoint() {
+        let addr = dashboard_test_server();
+
+        let response = reqwest::get(&format!("http://{}/", addr))
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/html; charset=utf-8");
+        let page = response.text().await.expect("Failed to read response");
+        assert!(page.contains("fetch(\"snapshot\""));
+
+        // Everything the page needs is embedded, nothing is loaded from elsewhere
+        assert!(!page.contains("http://") && !page.contains("https://"));
+        assert!(!page.contains("<script src") && !page.contains("<link"));
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_for_browsers() {
+        // Browsers accept HTML first, which none of the API formats are
+        let req = hyper::Request::get("/").header("accept", "text/html,application/xhtml+xml").body(hyper::Body::empty()).unwrap();
+        let response = fixture::send(&fixture::state(), req).await;
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/html; charset=utf-8");
+    }
+
+    fn dashboard_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
+    }
+}
@@ -3,53 +3,58 @@ use std::sync::{Arc, Mutex};
 use hyper::{Body, Response};
 use hyper::http::StatusCode;
 
-use serde_json::json;
-use sysinfo::{System, SystemExt, DiskExt};
-pub(crate) async fn handle_disks(system: Arc<Mutex<System>>) -> Result<Response<Body>, hyper::Error> {
+use sysinfo::{SystemExt, DiskExt, System};
+
+use crate::conditional::stamp;
+use crate::encoding::{respond, Format};
+use crate::model::{DiskInfo, DiskInfoV1};
+use crate::sampler::Sampled;
+
+/// Units of the `DiskInfo` fields, reported by the `/v2` API.
+pub(crate) const UNITS: &[(&str, &str)] = &[
+    ("available_space", "bytes"),
+    ("total_space", "bytes"),
+];
+
+/// Reads the disks from sysinfo, for its collector.
+pub(crate) fn read(system: &System) -> Vec<DiskInfo> {
+    system.disks().iter().map(|disk| {
+        DiskInfo {
+            device_name: disk.name().to_str().unwrap_or_default().to_string(),
+            file_system: std::str::from_utf8(disk.file_system()).unwrap_or_default().to_string(),
+            mount_point: disk.mount_point().to_string_lossy().to_string(),
+            total_space: disk.total_space(),
+            available_space: disk.available_space(),
+        }
+    }).collect()
+}
+
+pub(crate) fn collect(system: &mut Sampled) -> Vec<DiskInfo> {
+    system.refresh("disks");
+    system.disks()
+}
+
+pub(crate) async fn handle_disks(system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
     let mut system = system.lock().unwrap();
-    system.refresh_disks();
-    let disks_info: Vec<_> = system.disks().iter().map(|disk| {
-        json!({
-            "device_name": disk.name().to_str().unwrap_or_default(),
-            "file_system": std::str::from_utf8(disk.file_system()).unwrap_or_default(),
-            "total_space": disk.total_space(),
-            "available_space": disk.available_space()
-        })
-    }).collect();
-    let response = match Response::builder()
-        .header("Content-Type", "application/json")
-        .body(Body::from(json!(disks_info).to_string())) {
-        Ok(res) => res,
-        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap(),
-    };
-    Ok(response)
+    let disks_info: Vec<DiskInfoV1> = collect(&mut system).into_iter().map(DiskInfoV1::from).collect();
+    Ok(stamp(respond(format, StatusCode::OK, &disks_info), system.refreshed("disks")))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::convert::Infallible;
     use super::*;
+    use sysinfo::System;
     use std::net::SocketAddr;
-    use hyper::Server;
-    use hyper::service::{make_service_fn, service_fn};
-    use reqwest;
-    use tokio;
-    use crate::handle_request;
-
-    const TEST_SERVER_ADDR: &str = "127.0.0.1:8083"; // Use a different port for testing
+    use crate::config::Config;
+    use crate::AppState;
+    use serde_json::json;
+    use crate::collector::fixture;
 
     #[tokio::test]
     async fn test_disks_endpoint() {
-        // Start the server in a background task
-        tokio::spawn(async {
-            let addr: SocketAddr = TEST_SERVER_ADDR.parse().expect("Invalid socket address");
-            disks_test_server(addr).await;
-        });
-
-        // Give the server a moment to start
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        let addr = disks_test_server();
 
-        let response: serde_json::Value = reqwest::get(&format!("http://{}/disks", TEST_SERVER_ADDR))
+        let response: serde_json::Value = reqwest::get(&format!("http://{}/disks", addr))
             .await
             .expect("Failed to send request")
             .json()
@@ -77,20 +82,25 @@ mod tests {
         }
     }
 
-    async fn disks_test_server(addr: SocketAddr) {
-        let system = Arc::new(Mutex::new(System::new_all()));
-
-        let test_service_load_avg = make_service_fn(move |_| {
-            let system = system.clone();
-            async {
-                Ok::<_, Infallible>(service_fn(move |req| handle_request(req, system.clone())))
-            }
-        });
-
-        let server = Server::bind(&addr).serve(test_service_load_avg);
-
-        if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
+    #[tokio::test]
+    async fn test_disks_fixture() {
+        // The sysinfo-http compatible routes keep their shape, mount points are only in the newer ones
+        let state = fixture::state();
+        for path in ["/disks", "/v1/disks"] {
+            let (status, response) = fixture::get(&state, path).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(response, json!([
+                {"available_space": 107374182400u64, "device_name": "/dev/nvme0n1p2", "file_system": "ext4", "total_space": 536870912000u64},
+                {"available_space": 268435456, "device_name": "/dev/nvme0n1p1", "file_system": "vfat", "total_space": 536870912},
+            ]));
         }
+        let (_, response) = fixture::get(&state, "/v2/disks").await;
+        assert_eq!(response["data"][1]["mount_point"], "/boot/efi");
+        let (_, response) = fixture::get(&state, "/snapshot").await;
+        assert_eq!(response["disks"][1]["mount_point"], "/boot/efi");
+    }
+
+    fn disks_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
     }
 }
@@ -0,0 +1,297 @@
+use hyper::{Body, Request, Response};
+use hyper::header::ACCEPT;
+use hyper::http::StatusCode;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::model::ApiError;
+
+/// Representation of a response body, negotiated from `?format=` or the `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Format {
+    Json,
+    PrettyJson,
+    Ndjson,
+    Csv,
+    Yaml,
+    MessagePack,
+    Cbor,
+}
+
+// Name used by `?format=` and media type of every format
+const FORMATS: [(&str, &str, Format); 7] = [
+    ("json", "application/json", Format::Json),
+    ("pretty", "application/json", Format::PrettyJson),
+    ("ndjson", "application/x-ndjson", Format::Ndjson),
+    ("csv", "text/csv", Format::Csv),
+    ("yaml", "application/yaml", Format::Yaml),
+    ("msgpack", "application/msgpack", Format::MessagePack),
+    ("cbor", "application/cbor", Format::Cbor),
+];
+
+// Further media types accepted for some of the formats
+const ALIASES: [(&str, Format); 5] = [
+    ("application/x-yaml", Format::Yaml),
+    ("text/yaml", Format::Yaml),
+    ("application/x-msgpack", Format::MessagePack),
+    ("application/vnd.msgpack", Format::MessagePack),
+    ("application/ndjson", Format::Ndjson),
+];
+
+impl Format {
+    pub(crate) fn content_type(self) -> &'static str {
+        FORMATS.iter().find(|(_, _, format)| *format == self).map(|(_, media_type, _)| *media_type).unwrap()
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Format> {
+        match media_type {
+            "*/*" | "application/*" => Some(Format::Json),
+            _ => FORMATS.iter().filter(|(name, _, _)| *name != "pretty").map(|(_, media_type, format)| (*media_type, *format))
+                .chain(ALIASES)
+                .find(|(candidate, _)| *candidate == media_type)
+                .map(|(_, format)| format),
+        }
+    }
+
+    /// Picks the format of the response to `req`. `?format=` takes precedence over
+    /// `Accept`, JSON is used when neither is given.
+    pub(crate) fn negotiate(req: &Request<Body>) -> Result<Format, (StatusCode, String)> {
+        let query = req.uri().query().unwrap_or_default();
+        if let Some((_, name)) = url::form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "format") {
+            return FORMATS.iter().find(|(candidate, _, _)| *candidate == name).map(|(_, _, format)| *format).ok_or_else(|| {
+                let names: Vec<_> = FORMATS.iter().map(|(name, _, _)| *name).collect();
+                (StatusCode::BAD_REQUEST, format!("Unknown format `{}`, expected one of {}", name, names.join(", ")))
+            });
+        }
+
+        let accept = match req.headers().get(ACCEPT).and_then(|accept| accept.to_str().ok()) {
+            Some(accept) if !accept.trim().is_empty() => accept,
+            _ => return Ok(Format::Json),
+        };
+
+        // Media ranges ordered by their quality, keeping the order of equally preferred ones
+        let mut ranges: Vec<(f32, &str)> = accept.split(',').filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media_type = parts.next().filter(|media_type| !media_type.is_empty())?;
+            let quality = parts.filter_map(|param| param.strip_prefix("q=")).next()
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            Some((quality, media_type))
+        }).collect();
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        ranges.iter()
+            .filter(|(quality, _)| *quality > 0.0)
+            .find_map(|(_, media_type)| Format::from_media_type(&media_type.to_ascii_lowercase()))
+            .ok_or_else(|| (StatusCode::NOT_ACCEPTABLE, format!("None of `{}` can be produced", accept)))
+    }
+}
+
+// Rows of a tabular response: a list, the only list of a legacy wrapper object
+// such as `cpu_info`, or the list in the `data` of a `/v2` envelope
+fn rows(value: &Value) -> Option<&Vec<Value>> {
+    match value {
+        Value::Array(rows) => Some(rows),
+        Value::Object(object) if object.len() == 1 => object.values().next().and_then(Value::as_array),
+        Value::Object(object) => object.get("data").and_then(Value::as_array),
+        _ => None,
+    }
+}
+
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(string) => string.clone(),
+        Value::Array(values) => values.iter().map(csv_cell).collect::<Vec<_>>().join(";"),
+        other => other.to_string(),
+    }
+}
+
+fn encode_csv(rows: &[Value]) -> Result<Vec<u8>, String> {
+    let mut header: Vec<&String> = Vec::new();
+    for row in rows {
+        for key in row.as_object().ok_or("Rows are not objects")?.keys() {
+            if !header.contains(&key) {
+                header.push(key);
+            }
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&header).map_err(|e| e.to_string())?;
+    for row in rows {
+        writer.write_record(header.iter().map(|key| csv_cell(&row[key.as_str()]))).map_err(|e| e.to_string())?;
+    }
+    writer.into_inner().map_err(|e| e.to_string())
+}
+
+/// Serializes `value` in `format`. Fails for CSV if the value is not tabular.
+pub(crate) fn encode<T: Serialize>(format: Format, value: &T) -> Result<Vec<u8>, String> {
+    match format {
+        Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+        Format::PrettyJson => serde_json::to_vec_pretty(value).map_err(|e| e.to_string()),
+        Format::Ndjson => {
+            let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+            let lines = match rows(&value) {
+                Some(rows) => rows.iter().map(Value::to_string).collect(),
+                None => vec![value.to_string()],
+            };
+            Ok(lines.into_iter().map(|line| line + "\n").collect::<String>().into_bytes())
+        }
+        Format::Csv => {
+            let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+            let rows = rows(&value).ok_or("This resource is not tabular and cannot be encoded as CSV")?;
+            encode_csv(rows)
+        }
+        Format::Yaml => serde_yaml::to_string(value).map(String::into_bytes).map_err(|e| e.to_string()),
+        Format::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        Format::Cbor => {
+            let mut body = Vec::new();
+            ciborium::into_writer(value, &mut body).map_err(|e| e.to_string())?;
+            Ok(body)
+        }
+    }
+}
+
+/// Builds a response with `value` encoded in `format`.
+pub(crate) fn respond<T: Serialize>(format: Format, status: StatusCode, value: &T) -> Response<Body> {
+    let body = match encode(format, value) {
+        Ok(body) => body,
+        Err(message) => return error_response(StatusCode::NOT_ACCEPTABLE, message),
+    };
+    match Response::builder()
+        .status(status)
+        .header("Content-Type", format.content_type())
+        .body(Body::from(body)) {
+        Ok(res) => res,
+        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap(),
+    }
+}
+
+/// JSON error body shared by the `/v2` API and every endpoint added after it.
+pub(crate) fn error_response(status: StatusCode, message: String) -> Response<Body> {
+    let error = ApiError {
+        status: status.as_u16(),
+        error: status.canonical_reason().unwrap_or("error").to_lowercase().replace(' ', "_"),
+        message,
+    };
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&error).unwrap()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use sysinfo::{System, SystemExt};
+    use crate::config::Config;
+    use crate::collector::fixture;
+    use crate::model::{DiskInfoV1, MemoryInfo};
+    use crate::AppState;
+
+    fn request(accept: &str, query: &str) -> Request<Body> {
+        Request::builder()
+            .uri(format!("/disks{}", query))
+            .header(ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Format::negotiate(&request("", "")).unwrap(), Format::Json);
+        assert_eq!(Format::negotiate(&request("text/html,application/xhtml+xml,*/*;q=0.8", "")).unwrap(), Format::Json);
+        assert_eq!(Format::negotiate(&request("application/json;q=0.5, text/csv", "")).unwrap(), Format::Csv);
+        assert_eq!(Format::negotiate(&request("application/x-yaml", "")).unwrap(), Format::Yaml);
+        assert_eq!(Format::negotiate(&request("application/cbor", "?format=pretty")).unwrap(), Format::PrettyJson);
+        assert_eq!(Format::negotiate(&request("text/html", "")).unwrap_err().0, StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(Format::negotiate(&request("", "?format=xml")).unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_encode_csv() {
+        let cpus = serde_json::json!({ "cpu_info": [
+            { "cpu_num": "cpu0", "percent": 1.5 },
+            { "cpu_num": "cpu1", "percent": 2.0 },
+        ]});
+        let csv = encode(Format::Csv, &cpus).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "cpu_num,percent\ncpu0,1.5\ncpu1,2.0\n");
+
+        let groups = serde_json::json!([{ "name": "root", "group": ["root", "wheel"] }]);
+        let csv = encode(Format::Csv, &groups).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "group,name\nroot;wheel,root\n");
+
+        assert!(encode(Format::Csv, &serde_json::json!({ "boot_time": 1 })).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_content_negotiation() {
+        let addr = encoding_test_server();
+
+        let client = reqwest::Client::new();
+        let get = |path: &str, accept: &str| {
+            client.get(format!("http://{}{}", addr, path)).header("Accept", accept).send()
+        };
+
+        let response = get("/memory", "application/msgpack").await.expect("Failed to send request");
+        assert_eq!(response.headers()["Content-Type"], "application/msgpack");
+        let memory: Vec<MemoryInfo> = rmp_serde::from_slice(&response.bytes().await.unwrap()).expect("Invalid MessagePack");
+        assert_eq!(memory.len(), 1);
+
+        let response = get("/memory", "application/cbor").await.expect("Failed to send request");
+        let memory: Vec<MemoryInfo> = ciborium::from_reader(&response.bytes().await.unwrap()[..]).expect("Invalid CBOR");
+        assert_eq!(memory.len(), 1);
+
+        let response = get("/disks", "application/yaml").await.expect("Failed to send request");
+        let disks: Vec<DiskInfoV1> = serde_yaml::from_slice(&response.bytes().await.unwrap()).expect("Invalid YAML");
+        let response = get("/disks", "text/csv").await.expect("Failed to send request");
+        assert_eq!(response.headers()["Content-Type"], "text/csv");
+        let csv = response.text().await.unwrap();
+        let mut lines = csv.lines();
+        if !disks.is_empty() {
+            assert_eq!(lines.next(), Some("available_space,device_name,file_system,total_space"));
+        }
+        assert_eq!(lines.count(), disks.len());
+
+        let response = get("/v2/networks?format=ndjson", "").await.expect("Failed to send request");
+        assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+        for line in response.text().await.unwrap().lines() {
+            let network: serde_json::Value = serde_json::from_str(line).expect("Invalid NDJSON line");
+            assert!(network["interface_name"].is_string());
+        }
+
+        let response = get("/boot_time?format=pretty", "").await.expect("Failed to send request");
+        assert!(response.text().await.unwrap().contains("\n  \"boot_time\""));
+
+        let response = get("/boot_time", "text/csv").await.expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_ACCEPTABLE);
+
+        let response = get("/memory", "image/png").await.expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_paths_before_negotiation() {
+        let state = fixture::state();
+        let status = |path: &str, accept: &str| {
+            let req = Request::get(path).header(ACCEPT, accept).body(Body::empty()).unwrap();
+            let state = state.clone();
+            async move { fixture::send(&state, req).await.status() }
+        };
+        // Paths that are not there are so whatever the client accepts
+        assert_eq!(status("/nothing", "image/png").await, StatusCode::NOT_FOUND);
+        assert_eq!(status("/v1/nothing", "image/png").await, StatusCode::NOT_FOUND);
+        assert_eq!(status("/v2/nothing", "image/png").await, StatusCode::NOT_FOUND);
+        assert_eq!(status("/nothing?format=bogus", "").await, StatusCode::NOT_FOUND);
+        assert_eq!(status("/memory", "image/png").await, StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(status("/v2/memory", "image/png").await, StatusCode::NOT_ACCEPTABLE);
+    }
+
+    fn encoding_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
+    }
+}
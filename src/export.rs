@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::Interval;
+
+use crate::history::History;
+use crate::model::Snapshot;
+use crate::AppState;
+
+/// Largest UDP datagram sent, below the usual MTU to avoid fragmentation.
+pub(crate) const DATAGRAM_SIZE: usize = 1400;
+
+/// Whether each configured exporter is failing to export, by name, for `/readyz`.
+#[derive(Debug, Default)]
+pub(crate) struct Exporters(Mutex<BTreeMap<&'static str, bool>>);
+
+impl Exporters {
+    /// Records whether `exporter` failed to export the last sample, listing it from then on.
+    pub(crate) fn set_failing(&self, exporter: &'static str, failing: bool) {
+        self.0.lock().unwrap().insert(exporter, failing);
+    }
+
+    pub(crate) fn failing(&self) -> BTreeMap<&'static str, bool> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// The background sampler's snapshots as they are taken, for the exporters.
+pub(crate) struct Snapshots {
+    history: Arc<Mutex<History>>,
+    interval: Interval,
+    last: Option<u64>,
+}
+
+impl Snapshots {
+    pub(crate) fn new(state: &AppState) -> Snapshots {
+        Snapshots { history: state.history.clone(), interval: tokio::time::interval(state.config.sample_interval), last: None }
+    }
+
+    /// Waits for a snapshot newer than the one returned last.
+    pub(crate) async fn next(&mut self) -> Snapshot {
+        loop {
+            self.interval.tick().await;
+            let latest = self.history.lock().unwrap().latest().cloned();
+            if let Some(snapshot) = latest.filter(|snapshot| Some(snapshot.timestamp) != self.last) {
+                self.last = Some(snapshot.timestamp);
+                return snapshot;
+            }
+        }
+    }
+}
+
+/// `lines` joined into payloads of at most `size` bytes, or `count` lines. Longer lines are sent on their own.
+pub(crate) fn batches(lines: &[String], count: usize, size: usize) -> Vec<String> {
+    let mut batches: Vec<String> = Vec::new();
+    let mut in_batch = 0;
+    for line in lines {
+        match batches.last_mut() {
+            Some(batch) if in_batch < count && batch.len() + 1 + line.len() <= size => {
+                batch.push('\n');
+                batch.push_str(line);
+                in_batch += 1;
+            }
+            _ => {
+                batches.push(line.clone());
+                in_batch = 1;
+            }
+        }
+    }
+    batches
+}
+
+/// Socket sending datagrams to `addr`, a `host:port`.
+pub(crate) async fn udp_socket(addr: &str) -> std::io::Result<UdpSocket> {
+    let target = lookup_host(addr).await?.next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} has no address", addr)))?;
+    let socket = UdpSocket::bind(if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await?;
+    socket.connect(target).await?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches() {
+        let lines: Vec<String> = ["aaaa", "bb", "cc", "dddddd"].map(str::to_string).to_vec();
+        assert_eq!(batches(&lines, 2, 8), ["aaaa\nbb", "cc", "dddddd"]);
+        assert_eq!(batches(&lines, 10, 5), ["aaaa", "bb\ncc", "dddddd"]);
+    }
+}
@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use crate::config::Graphite;
+use crate::export::Snapshots;
+use crate::model::Snapshot;
+use crate::AppState;
+
+/// `name` as a single component of a metric path, such as `dev_sda1` for `/dev/sda1`.
+pub(crate) fn sanitize(name: &str) -> String {
+    let sanitized: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect();
+    let sanitized = sanitized.trim_matches('_');
+    if sanitized.is_empty() { "_".to_string() } else { sanitized.to_string() }
+}
+
+/// Every sample of `snapshot` as a dotted metric path below `prefix` and the host name, and its value.
+pub(crate) fn metrics(snapshot: &Snapshot, prefix: &str) -> Vec<(String, f64)> {
+    let host = snapshot.sysinfo.as_ref().map_or(&snapshot.hostname, |sysinfo| &sysinfo.host_name);
+    let base = prefix.split('.')
+        .filter(|component| !component.is_empty())
+        .map(sanitize)
+        .chain([sanitize(host)])
+        .collect::<Vec<_>>()
+        .join(".");
+    let mut metrics = Vec::new();
+    let mut metric = |path: String, value: f64| metrics.push((format!("{}.{}", base, path), value));
+
+    if let Some(memory) = &snapshot.memory {
+        let fields = [
+            ("available_memory", memory.available_memory),
+            ("free_memory", memory.free_memory),
+            ("free_swap", memory.free_swap),
+            ("total_memory", memory.total_memory),
+            ("total_swap", memory.total_swap),
+            ("used_memory", memory.used_memory),
+            ("used_swap", memory.used_swap),
+        ];
+        for (field, value) in fields {
+            metric(format!("memory.{}", field), value as f64);
+        }
+    }
+    for cpu in snapshot.cpus.iter().flatten() {
+        metric(format!("cpus.{}.percent", sanitize(&cpu.cpu_num)), cpu.percent);
+        metric(format!("cpus.{}.frequency", sanitize(&cpu.cpu_num)), cpu.frequency as f64);
+    }
+    for temperature in snapshot.temperatures.iter().flatten() {
+        metric(format!("temperatures.{}", sanitize(&temperature.name)), temperature.temperature);
+    }
+    // By mount point, as a device may be mounted at several
+    for disk in snapshot.disks.iter().flatten() {
+        metric(format!("disks.{}.available_space", sanitize(&disk.mount_point)), disk.available_space as f64);
+        metric(format!("disks.{}.total_space", sanitize(&disk.mount_point)), disk.total_space as f64);
+    }
+    for network in snapshot.networks.iter().flatten() {
+        // Counters, as the traffic since the previous refresh also depends on the requests to the API
+        metric(format!("networks.{}.total_received", sanitize(&network.interface_name)), network.total_received as f64);
+        metric(format!("networks.{}.total_transmitted", sanitize(&network.interface_name)), network.total_transmitted as f64);
+    }
+    if let Some(load) = &snapshot.load_average {
+        metric("load_average.one".to_string(), load.one);
+        metric("load_average.five".to_string(), load.five);
+        metric("load_average.fifteen".to_string(), load.fifteen);
+    }
+    if let Some(boot_time) = snapshot.boot_time {
+        metric("boot_time".to_string(), boot_time as f64);
+    }
+    if let Some(users) = &snapshot.users {
+        metric("users".to_string(), users.len() as f64);
+    }
+    metrics
+}
+
+/// Sends samples to Graphite over one TCP connection, reconnecting when it is lost.
+struct Exporter {
+    graphite: Graphite,
+    connection: Option<TcpStream>,
+    /// Whether the last push failed, to only report failures when they start
+    failing: bool,
+}
+
+impl Exporter {
+    async fn connect(&mut self) -> std::io::Result<&mut TcpStream> {
+        if self.connection.is_none() {
+            let connection = tokio::time::timeout(Duration::from_secs(5), TcpStream::connect(&self.graphite.addr)).await
+                .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out connecting"))??;
+            self.connection = Some(connection);
+        }
+        Ok(self.connection.as_mut().unwrap())
+    }
+
+    async fn send(&mut self, payload: &[u8]) -> std::io::Result<()> {
+        let connection = self.connect().await?;
+        let sent = connection.write_all(payload).await;
+        if sent.is_err() {
+            self.connection = None;
+        }
+        sent
+    }
+
+    async fn push(&mut self, snapshot: &Snapshot) {
+        let payload: String = metrics(snapshot, &self.graphite.prefix).iter()
+            .map(|(path, value)| format!("{} {} {}\n", path, value, snapshot.timestamp))
+            .collect();
+        // A connection closed by the server is only noticed when writing, so try a new one once
+        let mut sent = self.send(payload.as_bytes()).await;
+        if sent.is_err() {
+            sent = self.send(payload.as_bytes()).await;
+        }
+        match sent {
+            Ok(()) if self.failing => {
+                eprintln!("Sending to Graphite at {} again", self.graphite.addr);
+                self.failing = false;
+            }
+            Err(e) if !self.failing => {
+                eprintln!("Cannot send to Graphite at {}, retrying with every sample: {}", self.graphite.addr, e);
+                self.failing = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Sends the background sampler's snapshots to Graphite as they are taken.
+pub(crate) async fn run(state: AppState, graphite: Graphite) {
+    let mut exporter = Exporter { graphite, connection: None, failing: false };
+    let mut snapshots = Snapshots::new(&state);
+    state.exporters.set_failing("graphite", false);
+    loop {
+        exporter.push(&snapshots.next().await).await;
+        state.exporters.set_failing("graphite", exporter.failing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use crate::collector::fixture;
+
+    fn snapshot() -> Snapshot {
+        serde_json::from_str(fixture::HOST).unwrap()
+    }
+
+    #[test]
+    fn test_graphite_metrics() {
+        assert_eq!(sanitize("/dev/nvme0n1p2"), "dev_nvme0n1p2");
+        assert_eq!(sanitize("coretemp Package id 0"), "coretemp_Package_id_0");
+        assert_eq!(sanitize("box.example.com"), "box_example_com");
+        assert_eq!(sanitize("/"), "_");
+
+        let metrics = metrics(&snapshot(), "servers.");
+        assert_eq!(metrics.len(), 30);
+        assert_eq!(metrics[5], ("servers.fixture-host.memory.used_memory".to_string(), 4294967296.0));
+        assert_eq!(metrics[7], ("servers.fixture-host.cpus.cpu0.percent".to_string(), 12.5));
+        assert_eq!(metrics[15], ("servers.fixture-host.temperatures.coretemp_Package_id_0".to_string(), 54.0));
+        assert_eq!(metrics[17], ("servers.fixture-host.disks._.available_space".to_string(), 107374182400.0));
+        assert_eq!(metrics[19], ("servers.fixture-host.disks.boot_efi.available_space".to_string(), 268435456.0));
+        assert_eq!(metrics[21], ("servers.fixture-host.networks.eth0.total_received".to_string(), 10737418240.0));
+        assert_eq!(metrics[29], ("servers.fixture-host.users".to_string(), 2.0));
+        assert_eq!(super::metrics(&snapshot(), "")[0].0, "fixture-host.memory.available_memory");
+        // Prefixes are sanitized too, keeping their dots
+        assert_eq!(super::metrics(&snapshot(), "eu west..servers")[0].0, "eu_west.servers.fixture-host.memory.available_memory");
+    }
+
+    #[tokio::test]
+    async fn test_graphite_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let graphite = Graphite { addr: listener.local_addr().unwrap().to_string(), prefix: String::new() };
+        let mut exporter = Exporter { graphite, connection: None, failing: false };
+
+        exporter.push(&snapshot()).await;
+        let (mut connection, _) = listener.accept().await.unwrap();
+        let mut received = vec![0; 4096];
+        let size = connection.read(&mut received).await.unwrap();
+        let received = String::from_utf8_lossy(&received[..size]).to_string();
+        assert!(received.starts_with("fixture-host.memory.available_memory 12884901888 1700000000\n"));
+
+        // Graphite going away is noticed on the next writes, and a new connection made
+        drop(connection);
+        let mut later = snapshot();
+        later.timestamp += 5;
+        for _ in 0..3 {
+            exporter.push(&later).await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let (mut connection, _) = listener.accept().await.unwrap();
+        let mut received = String::new();
+        drop(exporter);
+        connection.read_to_string(&mut received).await.unwrap();
+        assert!(received.starts_with("fixture-host.memory.available_memory 12884901888 1700000005\n"));
+    }
+}
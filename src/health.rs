@@ -0,0 +1,133 @@
+use std::time::SystemTime;
+
+use hyper::{Body, Response};
+use hyper::http::StatusCode;
+
+use crate::AppState;
+use crate::encoding::{respond, Format};
+use crate::model::{ExporterReadiness, Health, Readiness, SubsystemReadiness};
+use crate::sampler::SUBSYSTEMS;
+
+pub(crate) async fn handle_healthz(format: Format) -> Result<Response<Body>, hyper::Error> {
+    Ok(respond(format, StatusCode::OK, &Health { status: "ok".to_string() }))
+}
+
+pub(crate) async fn handle_readyz(state: AppState, format: Format) -> Result<Response<Body>, hyper::Error> {
+    let max_age = state.config.ready_max_age;
+    let system = state.system.lock().unwrap();
+    let now = SystemTime::now();
+
+    let mut stale = Vec::new();
+    let subsystems = SUBSYSTEMS.iter().map(|subsystem| {
+        let age = system.refreshed(subsystem).map(|sampled| now.duration_since(sampled).unwrap_or_default());
+        let is_stale = age.is_none_or(|age| age > max_age);
+        if is_stale {
+            stale.push(subsystem.to_string());
+        }
+        (subsystem.to_string(), SubsystemReadiness {
+            age: age.map(|age| age.as_secs_f64()),
+            stale: is_stale,
+        })
+    }).collect();
+
+    let mut failing = Vec::new();
+    let exporters = state.exporters.failing().into_iter().map(|(exporter, is_failing)| {
+        if is_failing {
+            failing.push(exporter.to_string());
+        }
+        (exporter.to_string(), ExporterReadiness { failing: is_failing })
+    }).collect();
+
+    let ready = stale.is_empty() && failing.is_empty();
+    let body = Readiness {
+        ready,
+        max_age: max_age.as_secs(),
+        stale,
+        subsystems,
+        failing,
+        exporters,
+    };
+
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Ok(respond(format, status, &body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use sysinfo::{System, SystemExt};
+    use crate::collector::fixture;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn test_healthz_and_readyz_endpoints() {
+        let addr = health_test_server(true);
+
+        let response = reqwest::get(&format!("http://{}/healthz", addr))
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let health: serde_json::Value = response.json().await.expect("Failed to parse response as JSON");
+        assert_eq!(health["status"], "ok");
+
+        let response = reqwest::get(&format!("http://{}/readyz", addr))
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let readiness: serde_json::Value = response.json().await.expect("Failed to parse response as JSON");
+        assert_eq!(readiness["ready"], true);
+        assert!(readiness["stale"].as_array().expect("`stale` is not an array").is_empty());
+
+        let subsystems = readiness["subsystems"].as_object().expect("`subsystems` is not an object");
+        assert_eq!(subsystems.len(), SUBSYSTEMS.len());
+        for subsystem in subsystems.values() {
+            assert_eq!(subsystem["stale"], false);
+            assert!(subsystem["age"].as_f64().expect("`age` is not a float") >= 0.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_readyz_reports_stale_subsystems() {
+        let addr = health_test_server(false);
+
+        let response = reqwest::get(&format!("http://{}/readyz", addr))
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        let readiness: serde_json::Value = response.json().await.expect("Failed to parse response as JSON");
+        assert_eq!(readiness["ready"], false);
+        let stale = readiness["stale"].as_array().expect("`stale` is not an array");
+        assert_eq!(stale.len(), SUBSYSTEMS.len());
+        assert!(readiness["subsystems"]["memory"]["age"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_readyz_reports_failing_exporters() {
+        let state = fixture::state();
+        state.exporters.set_failing("graphite", false);
+        state.exporters.set_failing("mqtt", true);
+        let (status, readiness) = fixture::get(&state, "/readyz").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(readiness["ready"], false);
+        assert_eq!(readiness["failing"], serde_json::json!(["mqtt"]));
+        assert_eq!(readiness["exporters"]["graphite"]["failing"], false);
+
+        state.exporters.set_failing("mqtt", false);
+        let (_, readiness) = fixture::get(&state, "/readyz").await;
+        assert!(readiness.get("failing").is_none());
+        assert_eq!(readiness["exporters"]["mqtt"]["failing"], false);
+    }
+
+    fn health_test_server(sampled: bool) -> SocketAddr {
+        let state = AppState::new(Box::new(System::new_all()), Config::default());
+        // Every subsystem sampled once, as the background sampler does on start
+        if sampled {
+            for subsystem in SUBSYSTEMS {
+                state.system.lock().unwrap().resample(subsystem);
+            }
+        }
+        fixture::serve(state)
+    }
+}
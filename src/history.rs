@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::{Body, Response};
+use hyper::http::StatusCode;
+
+use crate::encoding::{error_response, respond, Format};
+use crate::model::{Change, Changes, DiskInfo, DisksDiff, MemoryInfo, MemoryDelta, Snapshot, SnapshotDiff, SpaceDelta};
+
+/// Snapshots of the background sampler kept for `/diff`.
+pub(crate) struct History {
+    retention: Duration,
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl History {
+    pub(crate) fn new(retention: Duration) -> History {
+        History { retention, snapshots: VecDeque::new() }
+    }
+
+    /// Adds the newest snapshot, dropping those older than the retention.
+    pub(crate) fn push(&mut self, snapshot: Snapshot) {
+        let oldest = snapshot.timestamp.saturating_sub(self.retention.as_secs());
+        self.snapshots.push_back(snapshot);
+        while self.snapshots.front().is_some_and(|snapshot| snapshot.timestamp < oldest) {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// The newest snapshot, for the exporters.
+    pub(crate) fn latest(&self) -> Option<&Snapshot> {
+        self.snapshots.back()
+    }
+
+    /// The newest snapshot taken at or before `timestamp`, or the oldest one when all are newer.
+    fn at(&self, timestamp: u64) -> Option<&Snapshot> {
+        self.snapshots.iter().rev().find(|snapshot| snapshot.timestamp <= timestamp).or(self.snapshots.front())
+    }
+}
+
+// Signed difference of two counts, saturating at the bounds of i64
+fn delta(from: u64, to: u64) -> i64 {
+    if to >= from {
+        i64::try_from(to - from).unwrap_or(i64::MAX)
+    } else {
+        i64::try_from(from - to).map_or(i64::MIN, |decrease| -decrease)
+    }
+}
+
+// Items of `to` missing from `from`, and of `from` missing from `to`
+fn changes<T: Clone + PartialEq>(from: &[T], to: &[T]) -> Changes<T> {
+    Changes {
+        added: to.iter().filter(|item| !from.contains(item)).cloned().collect(),
+        removed: from.iter().filter(|item| !to.contains(item)).cloned().collect(),
+    }
+}
+
+fn memory_delta(from: &MemoryInfo, to: &MemoryInfo) -> MemoryDelta {
+    MemoryDelta {
+        available_memory: delta(from.available_memory, to.available_memory),
+        free_memory: delta(from.free_memory, to.free_memory),
+        free_swap: delta(from.free_swap, to.free_swap),
+        total_memory: delta(from.total_memory, to.total_memory),
+        total_swap: delta(from.total_swap, to.total_swap),
+        used_memory: delta(from.used_memory, to.used_memory),
+        used_swap: delta(from.used_swap, to.used_swap),
+    }
+}
+
+fn disks_diff(from: &[DiskInfo], to: &[DiskInfo]) -> DisksDiff {
+    let find = |disks: &'_ [DiskInfo], mount_point: &str| disks.iter().find(|disk| disk.mount_point == mount_point).cloned();
+    let mut diff = DisksDiff::default();
+    for disk in to {
+        match find(from, &disk.mount_point) {
+            None => diff.mounted.push(disk.clone()),
+            Some(old) if old.available_space != disk.available_space || old.total_space != disk.total_space => {
+                diff.space.insert(disk.mount_point.clone(), SpaceDelta {
+                    available_space: delta(old.available_space, disk.available_space),
+                    total_space: delta(old.total_space, disk.total_space),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    diff.unmounted = from.iter().filter(|disk| find(to, &disk.mount_point).is_none()).cloned().collect();
+    diff
+}
+
+/// What changed from the snapshot `from` to the snapshot `to`.
+pub(crate) fn diff(from: &Snapshot, to: &Snapshot) -> SnapshotDiff {
+    let names = |snapshot: &Snapshot| -> (Vec<String>, Vec<String>) {
+        (
+            snapshot.networks.iter().flatten().map(|network| network.interface_name.clone()).collect(),
+            snapshot.users.iter().flatten().map(|user| user.name.clone()).collect(),
+        )
+    };
+    let ((from_networks, from_users), (to_networks, to_users)) = (names(from), names(to));
+
+    let mut sysinfo = BTreeMap::new();
+    if let (Some(old), Some(new)) = (&from.sysinfo, &to.sysinfo) {
+        let fields = [
+            ("distribution_id", &old.distribution_id, &new.distribution_id),
+            ("host_name", &old.host_name, &new.host_name),
+            ("kernel_version", &old.kernel_version, &new.kernel_version),
+            ("long_os_version", &old.long_os_version, &new.long_os_version),
+            ("os_version", &old.os_version, &new.os_version),
+        ];
+        for (field, old, new) in fields {
+            if old != new {
+                sysinfo.insert(field.to_string(), Change { from: old.clone(), to: new.clone() });
+            }
+        }
+    }
+
+    SnapshotDiff {
+        from: from.timestamp,
+        to: to.timestamp,
+        memory: match (&from.memory, &to.memory) {
+            (Some(old), Some(new)) => memory_delta(old, new),
+            _ => MemoryDelta::default(),
+        },
+        disks: disks_diff(from.disks.as_deref().unwrap_or_default(), to.disks.as_deref().unwrap_or_default()),
+        networks: changes(&from_networks, &to_networks),
+        users: changes(&from_users, &to_users),
+        sysinfo,
+    }
+}
+
+// `now`, seconds since the epoch, or seconds before `now` such as `-90`, `-10m`, `-2h` or `-1d`
+fn parse_time(value: &str, now: u64) -> Result<u64, String> {
+    let invalid = || format!("Invalid time `{}`, expected now, seconds since the epoch or an age such as -10m", value);
+    if value == "now" {
+        return Ok(now);
+    }
+    let Some(age) = value.strip_prefix('-') else {
+        return value.parse().map_err(|_| invalid());
+    };
+    let (count, unit) = match age.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => age.split_at(i),
+        None => (age, "s"),
+    };
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(invalid()),
+    };
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    Ok(now.saturating_sub(count.saturating_mul(unit)))
+}
+
+/// Serves `/diff?from=&to=`, comparing the stored snapshots closest to both times. `from`
+/// defaults to the oldest snapshot and `to` to the newest.
+pub(crate) async fn handle_diff(query: Option<&str>, history: Arc<Mutex<History>>, format: Format) -> Result<Response<Body>, hyper::Error> {
+    let history = history.lock().unwrap();
+    let (Some(oldest), Some(newest)) = (history.snapshots.front(), history.snapshots.back()) else {
+        return Ok(error_response(StatusCode::SERVICE_UNAVAILABLE, "No snapshot has been stored yet".to_string()));
+    };
+
+    let (mut from, mut to) = (oldest.timestamp, newest.timestamp);
+    for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        let time = match parse_time(&value, newest.timestamp) {
+            Ok(time) => time,
+            Err(message) => return Ok(error_response(StatusCode::BAD_REQUEST, message)),
+        };
+        match &*key {
+            "from" => from = time,
+            "to" => to = time,
+            _ => {}
+        }
+    }
+    if from > to {
+        return Ok(error_response(StatusCode::BAD_REQUEST, format!("`from` ({}) is after `to` ({})", from, to)));
+    }
+
+    // Both exist as the history is not empty
+    let body = diff(history.at(from).unwrap(), history.at(to).unwrap());
+    Ok(respond(format, StatusCode::OK, &body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::collector::fixture;
+    use crate::model::{NetworkInfo, UserInfo};
+
+    fn snapshot(timestamp: u64) -> Snapshot {
+        let mut snapshot: Snapshot = serde_json::from_str(fixture::HOST).unwrap();
+        snapshot.timestamp = timestamp;
+        snapshot
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new(Duration::from_secs(60));
+        for timestamp in [1000, 1030, 1060, 1090] {
+            history.push(snapshot(timestamp));
+        }
+        // 1000 is older than a minute before the newest snapshot
+        assert_eq!(history.snapshots.iter().map(|snapshot| snapshot.timestamp).collect::<Vec<_>>(), [1030, 1060, 1090]);
+        assert_eq!(history.at(1075).unwrap().timestamp, 1060);
+        assert_eq!(history.at(2000).unwrap().timestamp, 1090);
+        assert_eq!(history.at(0).unwrap().timestamp, 1030);
+
+        assert_eq!(parse_time("now", 1090), Ok(1090));
+        assert_eq!(parse_time("-30", 1090), Ok(1060));
+        assert_eq!(parse_time("-1m", 1090), Ok(1030));
+        assert_eq!(parse_time("1050", 1090), Ok(1050));
+        assert!(parse_time("-1w", 1090).is_err());
+        assert!(parse_time("yesterday", 1090).is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let from = snapshot(1000);
+        let mut to = snapshot(1600);
+        let memory = to.memory.as_mut().unwrap();
+        memory.available_memory -= 1024;
+        memory.used_memory += 1024;
+        let disks = to.disks.as_mut().unwrap();
+        let efi = disks.remove(1);
+        disks[0].available_space -= 4096;
+        // A device mounted at a second point is reported as mounted there
+        disks.push(DiskInfo { mount_point: "/srv".to_string(), ..disks[0].clone() });
+        disks.push(DiskInfo { device_name: "/dev/sdb1".to_string(), mount_point: "/media/usb".to_string(), ..efi.clone() });
+        to.networks.as_mut().unwrap().push(NetworkInfo { interface_name: "docker0".to_string(), data_received: 0, data_transmitted: 0, total_received: 0, total_transmitted: 0 });
+        to.users.as_mut().unwrap().retain(|user| user.name != "alice");
+        to.users.as_mut().unwrap().push(UserInfo { name: "bob".to_string(), group: Vec::new() });
+        to.sysinfo.as_mut().unwrap().kernel_version = "6.1.0-18-amd64".to_string();
+
+        let diff = serde_json::to_value(diff(&from, &to)).unwrap();
+        assert_eq!(diff, json!({
+            "from": 1000,
+            "to": 1600,
+            "memory": {
+                "available_memory": -1024,
+                "free_memory": 0,
+                "free_swap": 0,
+                "total_memory": 0,
+                "total_swap": 0,
+                "used_memory": 1024,
+                "used_swap": 0,
+            },
+            "disks": {
+                "mounted": [
+                    {"available_space": 107374178304u64, "device_name": "/dev/nvme0n1p2", "file_system": "ext4", "mount_point": "/srv", "total_space": 536870912000u64},
+                    {"available_space": 268435456, "device_name": "/dev/sdb1", "file_system": "vfat", "mount_point": "/media/usb", "total_space": 536870912},
+                ],
+                "unmounted": [{"available_space": 268435456, "device_name": "/dev/nvme0n1p1", "file_system": "vfat", "mount_point": "/boot/efi", "total_space": 536870912}],
+                "space": {"/": {"available_space": -4096, "total_space": 0}},
+            },
+            "networks": {"added": ["docker0"], "removed": []},
+            "users": {"added": ["bob"], "removed": ["alice"]},
+            "sysinfo": {"kernel_version": {"from": "6.1.0-13-amd64", "to": "6.1.0-18-amd64"}},
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_diff_endpoint() {
+        let state = fixture::state();
+        let (status, _) = fixture::get(&state, "/diff").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        for timestamp in [1000, 1300, 1600] {
+            state.history.lock().unwrap().push(snapshot(timestamp));
+        }
+        let (status, response) = fixture::get(&state, "/diff").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((response["from"].as_u64(), response["to"].as_u64()), (Some(1000), Some(1600)));
+
+        let (_, response) = fixture::get(&state, "/v1/diff?from=-5m&to=now").await;
+        assert_eq!((response["from"].as_u64(), response["to"].as_u64()), (Some(1300), Some(1600)));
+
+        let (status, _) = fixture::get(&state, "/diff?from=now&to=-10m").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
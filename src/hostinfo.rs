@@ -3,54 +3,51 @@ use std::sync::{Arc, Mutex};
 use hyper::{Body, Response};
 use hyper::http::StatusCode;
 
-use serde_json::json;
 use sysinfo::{System, SystemExt};
 
-pub(crate) async fn handle_system_info(system: Arc<Mutex<System>>) -> Result<Response<Body>, hyper::Error> {
+use crate::conditional::stamp;
+use crate::encoding::{respond, Format};
+use crate::model::SystemInfo;
+use crate::sampler::Sampled;
+
+/// Reads the host information from sysinfo, for its collector.
+pub(crate) fn read(system: &System) -> SystemInfo {
+    SystemInfo {
+        kernel_version: system.kernel_version().unwrap_or_else(|| "N/A".to_string()),
+        os_version: system.os_version().unwrap_or_else(|| "N/A".to_string()),
+        long_os_version: system.long_os_version().unwrap_or_else(|| "N/A".to_string()),
+        distribution_id: system.distribution_id(),
+        host_name: system.host_name().unwrap_or_else(|| "N/A".to_string()),
+    }
+}
+
+pub(crate) fn collect(system: &mut Sampled) -> SystemInfo {
+    system.refresh("system");
+    system.system_info()
+}
+
+pub(crate) async fn handle_system_info(system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
     let mut system = system.lock().unwrap();
-    system.refresh_system();
-    let system_data = json!([{
-        "kernel_version": system.kernel_version().unwrap_or_else(|| "N/A".to_string()),
-        "os_version": system.os_version().unwrap_or_else(|| "N/A".to_string()),
-        "long_os_version": system.long_os_version().unwrap_or_else(|| "N/A".to_string()),
-        "distribution_id": system.distribution_id(),
-        "host_name": system.host_name().unwrap_or_else(|| "N/A".to_string()),
-    }]);
-
-    let response = match Response::builder()
-        .header("Content-Type", "application/json")
-        .body(Body::from(json!(system_data).to_string())) {
-        Ok(res) => res,
-        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap(),
-    };
-    Ok(response)
+    let system_data = vec![collect(&mut system)];
+
+    Ok(stamp(respond(format, StatusCode::OK, &system_data), system.refreshed("system")))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::convert::Infallible;
     use super::*;
+    use sysinfo::System;
     use std::net::SocketAddr;
-    use hyper::Server;
-    use hyper::service::{make_service_fn, service_fn};
-    use reqwest;
-    use tokio;
-    use crate::handle_request;
-
-    const TEST_SERVER_ADDR: &str = "127.0.0.1:8084"; // Use a different port for testing
+    use crate::config::Config;
+    use crate::AppState;
+    use serde_json::json;
+    use crate::collector::fixture;
 
     #[tokio::test]
     async fn test_sysinfo_endpoint() {
-        // Start the server in a background task
-        tokio::spawn(async {
-            let addr: SocketAddr = TEST_SERVER_ADDR.parse().expect("Invalid socket address");
-            sysinfo_test_server(addr).await;
-        });
+        let addr = sysinfo_test_server();
 
-        // Give the server a moment to start
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-        let response: serde_json::Value = reqwest::get(&format!("http://{}/sysinfo", TEST_SERVER_ADDR))
+        let response: serde_json::Value = reqwest::get(&format!("http://{}/sysinfo", addr))
             .await
             .expect("Failed to send request")
             .json()
@@ -78,20 +75,20 @@ mod tests {
         }
     }
 
-    async fn sysinfo_test_server(addr: SocketAddr) {
-        let system = Arc::new(Mutex::new(System::new_all()));
-
-        let test_service_load_avg = make_service_fn(move |_| {
-            let system = system.clone();
-            async {
-                Ok::<_, Infallible>(service_fn(move |req| handle_request(req, system.clone())))
-            }
-        });
-
-        let server = Server::bind(&addr).serve(test_service_load_avg);
+    #[tokio::test]
+    async fn test_system_info_fixture() {
+        let (status, response) = fixture::get(&fixture::state(), "/sysinfo").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, json!([{
+            "distribution_id": "debian",
+            "host_name": "fixture-host",
+            "kernel_version": "6.1.0-13-amd64",
+            "long_os_version": "Linux 12 Debian GNU/Linux",
+            "os_version": "12",
+        }]));
+    }
 
-        if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
-        }
+    fn sysinfo_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
     }
 }
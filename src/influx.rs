@@ -0,0 +1,378 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::time::Duration;
+
+use hyper::header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE};
+use tokio::net::UdpSocket;
+
+use crate::config::Influx;
+use crate::export::{batches, udp_socket, Snapshots, DATAGRAM_SIZE};
+use crate::model::Snapshot;
+use crate::AppState;
+
+/// Where line protocol is pushed to.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Target {
+    Http(String),
+    /// `host:port`
+    Udp(String),
+}
+
+impl Target {
+    pub(crate) fn parse(url: &str) -> Result<Target, String> {
+        if let Some(addr) = url.strip_prefix("udp://") {
+            return match addr.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(Target::Udp(addr.to_string())),
+                _ => Err(format!("'{}' is not a udp://HOST:PORT address", url)),
+            };
+        }
+        match url.parse::<hyper::Uri>() {
+            Ok(uri) if matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some() => Ok(Target::Http(url.to_string())),
+            _ => Err(format!("'{}' is neither an http, https nor udp URL", url)),
+        }
+    }
+}
+
+// Backslash before every character of `special` in `value`
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// One line of line protocol.
+struct Line {
+    measurement: &'static str,
+    tags: Vec<(&'static str, String)>,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Line {
+    fn new(measurement: &'static str, tags: &[(&'static str, String)]) -> Line {
+        Line { measurement, tags: tags.to_vec(), fields: Vec::new() }
+    }
+
+    fn tag(mut self, key: &'static str, value: &str) -> Line {
+        self.tags.push((key, value.to_string()));
+        self
+    }
+
+    fn integer(mut self, key: &'static str, value: u64) -> Line {
+        self.fields.push((key, format!("{}i", value)));
+        self
+    }
+
+    // NaN and infinities have no line protocol, and InfluxDB would reject the whole write for one
+    fn float(mut self, key: &'static str, value: f64) -> Line {
+        if value.is_finite() {
+            self.fields.push((key, value.to_string()));
+        }
+        self
+    }
+
+    fn format(mut self, timestamp: u64) -> String {
+        // Sorted tags are what InfluxDB indexes fastest, and empty ones are not allowed
+        self.tags.retain(|(_, value)| !value.is_empty());
+        self.tags.sort();
+        let mut line = escape(self.measurement, &[',', ' ']);
+        for (key, value) in &self.tags {
+            line.push_str(&format!(",{}={}", key, escape(value, &[',', '=', ' '])));
+        }
+        let fields: Vec<String> = self.fields.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        format!("{} {} {}000000000", line, fields.join(","), timestamp)
+    }
+}
+
+/// Every sample of `snapshot` as line protocol, tagged with the host's name, OS and kernel.
+pub(crate) fn lines(snapshot: &Snapshot) -> Vec<String> {
+    let mut host = vec![("host", snapshot.hostname.clone())];
+    if let Some(sysinfo) = &snapshot.sysinfo {
+        host = vec![
+            ("host", sysinfo.host_name.clone()),
+            ("os", sysinfo.distribution_id.clone()),
+            ("os_version", sysinfo.os_version.clone()),
+            ("kernel", sysinfo.kernel_version.clone()),
+        ];
+    }
+
+    let mut lines = Vec::new();
+    if let Some(memory) = &snapshot.memory {
+        lines.push(Line::new("memory", &host)
+            .integer("available_memory", memory.available_memory)
+            .integer("free_memory", memory.free_memory)
+            .integer("free_swap", memory.free_swap)
+            .integer("total_memory", memory.total_memory)
+            .integer("total_swap", memory.total_swap)
+            .integer("used_memory", memory.used_memory)
+            .integer("used_swap", memory.used_swap));
+    }
+    for cpu in snapshot.cpus.iter().flatten() {
+        lines.push(Line::new("cpu", &host).tag("cpu", &cpu.cpu_num).integer("frequency", cpu.frequency).float("percent", cpu.percent));
+    }
+    for temperature in snapshot.temperatures.iter().flatten() {
+        lines.push(Line::new("temperature", &host).tag("component", &temperature.name).float("temperature", temperature.temperature));
+    }
+    for disk in snapshot.disks.iter().flatten() {
+        lines.push(Line::new("disk", &host)
+            .tag("device", &disk.device_name)
+            .tag("file_system", &disk.file_system)
+            .tag("mount_point", &disk.mount_point)
+            .integer("available_space", disk.available_space)
+            .integer("total_space", disk.total_space));
+    }
+    for network in snapshot.networks.iter().flatten() {
+        lines.push(Line::new("network", &host)
+            .tag("interface", &network.interface_name)
+            // Counters, as the traffic since the previous refresh also depends on the requests to the API
+            .integer("total_received", network.total_received)
+            .integer("total_transmitted", network.total_transmitted));
+    }
+    if let Some(load) = &snapshot.load_average {
+        lines.push(Line::new("load_average", &host).float("one", load.one).float("five", load.five).float("fifteen", load.fifteen));
+    }
+    let mut system = Line::new("system", &host);
+    if let Some(boot_time) = snapshot.boot_time {
+        system = system.integer("boot_time", boot_time);
+    }
+    if let Some(users) = &snapshot.users {
+        system = system.integer("users", users.len() as u64);
+    }
+    lines.push(system);
+    // Lines need a field, which a line of unknown floats has not
+    lines.into_iter()
+        .filter(|line| !line.fields.is_empty())
+        .map(|line| line.format(snapshot.timestamp))
+        .collect()
+}
+
+/// Pushes samples to InfluxDB, keeping the HTTP requests that failed to retry them first.
+struct Exporter {
+    influx: Influx,
+    target: Target,
+    client: reqwest::Client,
+    socket: Option<UdpSocket>,
+    queue: VecDeque<String>,
+    /// Whether lines of the last push were not exported, or only queued for retrying
+    failing: bool,
+}
+
+enum Failure {
+    /// Worth retrying, such as a network error or an overloaded server
+    Transient(String),
+    /// The request will never be accepted
+    Permanent(String),
+}
+
+impl Exporter {
+    fn new(influx: Influx) -> Exporter {
+        // Validated along with the configuration
+        let target = Target::parse(&influx.url).expect("Invalid InfluxDB URL");
+        // A hung server is retried with the next sample rather than holding up the exports
+        let client = reqwest::Client::builder().timeout(Duration::from_secs(10)).build().expect("Failed to create HTTP client");
+        Exporter { influx, target, client, socket: None, queue: VecDeque::new(), failing: false }
+    }
+
+    async fn push(&mut self, snapshot: &Snapshot) {
+        let lines = lines(snapshot);
+        self.failing = false;
+        match self.target.clone() {
+            Target::Udp(addr) => {
+                // Datagrams are not acknowledged, so there is nothing to retry
+                for datagram in batches(&lines, usize::MAX, DATAGRAM_SIZE) {
+                    if let Err(e) = self.send_udp(&addr, datagram.as_bytes()).await {
+                        eprintln!("Cannot push to InfluxDB at {}: {}", addr, e);
+                        self.socket = None;
+                        self.failing = true;
+                        break;
+                    }
+                }
+            }
+            Target::Http(url) => {
+                self.queue.extend(batches(&lines, self.influx.batch_size, usize::MAX));
+                let overflow = self.queue.len().saturating_sub(self.influx.queue);
+                if overflow > 0 {
+                    self.queue.drain(..overflow);
+                    self.failing = true;
+                    eprintln!("Dropped {} InfluxDB requests, the retry queue being full", overflow);
+                }
+                while let Some(batch) = self.queue.front() {
+                    match self.send_http(&url, batch).await {
+                        Ok(()) => {}
+                        Err(Failure::Transient(e)) => {
+                            eprintln!("Cannot push to InfluxDB, retrying later: {}", e);
+                            self.failing = true;
+                            break;
+                        }
+                        Err(Failure::Permanent(e)) => {
+                            eprintln!("InfluxDB rejected {} lines: {}", batch.lines().count(), e);
+                            self.failing = true;
+                        }
+                    }
+                    self.queue.pop_front();
+                }
+            }
+        }
+    }
+
+    async fn send_udp(&mut self, addr: &str, datagram: &[u8]) -> std::io::Result<()> {
+        if self.socket.is_none() {
+            self.socket = Some(udp_socket(addr).await?);
+        }
+        self.socket.as_ref().unwrap().send(datagram).await.map(|_| ())
+    }
+
+    async fn send_http(&self, url: &str, batch: &str) -> Result<(), Failure> {
+        let mut request = self.client.post(url).header(CONTENT_TYPE, "text/plain; charset=utf-8");
+        if let Some(token) = &self.influx.token {
+            request = request.header(AUTHORIZATION, format!("Token {}", token));
+        }
+        request = if self.influx.gzip {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(batch.as_bytes()).and_then(|_| encoder.finish())
+                .map(|body| request.header(CONTENT_ENCODING, "gzip").body(body))
+                .map_err(|e| Failure::Permanent(e.to_string()))?
+        } else {
+            request.body(batch.to_string())
+        };
+
+        let response = request.send().await.map_err(|e| Failure::Transient(e.to_string()))?;
+        let status = response.status();
+        match status {
+            status if status.is_success() => Ok(()),
+            status if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                Err(Failure::Permanent(format!("{}: {}", status, response.text().await.unwrap_or_default())))
+            }
+            status => Err(Failure::Transient(status.to_string())),
+        }
+    }
+}
+
+/// Pushes the background sampler's snapshots to InfluxDB as they are taken.
+pub(crate) async fn run(state: AppState, influx: Influx) {
+    let mut exporter = Exporter::new(influx);
+    let mut snapshots = Snapshots::new(&state);
+    state.exporters.set_failing("influxdb", false);
+    loop {
+        exporter.push(&snapshots.next().await).await;
+        state.exporters.set_failing("influxdb", exporter.failing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::io::Read;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use hyper::{Body, Request, Response, Server, StatusCode};
+    use hyper::service::{make_service_fn, service_fn};
+    use crate::collector::fixture;
+
+    fn snapshot() -> Snapshot {
+        serde_json::from_str(fixture::HOST).unwrap()
+    }
+
+    #[test]
+    fn test_line_protocol() {
+        let lines = lines(&snapshot());
+        let tags = "host=fixture-host,kernel=6.1.0-13-amd64,os=debian,os_version=12";
+        assert_eq!(lines.len(), 13);
+        assert_eq!(lines[0], format!("memory,{} available_memory=12884901888i,free_memory=4294967296i,free_swap=1073741824i,\
+            total_memory=17179869184i,total_swap=2147483648i,used_memory=4294967296i,used_swap=1073741824i 1700000000000000000", tags));
+        assert_eq!(lines[1], format!("cpu,cpu=cpu0,{} frequency=2400i,percent=12.5 1700000000000000000", tags));
+        assert_eq!(lines[5], format!("temperature,component=coretemp\\ Package\\ id\\ 0,{} temperature=54 1700000000000000000", tags));
+        assert_eq!(lines[7], "disk,device=/dev/nvme0n1p2,file_system=ext4,host=fixture-host,kernel=6.1.0-13-amd64,mount_point=/,\
+            os=debian,os_version=12 available_space=107374182400i,total_space=536870912000i 1700000000000000000");
+        assert_eq!(lines[11], format!("load_average,{} one=1.25,five=0.75,fifteen=0.5 1700000000000000000", tags));
+        assert_eq!(lines[12], format!("system,{} boot_time=1699990000i,users=2i 1700000000000000000", tags));
+
+        // Fields that are not finite are left out, and so are lines left without any
+        let mut unknown = snapshot();
+        unknown.cpus.as_mut().unwrap()[0].percent = f64::NAN;
+        unknown.temperatures.as_mut().unwrap()[0].temperature = f64::INFINITY;
+        let lines = super::lines(&unknown);
+        assert_eq!(lines.len(), 12);
+        assert_eq!(lines[1], format!("cpu,cpu=cpu0,{} frequency=2400i 1700000000000000000", tags));
+        assert!(lines.iter().all(|line| !line.contains("NaN") && !line.contains("inf")));
+
+        assert_eq!(escape("a,b=c d", &[',', '=', ' ']), "a\\,b\\=c\\ d");
+
+        assert_eq!(Target::parse("udp://telegraf:8089"), Ok(Target::Udp("telegraf:8089".to_string())));
+        assert!(Target::parse("udp://telegraf").is_err());
+        assert!(Target::parse("tcp://influxdb:8086").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_influx_http() {
+        // Stand-in for InfluxDB, unavailable for its first request
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let requests = received.clone();
+        let service = make_service_fn(move |_| {
+            let requests = requests.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let requests = requests.clone();
+                    async move {
+                        let token = req.headers()["authorization"].to_str().unwrap().to_string();
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let mut lines = String::new();
+                        flate2::read::GzDecoder::new(&body[..]).read_to_string(&mut lines).expect("Invalid gzip body");
+                        let mut requests = requests.lock().unwrap();
+                        requests.push((token, lines));
+                        let status = if requests.len() == 1 { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::NO_CONTENT };
+                        Ok::<_, Infallible>(Response::builder().status(status).body(Body::empty()).unwrap())
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let influx = Influx {
+            token: Some("secret".to_string()),
+            gzip: true,
+            batch_size: 10,
+            queue: 2,
+            ..Influx::new(&format!("http://{}/api/v2/write?org=o&bucket=b", addr))
+        };
+        let mut exporter = Exporter::new(influx);
+
+        // The failed first batch is kept, the second one not being attempted
+        exporter.push(&snapshot()).await;
+        assert_eq!(exporter.queue.len(), 2);
+        // The queue holds 2 batches, the oldest being dropped
+        let mut later = snapshot();
+        later.timestamp += 5;
+        exporter.push(&later).await;
+        assert!(exporter.queue.is_empty());
+
+        let requests = received.lock().unwrap().clone();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|(token, _)| token == "Token secret"));
+        assert_eq!(requests[1].1, lines(&later)[..10].join("\n"));
+        assert_eq!(requests[2].1, lines(&later)[10..].join("\n"));
+    }
+
+    #[tokio::test]
+    async fn test_influx_udp() {
+        let telegraf = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut exporter = Exporter::new(Influx::new(&format!("udp://{}", telegraf.local_addr().unwrap())));
+        exporter.push(&snapshot()).await;
+
+        let mut received = Vec::new();
+        let mut datagram = [0; 65536];
+        while received.len() < 13 {
+            let size = telegraf.recv(&mut datagram).await.unwrap();
+            assert!(size <= DATAGRAM_SIZE);
+            received.extend(String::from_utf8_lossy(&datagram[..size]).lines().map(str::to_string));
+        }
+        assert_eq!(received, lines(&snapshot()));
+    }
+}
@@ -0,0 +1,209 @@
+mod alerts;
+mod collector;
+mod cpus;
+mod disks;
+mod memory;
+mod temperatures;
+mod hostinfo;
+mod users;
+mod networks;
+mod load_avg;
+mod boot_time;
+mod self_stats;
+mod config;
+mod sampler;
+mod health;
+mod graphite;
+mod history;
+mod influx;
+mod mqtt;
+mod openapi;
+mod otlp;
+mod v2;
+mod snapshot;
+mod encoding;
+mod export;
+mod conditional;
+mod compression;
+mod rate_limit;
+mod recording;
+mod cors;
+mod dashboard;
+#[cfg(target_os = "linux")]
+mod procfs;
+mod server;
+mod statsd;
+mod webhooks;
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use hyper::{Body, Method, Request, Response};
+use hyper::http::StatusCode;
+
+use sysinfo_server_model as model;
+use sampler::Sampled;
+
+pub use alerts::AlertRule;
+pub use collector::{Collector, FixtureCollector, ProcessUsage};
+pub use config::{Config, Cors, Graphite, Influx, MinRefreshInterval, Mqtt, Otlp, OtlpProtocol, RateLimit, Statsd};
+#[cfg(target_os = "linux")]
+pub use procfs::ProcfsCollector;
+pub use rate_limit::ClientAddr;
+pub use recording::{Replay, ReplayCollector};
+pub use server::{Endpoint, IntoMakeService, SysinfoServer, SysinfoServerBuilder};
+pub use webhooks::{Webhook, WebhookFormat};
+
+/// State shared by every request handler and background task.
+#[derive(Clone)]
+pub(crate) struct AppState {
+    pub(crate) system: Arc<Mutex<Sampled>>,
+    pub(crate) config: Arc<Config>,
+    pub(crate) rate_limiter: Arc<rate_limit::RateLimiter>,
+    /// Endpoints served, the others answering 404
+    pub(crate) endpoints: Arc<BTreeSet<Endpoint>>,
+    /// Snapshots of the background sampler, for `/diff`
+    pub(crate) history: Arc<Mutex<history::History>>,
+    /// Alerts of the configured rules, for `/alerts`
+    pub(crate) alerts: Arc<Mutex<alerts::Alerting>>,
+    /// Notified of the alerts that fire or are resolved
+    pub(crate) webhooks: Arc<webhooks::Webhooks>,
+    /// Whether the exporters fail, for `/readyz`
+    pub(crate) exporters: Arc<export::Exporters>,
+}
+
+impl AppState {
+    pub(crate) fn new(collector: Box<dyn Collector>, config: Config) -> AppState {
+        AppState {
+            system: Arc::new(Mutex::new(Sampled::new(collector, config.min_refresh_interval.clone()))),
+            rate_limiter: Arc::new(rate_limit::RateLimiter::new(config.rate_limit)),
+            history: Arc::new(Mutex::new(history::History::new(config.history))),
+            alerts: Arc::new(Mutex::new(alerts::Alerting::new(config.alert_rules.clone()))),
+            webhooks: Arc::new(webhooks::Webhooks::new(config.webhooks.clone(), config.webhook_outbox.clone())),
+            config: Arc::new(config),
+            endpoints: Arc::new(Endpoint::ALL.into()),
+            exporters: Arc::new(export::Exporters::default()),
+        }
+    }
+}
+
+/// Current time in seconds since the Unix epoch.
+pub(crate) fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+pub(crate) fn not_found() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from("Not Found"))
+        .unwrap()
+}
+
+async fn handle_request(req: Request<Body>, state: AppState) -> Result<Response<Body>, hyper::Error> {
+    let started = Instant::now();
+    let path = req.uri().path().to_string();
+    let preconditions = conditional::Preconditions::from_request(&req);
+    let coding = compression::Coding::negotiate(&req);
+    let method = req.method().clone();
+    let origin = req.headers().get(hyper::header::ORIGIN).cloned();
+    let config = state.config.clone();
+    let response = match route_request(req, state).await {
+        Ok(response) => {
+            let response = conditional::apply(&preconditions, response);
+            let response = cors::apply(config.cors.as_ref(), &method, origin.as_ref(), response);
+            compression::compress(coding, response).await
+        }
+        Err(e) => Err(e),
+    };
+    if let Ok(res) = &response {
+        self_stats::record_request(route_label(&path, res.status()), started.elapsed());
+    }
+    response
+}
+
+// Label of a request for `path` in the statistics of /self. Unknown paths share a single label,
+// and the files below /docs that of /docs, to keep the number of series bounded
+fn route_label(path: &str, status: StatusCode) -> &str {
+    if status == StatusCode::NOT_FOUND {
+        return "not_found";
+    }
+    match path.find("/docs/") {
+        Some(docs) => &path[..docs + "/docs".len()],
+        None => path,
+    }
+}
+
+// `path` below the API version `version`, such as `/memory` for `/v2/memory`, but not for `/v2memory`
+fn below_version<'a>(path: &'a str, version: &str) -> Option<&'a str> {
+    path.strip_prefix(version).filter(|path| path.starts_with('/'))
+}
+
+async fn route_request(req: Request<Body>, state: AppState) -> Result<Response<Body>, hyper::Error> {
+    // Endpoints that are not enabled are not there at all, whatever the method or version
+    let path = req.uri().path();
+    let v2 = below_version(path, "/v2");
+    let resource = v2.or_else(|| below_version(path, "/v1")).unwrap_or(path);
+    if server::Endpoint::of_path(resource).is_some_and(|endpoint| !state.endpoints.contains(&endpoint)) {
+        return Ok(not_found());
+    }
+
+    // Unknown paths are not there, whatever the method or the client accepts
+    match v2 {
+        Some(path) if !v2::serves(path) => return Ok(v2::not_found(path)),
+        None if server::Endpoint::of_path(resource).is_none() => return Ok(not_found()),
+        _ => {}
+    }
+
+    if req.method() == Method::OPTIONS {
+        return Ok(cors::handle_options(state.config.cors.as_ref(), &req));
+    }
+
+    // Probes must keep working however busy their client is
+    if !matches!(resource, "/healthz" | "/readyz") {
+        if let Some(response) = state.rate_limiter.check(&req) {
+            return Ok(response);
+        }
+    }
+
+    // Pages for browsers, whose Accept lists HTML rather than the formats of the API
+    if v2.is_none() && req.method() == Method::GET {
+        match resource {
+            "/" => return dashboard::handle_dashboard().await,
+            #[cfg(feature = "docs-ui")]
+            path if path == "/docs" || path.starts_with("/docs/") => return openapi::handle_docs(&path["/docs".len()..]).await,
+            _ => {}
+        }
+    }
+
+    let format = match encoding::Format::negotiate(&req) {
+        Ok(format) => format,
+        Err((status, message)) => return Ok(encoding::error_response(status, message)),
+    };
+
+    if let Some(path) = v2 {
+        return v2::handle_v2(req.method(), path, state, format).await;
+    }
+
+    // The sysinfo-http compatible routes are served both unprefixed and below /v1
+    let system = state.system.clone();
+    match (req.method(), resource) {
+        (&Method::GET, "/memory") => memory::handle_memory(system, format).await,
+        (&Method::GET, "/temperatures") => temperatures::handle_temperatures(system, format).await,
+        (&Method::GET, "/sysinfo") => hostinfo::handle_system_info(system, format).await,
+        (&Method::GET, "/disks") => disks::handle_disks(system, format).await,
+        (&Method::GET, "/cpus") => cpus::handle_cpus(system, format).await,
+        (&Method::GET, "/users") => users::handle_users(system, format).await,
+        (&Method::GET, "/networks") => networks::handle_networks(system, format).await,
+        (&Method::GET, "/load_average") => load_avg::handle_load_average(system, format).await,
+        (&Method::GET, "/boot_time") => boot_time::handle_boot_time(system, format).await,
+        (&Method::GET, "/snapshot") => snapshot::handle_snapshot(req.uri().query(), system, format).await,
+        (&Method::GET, "/diff") => history::handle_diff(req.uri().query(), state.history.clone(), format).await,
+        (&Method::GET, "/alerts") => alerts::handle_alerts(state.alerts.clone(), format).await,
+        (&Method::GET, "/self") => self_stats::handle_self(system, format).await,
+        (&Method::GET, "/healthz") => health::handle_healthz(format).await,
+        (&Method::GET, "/readyz") => health::handle_readyz(state, format).await,
+        (&Method::GET, "/openapi.json") => openapi::handle_openapi(format).await,
+        _ => Ok(not_found()),
+    }
+}
@@ -3,54 +3,51 @@ use std::sync::{Arc, Mutex};
 use hyper::{Body, Response};
 use hyper::http::StatusCode;
 
-use serde_json::json;
 use sysinfo::{System, SystemExt};
 
-pub(crate) async fn handle_load_average(system: Arc<Mutex<System>>) -> Result<Response<Body>, hyper::Error> {
-    let mut system = system.lock().unwrap();
-    system.refresh_system();
-        let load_average = system.load_average();
+use crate::conditional::stamp;
+use crate::encoding::{respond, Format};
+use crate::model::LoadAverage;
+use crate::sampler::Sampled;
+
+/// Reads the load average from sysinfo, for its collector.
+pub(crate) fn read(system: &System) -> LoadAverage {
+    let load_average = system.load_average();
+
+    LoadAverage {
+        one: load_average.one,
+        five: load_average.five,
+        fifteen: load_average.fifteen,
+    }
+}
+
+pub(crate) fn collect(system: &mut Sampled) -> LoadAverage {
+    system.refresh("system");
+    system.load_average()
+}
 
-        let result = json!([{
-        "one": load_average.one,
-        "five": load_average.five,
-        "fifteen": load_average.fifteen,
-    }]);
+pub(crate) async fn handle_load_average(system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
+    let mut system = system.lock().unwrap();
+    let result = vec![collect(&mut system)];
 
-    let response = match Response::builder()
-        .header("Content-Type", "application/json")
-        .body(Body::from(json!(result).to_string())) {
-        Ok(res) => res,
-        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap(),
-    };
-    Ok(response)
+    Ok(stamp(respond(format, StatusCode::OK, &result), system.refreshed("system")))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::convert::Infallible;
     use super::*;
+    use sysinfo::System;
     use std::net::SocketAddr;
-    use hyper::Server;
-    use hyper::service::{make_service_fn, service_fn};
-    use reqwest;
-    use tokio;
-    use crate::handle_request;
-
-    const TEST_SERVER_ADDR: &str = "127.0.0.1:8085"; // Use a different port for testing
+    use crate::config::Config;
+    use crate::AppState;
+    use serde_json::json;
+    use crate::collector::fixture;
 
     #[tokio::test]
     async fn test_load_average_endpoint() {
-        // Start the server in a background task
-        tokio::spawn(async {
-            let addr: SocketAddr = TEST_SERVER_ADDR.parse().expect("Invalid socket address");
-            load_avg_test_server(addr).await;
-        });
+        let addr = load_avg_test_server();
 
-        // Give the server a moment to start
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-        let response: serde_json::Value = reqwest::get(&format!("http://{}/load_average", TEST_SERVER_ADDR))
+        let response: serde_json::Value = reqwest::get(&format!("http://{}/load_average", addr))
             .await
             .expect("Failed to send request")
             .json()
@@ -68,20 +65,14 @@ mod tests {
         assert!(load_average["one"].as_f64().unwrap() >= 0.0);
     }
 
-    async fn load_avg_test_server(addr: SocketAddr) {
-        let system = Arc::new(Mutex::new(System::new_all()));
-
-        let test_service_load_avg = make_service_fn(move |_| {
-            let system = system.clone();
-            async {
-                Ok::<_, Infallible>(service_fn(move |req| handle_request(req, system.clone())))
-            }
-        });
-
-        let server = Server::bind(&addr).serve(test_service_load_avg);
+    #[tokio::test]
+    async fn test_load_average_fixture() {
+        let (status, response) = fixture::get(&fixture::state(), "/load_average").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, json!([{"fifteen": 0.5, "five": 0.75, "one": 1.25}]));
+    }
 
-        if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
-        }
+    fn load_avg_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
     }
 }
@@ -3,54 +3,63 @@ use std::sync::{Arc, Mutex};
 use hyper::{Body, Response};
 use hyper::http::StatusCode;
 
-use serde_json::json;
 use sysinfo::{System, SystemExt};
-pub(crate) async fn handle_memory(system: Arc<Mutex<System>>) -> Result<Response<Body>, hyper::Error> {
+
+use crate::conditional::stamp;
+use crate::encoding::{respond, Format};
+use crate::model::MemoryInfo;
+use crate::sampler::Sampled;
+
+/// Units of the `MemoryInfo` fields, reported by the `/v2` API.
+pub(crate) const UNITS: &[(&str, &str)] = &[
+    ("available_memory", "bytes"),
+    ("free_memory", "bytes"),
+    ("free_swap", "bytes"),
+    ("total_memory", "bytes"),
+    ("total_swap", "bytes"),
+    ("used_memory", "bytes"),
+    ("used_swap", "bytes"),
+];
+
+/// Reads the memory usage from sysinfo, for its collector.
+pub(crate) fn read(system: &System) -> MemoryInfo {
+    MemoryInfo {
+        available_memory: system.available_memory(),
+        free_memory: system.free_memory(),
+        free_swap: system.free_swap(),
+        total_memory: system.total_memory(),
+        total_swap: system.total_swap(),
+        used_memory: system.used_memory(),
+        used_swap: system.used_swap(),
+    }
+}
+
+pub(crate) fn collect(system: &mut Sampled) -> MemoryInfo {
+    system.refresh("memory");
+    system.memory()
+}
+
+pub(crate) async fn handle_memory(system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
     let mut system = system.lock().unwrap();
-    system.refresh_memory();
-    let memory_info = json!([{
-        "available_memory": system.available_memory(),
-        "free_memory": system.free_memory(),
-        "free_swap": system.free_swap(),
-        "total_memory": system.total_memory(),
-        "total_swap": system.total_swap(),
-        "used_memory": system.used_memory(),
-        "used_swap": system.used_swap(),
-    }]);
-    let response = match Response::builder()
-        .header("Content-Type", "application/json")
-        .body(Body::from(json!(memory_info).to_string())) {
-        Ok(res) => res,
-        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap(),
-    };
-    Ok(response)
+    let memory_info = vec![collect(&mut system)];
+    Ok(stamp(respond(format, StatusCode::OK, &memory_info), system.refreshed("memory")))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::convert::Infallible;
     use super::*;
+    use sysinfo::System;
     use std::net::SocketAddr;
-    use hyper::Server;
-    use hyper::service::{make_service_fn, service_fn};
-    use reqwest;
-    use tokio;
-    use crate::handle_request;
-
-    const TEST_SERVER_ADDR: &str = "127.0.0.1:8086"; // Use a different port for testing
+    use crate::config::Config;
+    use crate::AppState;
+    use serde_json::json;
+    use crate::collector::fixture;
 
     #[tokio::test]
     async fn test_memory_endpoint() {
-        // Start the server in a background task
-        tokio::spawn(async {
-            let addr: SocketAddr = TEST_SERVER_ADDR.parse().expect("Invalid socket address");
-            memory_test_server(addr).await;
-        });
+        let addr = memory_test_server();
 
-        // Give the server a moment to start
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-        let response: serde_json::Value = reqwest::get(&format!("http://{}/memory", TEST_SERVER_ADDR))
+        let response: serde_json::Value = reqwest::get(&format!("http://{}/memory", addr))
             .await
             .expect("Failed to send request")
             .json()
@@ -91,20 +100,22 @@ mod tests {
         }
     }
 
-    async fn memory_test_server(addr: SocketAddr) {
-        let system = Arc::new(Mutex::new(System::new_all()));
-
-        let test_service_load_avg = make_service_fn(move |_| {
-            let system = system.clone();
-            async {
-                Ok::<_, Infallible>(service_fn(move |req| handle_request(req, system.clone())))
-            }
-        });
-
-        let server = Server::bind(&addr).serve(test_service_load_avg);
+    #[tokio::test]
+    async fn test_memory_fixture() {
+        let (status, response) = fixture::get(&fixture::state(), "/memory").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, json!([{
+            "available_memory": 12884901888u64,
+            "free_memory": 4294967296u64,
+            "free_swap": 1073741824u64,
+            "total_memory": 17179869184u64,
+            "total_swap": 2147483648u64,
+            "used_memory": 4294967296u64,
+            "used_swap": 1073741824u64,
+        }]));
+    }
 
-        if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
-        }
+    fn memory_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
     }
 }
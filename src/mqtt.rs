@@ -0,0 +1,280 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS, Transport};
+use serde::Serialize;
+
+use crate::config::Mqtt;
+use crate::export::Snapshots;
+use crate::model::{BootTime, Envelope, Snapshot};
+use crate::AppState;
+use crate::{boot_time, cpus, disks, memory, networks, temperatures};
+
+/// Largest MQTT packet sent or received, enough for the resources of hosts with many CPUs or disks.
+const MAX_PACKET_SIZE: usize = 1 << 20;
+/// Messages waiting for the connection to the broker, beyond which samples are dropped.
+const QUEUE: usize = 64;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// `name` as a single topic level, without the separator and wildcards.
+fn topic_level(name: &str) -> String {
+    name.chars().map(|c| if matches!(c, '/' | '+' | '#') { '_' } else { c }).collect()
+}
+
+/// Topic below which the resources of the host `hostname` are published.
+fn base_topic(mqtt: &Mqtt, hostname: &str) -> String {
+    match &mqtt.topic_prefix[..] {
+        "" => topic_level(hostname),
+        prefix => format!("{}/{}", prefix, topic_level(hostname)),
+    }
+}
+
+/// `data` of `snapshot` in its `/v2` envelope, as JSON.
+fn envelope<T: Serialize>(snapshot: &Snapshot, data: &T, units: &[(&str, &str)]) -> Vec<u8> {
+    let envelope = Envelope {
+        timestamp: snapshot.timestamp,
+        hostname: snapshot.hostname.clone(),
+        data,
+        units: units.iter().map(|(field, unit)| (field.to_string(), unit.to_string())).collect(),
+    };
+    serde_json::to_vec(&envelope).expect("Resources serialize to JSON")
+}
+
+/// Each resource of `snapshot` as the topic below `base` it is published on, and its `/v2` envelope
+/// as JSON.
+pub(crate) fn messages(snapshot: &Snapshot, base: &str) -> Vec<(String, Vec<u8>)> {
+    let topic = |resource| format!("{}/{}", base, resource);
+    let mut messages = Vec::new();
+    if let Some(memory) = &snapshot.memory {
+        messages.push((topic("memory"), envelope(snapshot, memory, memory::UNITS)));
+    }
+    if let Some(cpus) = &snapshot.cpus {
+        messages.push((topic("cpus"), envelope(snapshot, cpus, cpus::UNITS)));
+    }
+    if let Some(temperatures) = &snapshot.temperatures {
+        messages.push((topic("temperatures"), envelope(snapshot, temperatures, temperatures::UNITS)));
+    }
+    if let Some(sysinfo) = &snapshot.sysinfo {
+        messages.push((topic("sysinfo"), envelope(snapshot, sysinfo, &[])));
+    }
+    if let Some(disks) = &snapshot.disks {
+        messages.push((topic("disks"), envelope(snapshot, disks, disks::UNITS)));
+    }
+    if let Some(users) = &snapshot.users {
+        messages.push((topic("users"), envelope(snapshot, users, &[])));
+    }
+    if let Some(networks) = &snapshot.networks {
+        messages.push((topic("networks"), envelope(snapshot, networks, networks::UNITS)));
+    }
+    if let Some(load_average) = &snapshot.load_average {
+        messages.push((topic("load_average"), envelope(snapshot, load_average, &[])));
+    }
+    if let Some(boot_time) = snapshot.boot_time {
+        messages.push((topic("boot_time"), envelope(snapshot, &BootTime { boot_time }, boot_time::UNITS)));
+    }
+    messages
+}
+
+/// Publishes samples to an MQTT broker as retained messages, the connection being kept up by a
+/// task of its own.
+struct Publisher {
+    client: AsyncClient,
+    base: String,
+    qos: QoS,
+    /// Whether the connection to the broker is lost, set by the task keeping it up
+    disconnected: Arc<AtomicBool>,
+    /// Whether messages of the last sample were dropped, to only report it when it starts
+    dropping: bool,
+}
+
+impl Publisher {
+    /// Connects as the host `hostname`, whose `status` topic the broker sets to `offline` when the
+    /// connection is lost, and to `online` on every connection.
+    fn connect(mqtt: &Mqtt, hostname: &str) -> Publisher {
+        let base = base_topic(mqtt, hostname);
+        let status = format!("{}/status", base);
+        let qos = match mqtt.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        };
+
+        let mut options = MqttOptions::new(format!("sysinfo-{}", topic_level(hostname)), &mqtt.host, mqtt.port);
+        options
+            .set_keep_alive(Duration::from_secs(30))
+            .set_max_packet_size(MAX_PACKET_SIZE, MAX_PACKET_SIZE)
+            .set_last_will(LastWill::new(&status, "offline", qos, true));
+        if let Some(username) = &mqtt.username {
+            options.set_credentials(username, mqtt.password.clone().unwrap_or_default());
+        }
+        if mqtt.tls {
+            options.set_transport(match &mqtt.ca {
+                Some(ca) => Transport::tls(ca.clone(), None, None),
+                None => Transport::tls_with_default_config(),
+            });
+        }
+
+        let (client, mut connection) = AsyncClient::new(options, QUEUE);
+        let broker = format!("{}:{}", mqtt.host, mqtt.port);
+        let online = client.clone();
+        let disconnected = Arc::new(AtomicBool::new(false));
+        let failing = disconnected.clone();
+        tokio::spawn(async move {
+            loop {
+                match connection.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        if failing.swap(false, Ordering::Relaxed) {
+                            eprintln!("Connected to the MQTT broker at {} again", broker);
+                        }
+                        // Replaces the last will of the previous connection, if any
+                        let _ = online.try_publish(&status, qos, true, "online");
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        if !failing.swap(true, Ordering::Relaxed) {
+                            eprintln!("Cannot connect to the MQTT broker at {}, retrying: {}", broker, e);
+                        }
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        });
+        Publisher { client, base, qos, disconnected, dropping: false }
+    }
+
+    /// Whether samples are not getting to the broker.
+    fn failing(&self) -> bool {
+        self.dropping || self.disconnected.load(Ordering::Relaxed)
+    }
+
+    fn publish(&mut self, snapshot: &Snapshot) {
+        let messages = messages(snapshot, &self.base);
+        let count = messages.len();
+        let dropped = messages.into_iter()
+            .filter(|(topic, payload)| self.client.try_publish(topic, self.qos, true, payload.clone()).is_err())
+            .count();
+        match dropped {
+            0 if self.dropping => {
+                eprintln!("Publishing to MQTT again");
+                self.dropping = false;
+            }
+            dropped if dropped > 0 && !self.dropping => {
+                eprintln!("Dropped {} of {} MQTT messages, the queue to the broker being full", dropped, count);
+                self.dropping = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Publishes the background sampler's snapshots over MQTT as they are taken.
+pub(crate) async fn run(state: AppState, mqtt: Mqtt) {
+    let mut snapshots = Snapshots::new(&state);
+    // The topics and the last will depend on the host name
+    let mut snapshot = snapshots.next().await;
+    let mut publisher = Publisher::connect(&mqtt, &snapshot.hostname);
+    loop {
+        publisher.publish(&snapshot);
+        state.exporters.set_failing("mqtt", publisher.failing());
+        snapshot = snapshots.next().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+    use rumqttc::{ConnAck, ConnectReturnCode, PubAck, Publish};
+    use serde_json::Value;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use crate::collector::fixture;
+
+    fn snapshot() -> Snapshot {
+        serde_json::from_str(fixture::HOST).unwrap()
+    }
+
+    #[test]
+    fn test_mqtt_messages() {
+        assert_eq!(topic_level("a/b+c#"), "a_b_c_");
+        let mut mqtt = Mqtt::new("mqtts://broker").unwrap();
+        assert_eq!((&mqtt.host[..], mqtt.port, mqtt.tls), ("broker", 8883, true));
+        assert_eq!(base_topic(&mqtt, "box/1"), "sysinfo/box_1");
+        mqtt.topic_prefix = String::new();
+        assert_eq!(base_topic(&mqtt, "box"), "box");
+        assert_eq!(Mqtt::new("mqtt://[::1]:1884").unwrap().host, "::1");
+        assert!(Mqtt::new("http://broker").is_err());
+
+        let messages = messages(&snapshot(), "sysinfo/fixture-host");
+        let topics: Vec<_> = messages.iter().map(|(topic, _)| topic.strip_prefix("sysinfo/fixture-host/").unwrap()).collect();
+        assert_eq!(topics, ["memory", "cpus", "temperatures", "sysinfo", "disks", "users", "networks", "load_average", "boot_time"]);
+        let memory: Value = serde_json::from_slice(&messages[0].1).unwrap();
+        assert_eq!(memory["timestamp"], 1700000000);
+        assert_eq!(memory["hostname"], "fixture-host");
+        assert_eq!(memory["data"]["used_memory"], 4294967296u64);
+        assert_eq!(memory["units"]["used_memory"], "bytes");
+        let boot_time: Value = serde_json::from_slice(&messages[8].1).unwrap();
+        assert_eq!(boot_time["data"]["boot_time"], 1699990000);
+    }
+
+    // Reads the next packet from a client of the stand-in broker
+    async fn read(connection: &mut tokio::net::TcpStream, buffer: &mut BytesMut) -> Packet {
+        loop {
+            match rumqttc::read(buffer, MAX_PACKET_SIZE) {
+                Ok(packet) => return packet,
+                Err(rumqttc::Error::InsufficientBytes(_)) => {
+                    assert!(connection.read_buf(buffer).await.unwrap() > 0, "Connection closed");
+                }
+                Err(e) => panic!("Invalid packet: {:?}", e),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mqtt_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mqtt = Mqtt {
+            username: Some("edge".to_string()),
+            password: Some("secret".to_string()),
+            ..Mqtt::new(&format!("mqtt://{}", addr)).unwrap()
+        };
+        let mut publisher = Publisher::connect(&mqtt, "fixture-host");
+        publisher.publish(&snapshot());
+
+        // Stand-in for a broker such as mosquitto, acknowledging the connection and messages
+        let (mut connection, _) = listener.accept().await.unwrap();
+        let mut buffer = BytesMut::new();
+        let Packet::Connect(connect) = read(&mut connection, &mut buffer).await else { panic!("Expected CONNECT") };
+        assert_eq!(connect.client_id, "sysinfo-fixture-host");
+        let login = connect.login.unwrap();
+        assert_eq!((&login.username[..], &login.password[..]), ("edge", "secret"));
+        let will = connect.last_will.unwrap();
+        assert_eq!((&will.topic[..], &will.message[..], will.qos, will.retain), ("sysinfo/fixture-host/status", &b"offline"[..], QoS::AtLeastOnce, true));
+
+        let mut reply = BytesMut::new();
+        ConnAck::new(ConnectReturnCode::Success, false).write(&mut reply).unwrap();
+        connection.write_all(&reply).await.unwrap();
+
+        let mut published: Vec<Publish> = Vec::new();
+        while published.len() < 10 {
+            match read(&mut connection, &mut buffer).await {
+                Packet::Publish(publish) => {
+                    let mut reply = BytesMut::new();
+                    PubAck::new(publish.pkid).write(&mut reply).unwrap();
+                    connection.write_all(&reply).await.unwrap();
+                    published.push(publish);
+                }
+                Packet::PingReq => {}
+                packet => panic!("Unexpected packet {:?}", packet),
+            }
+        }
+        assert!(published.iter().all(|publish| publish.retain && publish.qos == QoS::AtLeastOnce));
+        let status = published.iter().find(|publish| publish.topic == "sysinfo/fixture-host/status").unwrap();
+        assert_eq!(&status.payload[..], b"online");
+        let memory = published.iter().find(|publish| publish.topic == "sysinfo/fixture-host/memory").unwrap();
+        assert_eq!(memory.payload, messages(&snapshot(), "sysinfo/fixture-host")[0].1);
+        assert!(!publisher.failing());
+    }
+}
@@ -3,55 +3,63 @@ use std::sync::{Arc, Mutex};
 use hyper::{Body, Response};
 use hyper::http::StatusCode;
 
-use serde_json::json;
-use sysinfo::{NetworksExt, System, SystemExt , NetworkExt};
-pub(crate) async fn handle_networks(system: Arc<Mutex<System>>) -> Result<Response<Body>, hyper::Error> {
-    let mut system = system.lock().unwrap();
-    system.refresh_networks_list();
-    let networks: Vec<_> = system.networks().iter()
+use sysinfo::{NetworksExt, SystemExt, NetworkExt, System};
+
+use crate::conditional::stamp;
+use crate::encoding::{respond, Format};
+use crate::model::{NetworkInfo, NetworkInfoV1};
+use crate::sampler::Sampled;
+
+/// Units of the `NetworkInfo` fields, reported by the `/v2` API.
+pub(crate) const UNITS: &[(&str, &str)] = &[
+    ("data_received", "bytes"),
+    ("data_transmitted", "bytes"),
+    ("total_received", "bytes"),
+    ("total_transmitted", "bytes"),
+];
+
+/// Reads the network interfaces from sysinfo, for its collector.
+pub(crate) fn read(system: &System) -> Vec<NetworkInfo> {
+    system.networks().iter()
         .map(|(interface_name, network)| {
-            json!({
-                "interface_name": interface_name,
-                "data_received": network.received(),
-                "data_transmitted": network.transmitted(),
-            })
+            NetworkInfo {
+                interface_name: interface_name.clone(),
+                data_received: network.received(),
+                data_transmitted: network.transmitted(),
+                total_received: network.total_received(),
+                total_transmitted: network.total_transmitted(),
+            }
         })
-        .collect();
-
-    let response = match Response::builder()
-        .header("Content-Type", "application/json")
-        .body(Body::from(json!(networks).to_string())) {
-        Ok(res) => res,
-        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap(),
-    };
-    Ok(response)
+        .collect()
+}
+
+pub(crate) fn collect(system: &mut Sampled) -> Vec<NetworkInfo> {
+    system.refresh("networks");
+    system.networks()
+}
+
+pub(crate) async fn handle_networks(system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
+    let mut system = system.lock().unwrap();
+    let networks: Vec<NetworkInfoV1> = collect(&mut system).into_iter().map(NetworkInfoV1::from).collect();
+
+    Ok(stamp(respond(format, StatusCode::OK, &networks), system.refreshed("networks")))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::convert::Infallible;
     use super::*;
+    use sysinfo::System;
     use std::net::SocketAddr;
-    use hyper::Server;
-    use hyper::service::{make_service_fn, service_fn};
-    use reqwest;
-    use tokio;
-    use crate::handle_request;
-
-    const TEST_SERVER_ADDR: &str = "127.0.0.1:8087"; // Use a different port for testing
+    use crate::config::Config;
+    use crate::AppState;
+    use serde_json::json;
+    use crate::collector::fixture;
 
     #[tokio::test]
     async fn test_networks_endpoint() {
-        // Start the server in a background task
-        tokio::spawn(async {
-            let addr: SocketAddr = TEST_SERVER_ADDR.parse().expect("Invalid socket address");
-            networks_test_server(addr).await;
-        });
-
-        // Give the server a moment to start
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        let addr = networks_test_server();
 
-        let response: serde_json::Value = reqwest::get(&format!("http://{}/networks", TEST_SERVER_ADDR))
+        let response: serde_json::Value = reqwest::get(&format!("http://{}/networks", addr))
             .await
             .expect("Failed to send request")
             .json()
@@ -75,20 +83,26 @@ mod tests {
             assert!(network_obj["interface_name"].is_string());
         }
     }
-    async fn networks_test_server(addr: SocketAddr) {
-        let system = Arc::new(Mutex::new(System::new_all()));
-
-        let test_service_load_avg = make_service_fn(move |_| {
-            let system = system.clone();
-            async {
-                Ok::<_, Infallible>(service_fn(move |req| handle_request(req, system.clone())))
-            }
-        });
 
-        let server = Server::bind(&addr).serve(test_service_load_avg);
-
-        if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
+    #[tokio::test]
+    async fn test_networks_fixture() {
+        // The sysinfo-http compatible routes keep their shape, the totals are only in the newer ones
+        let state = fixture::state();
+        for path in ["/networks", "/v1/networks"] {
+            let (status, response) = fixture::get(&state, path).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(response, json!([
+                {"data_received": 1048576, "data_transmitted": 524288, "interface_name": "eth0"},
+                {"data_received": 4096, "data_transmitted": 4096, "interface_name": "lo"},
+            ]));
         }
+        let (_, response) = fixture::get(&state, "/v2/networks").await;
+        assert_eq!(response["data"][0]["total_received"], 10737418240u64);
+        let (_, response) = fixture::get(&state, "/snapshot").await;
+        assert_eq!(response["networks"][0]["total_transmitted"], 2147483648u64);
+    }
+
+    fn networks_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
     }
 }
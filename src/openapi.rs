@@ -0,0 +1,227 @@
+use hyper::{Body, Response};
+use hyper::http::StatusCode;
+
+use schemars::JsonSchema;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use serde_json::{json, Map, Value};
+
+use crate::encoding::{respond, Format};
+use crate::model::{
+    Alert, ApiError, BootTime, CpuInfo, Cpus, DiskInfo, DiskInfoV1, Envelope, Health, LoadAverage, MemoryInfo, NetworkInfo,
+    NetworkInfoV1, Readiness, SelfInfo, Snapshot, SnapshotDiff, SystemInfo, Temperature, Temperatures, UserInfo,
+};
+use crate::snapshot::SUBSYSTEMS;
+
+fn schema<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    json!(generator.subschema_for::<T>())
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema } },
+    })
+}
+
+fn get(summary: &str, schema: Value) -> Value {
+    json!({
+        "get": {
+            "summary": summary,
+            "responses": { "200": json_response("OK", schema) },
+        }
+    })
+}
+
+/// OpenAPI 3 description of every route served by `handle_request`, with the
+/// component schemas derived from the response models.
+pub(crate) fn document() -> Value {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    let g = &mut generator;
+
+    // Resources served unprefixed, below /v1 and enveloped below /v2
+    let resources = [
+        ("/memory", "Memory and swap usage in bytes", schema::<Vec<MemoryInfo>>(g), schema::<Envelope<MemoryInfo>>(g)),
+        ("/temperatures", "Temperatures of labelled components", schema::<Temperatures>(g), schema::<Envelope<Vec<Temperature>>>(g)),
+        ("/sysinfo", "Kernel, operating system and host name", schema::<Vec<SystemInfo>>(g), schema::<Envelope<SystemInfo>>(g)),
+        ("/disks", "Mounted disks with sizes in bytes", schema::<Vec<DiskInfoV1>>(g), schema::<Envelope<Vec<DiskInfo>>>(g)),
+        ("/cpus", "Usage and frequency of every CPU", schema::<Cpus>(g), schema::<Envelope<Vec<CpuInfo>>>(g)),
+        ("/users", "Users and their groups", schema::<Vec<UserInfo>>(g), schema::<Envelope<Vec<UserInfo>>>(g)),
+        ("/networks", "Bytes received and transmitted per interface", schema::<Vec<NetworkInfoV1>>(g), schema::<Envelope<Vec<NetworkInfo>>>(g)),
+        ("/load_average", "Load average", schema::<Vec<LoadAverage>>(g), schema::<Envelope<LoadAverage>>(g)),
+        ("/boot_time", "Boot time in seconds since the epoch", schema::<BootTime>(g), schema::<Envelope<BootTime>>(g)),
+    ];
+    let error = schema::<ApiError>(g);
+
+    let mut paths = Map::new();
+    for (path, summary, legacy, enveloped) in resources {
+        paths.insert(path.to_string(), get(summary, legacy.clone()));
+        paths.insert(format!("/v1{}", path), get(summary, legacy));
+        let mut v2 = get(summary, enveloped);
+        v2["get"]["responses"]["default"] = json_response("Error", error.clone());
+        paths.insert(format!("/v2{}", path), v2);
+    }
+    let mut snapshot = get("Every subsystem sampled at once", schema::<Snapshot>(g));
+    snapshot["get"]["parameters"] = json!([{
+        "name": "include",
+        "in": "query",
+        "description": format!("Comma separated subsystems to include, all by default: {}", SUBSYSTEMS.join(", ")),
+        "schema": { "type": "string" },
+    }]);
+    snapshot["get"]["responses"]["400"] = json_response("Unknown subsystem", error.clone());
+    paths.insert("/snapshot".to_string(), snapshot);
+    let time = |name, default| json!({
+        "name": name,
+        "in": "query",
+        "description": format!("`now`, seconds since the epoch or an age such as `-10m`, the {} stored snapshot by default", default),
+        "schema": { "type": "string" },
+    });
+    let mut diff = get("What changed between two snapshots of the background sampler", schema::<SnapshotDiff>(g));
+    diff["get"]["parameters"] = json!([time("from", "oldest"), time("to", "newest")]);
+    diff["get"]["responses"]["400"] = json_response("Invalid time", error.clone());
+    diff["get"]["responses"]["503"] = json_response("No snapshot stored yet", error.clone());
+    paths.insert("/diff".to_string(), diff);
+    paths.insert("/alerts".to_string(), get("Pending, firing and recently resolved alerts", schema::<Vec<Alert>>(g)));
+    paths.insert("/self".to_string(), get("Statistics of the server process itself", schema::<SelfInfo>(g)));
+    paths.insert("/healthz".to_string(), get("Liveness probe", schema::<Health>(g)));
+    paths.insert("/readyz".to_string(), json!({
+        "get": {
+            "summary": "Readiness probe",
+            "responses": {
+                "200": json_response("Every subsystem was sampled recently, and no exporter fails", schema::<Readiness>(g)),
+                "503": json_response("At least one subsystem is stale, or exporter failing", schema::<Readiness>(g)),
+            },
+        }
+    }));
+    paths.insert("/openapi.json".to_string(), get("This document", json!({ "type": "object" })));
+
+    // Definitions are not passed through the OpenAPI visitors by the generator itself
+    let mut schemas = generator.take_definitions();
+    for visitor in generator.visitors_mut() {
+        for schema in schemas.values_mut() {
+            visitor.visit_schema(schema);
+        }
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "HTTP server for the sysinfo crate, compatible with sysinfo-http",
+        },
+        "paths": paths,
+        "components": { "schemas": schemas },
+    })
+}
+
+pub(crate) async fn handle_openapi(format: Format) -> Result<Response<Body>, hyper::Error> {
+    Ok(respond(format, StatusCode::OK, &document()))
+}
+
+/// Serves the embedded Swagger UI, `tail` being the path below `/docs`.
+#[cfg(feature = "docs-ui")]
+pub(crate) async fn handle_docs(tail: &str) -> Result<Response<Body>, hyper::Error> {
+    use std::sync::Arc;
+
+    // Relative asset paths in the UI only resolve below a trailing slash. Both the redirect and
+    // the document URL are relative to keep working below the prefix of an embedding service
+    if tail.is_empty() {
+        let response = Response::builder()
+            .status(StatusCode::MOVED_PERMANENTLY)
+            .header("Location", "docs/")
+            .body(Body::empty())
+            .unwrap();
+        return Ok(response);
+    }
+
+    let config = Arc::new(utoipa_swagger_ui::Config::from("../openapi.json"));
+    let response = match utoipa_swagger_ui::serve(tail.trim_start_matches('/'), config) {
+        Ok(Some(file)) => Response::builder()
+            .header("Content-Type", file.content_type)
+            .body(Body::from(file.bytes.into_owned())),
+        Ok(None) => Response::builder().status(StatusCode::NOT_FOUND).body(Body::from("Not Found")),
+        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()),
+    };
+    Ok(response.unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use sysinfo::{System, SystemExt};
+    use crate::collector::fixture;
+    use crate::config::Config;
+    use crate::AppState;
+
+    // Checks that `value` has exactly the properties the referenced schema declares
+    fn assert_matches_schema(spec: &Value, schema: &Value, value: &Value) {
+        let schema = match schema["$ref"].as_str() {
+            Some(reference) => &spec["components"]["schemas"][reference.rsplit('/').next().unwrap()],
+            None => schema,
+        };
+        match schema["type"].as_str() {
+            Some("array") => {
+                for item in value.as_array().expect("Value is not an array") {
+                    assert_matches_schema(spec, &schema["items"], item);
+                }
+            }
+            Some("object") if schema.get("properties").is_some() => {
+                let object = value.as_object().expect("Value is not an object");
+                let properties = schema["properties"].as_object().unwrap();
+                let mut expected: Vec<_> = properties.keys().collect();
+                let mut actual: Vec<_> = object.keys().collect();
+                expected.sort();
+                actual.sort();
+                assert_eq!(expected, actual);
+                for (name, property) in properties {
+                    assert_matches_schema(spec, property, &object[name]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[cfg(feature = "docs-ui")]
+    #[tokio::test]
+    async fn test_docs_for_browsers() {
+        let req = hyper::Request::get("/docs/").header("accept", "text/html,application/xhtml+xml").body(Body::empty()).unwrap();
+        let response = crate::collector::fixture::send(&crate::collector::fixture::state(), req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+    }
+
+    #[tokio::test]
+    async fn test_openapi_endpoint() {
+        let addr = openapi_test_server();
+
+        let spec: Value = reqwest::get(&format!("http://{}/openapi.json", addr))
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse response as JSON");
+
+        assert_eq!(spec["openapi"], "3.0.3");
+        let paths = spec["paths"].as_object().expect("`paths` is not an object");
+
+        // Every documented resource is served in the documented shape
+        for (path, item) in paths {
+            if path == "/readyz" || path == "/diff" || path == "/openapi.json" {
+                continue;
+            }
+            let body: Value = reqwest::get(&format!("http://{}{}", addr, path))
+                .await
+                .expect("Failed to send request")
+                .json()
+                .await
+                .expect("Failed to parse response as JSON");
+            let schema = &item["get"]["responses"]["200"]["content"]["application/json"]["schema"];
+            assert_matches_schema(&spec, schema, &body);
+        }
+    }
+
+    fn openapi_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
+    }
+}
@@ -0,0 +1,475 @@
+use std::time::Duration;
+
+use hyper::header::CONTENT_TYPE;
+use opentelemetry_proto::tonic::collector::metrics::v1::metrics_service_client::MetricsServiceClient;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::metrics::v1::{
+    metric, number_data_point, AggregationTemporality, Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum,
+};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use prost::Message;
+use tonic::metadata::{MetadataKey, MetadataValue};
+use tonic::transport::{Channel, ClientTlsConfig};
+
+use crate::config::{Otlp, OtlpProtocol};
+use crate::export::Snapshots;
+use crate::model::Snapshot;
+use crate::AppState;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn attribute(key: &str, value: any_value::Value) -> KeyValue {
+    KeyValue { key: key.to_string(), value: Some(AnyValue { value: Some(value) }) }
+}
+
+fn string(key: &str, value: &str) -> KeyValue {
+    attribute(key, any_value::Value::StringValue(value.to_string()))
+}
+
+fn nanos(seconds: u64) -> u64 {
+    seconds.saturating_mul(1_000_000_000)
+}
+
+/// Kinds of OTLP metric, as the semantic conventions give them.
+enum Kind {
+    Gauge,
+    /// Cumulative sum that may go down, such as memory usage
+    UpDownCounter,
+    /// Cumulative sum that only goes up, such as network traffic
+    Counter,
+}
+
+/// Data points of one metric, all at the snapshot's time.
+struct Points {
+    name: &'static str,
+    unit: &'static str,
+    kind: Kind,
+    start: u64,
+    time: u64,
+    points: Vec<NumberDataPoint>,
+}
+
+impl Points {
+    fn add(&mut self, value: number_data_point::Value, attributes: Vec<KeyValue>) -> &mut Points {
+        self.points.push(NumberDataPoint {
+            attributes,
+            start_time_unix_nano: self.start,
+            time_unix_nano: self.time,
+            value: Some(value),
+            ..Default::default()
+        });
+        self
+    }
+
+    fn int(&mut self, value: u64, attributes: Vec<KeyValue>) -> &mut Points {
+        self.add(number_data_point::Value::AsInt(i64::try_from(value).unwrap_or(i64::MAX)), attributes)
+    }
+
+    fn double(&mut self, value: f64, attributes: Vec<KeyValue>) -> &mut Points {
+        self.add(number_data_point::Value::AsDouble(value), attributes)
+    }
+
+    fn metric(self) -> Metric {
+        let data_points = self.points;
+        let data = match self.kind {
+            Kind::Gauge => metric::Data::Gauge(Gauge { data_points }),
+            Kind::UpDownCounter => metric::Data::Sum(Sum {
+                data_points,
+                aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                is_monotonic: false,
+            }),
+            Kind::Counter => metric::Data::Sum(Sum {
+                data_points,
+                aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                is_monotonic: true,
+            }),
+        };
+        Metric { name: self.name.to_string(), unit: self.unit.to_string(), data: Some(data), ..Default::default() }
+    }
+}
+
+/// Attributes of the resource, the host, following the semantic conventions.
+fn resource(snapshot: &Snapshot) -> Resource {
+    let mut attributes = vec![
+        string("service.name", env!("CARGO_PKG_NAME")),
+        string("service.version", env!("CARGO_PKG_VERSION")),
+    ];
+    match &snapshot.sysinfo {
+        Some(sysinfo) => {
+            attributes.push(string("host.name", &sysinfo.host_name));
+            attributes.push(string("os.name", &sysinfo.distribution_id));
+            attributes.push(string("os.version", &sysinfo.os_version));
+            attributes.push(string("os.description", &sysinfo.long_os_version));
+        }
+        None => attributes.push(string("host.name", &snapshot.hostname)),
+    }
+    let os_type = match std::env::consts::OS {
+        "macos" => "darwin",
+        os => os,
+    };
+    attributes.push(string("os.type", os_type));
+    Resource { attributes, dropped_attributes_count: 0 }
+}
+
+/// The samples of `snapshot` as OTLP metrics named as in the semantic conventions for system
+/// metrics.
+pub(crate) fn request(snapshot: &Snapshot) -> ExportMetricsServiceRequest {
+    let time = nanos(snapshot.timestamp);
+    let boot = nanos(snapshot.boot_time.unwrap_or_default());
+    let mut metrics = Vec::new();
+    let points = |name, unit, kind, start| Points { name, unit, kind, start, time, points: Vec::new() };
+
+    if let Some(memory) = &snapshot.memory {
+        let state = |state| vec![string("system.memory.state", state)];
+        let mut usage = points("system.memory.usage", "By", Kind::UpDownCounter, boot);
+        usage.int(memory.used_memory, state("used")).int(memory.free_memory, state("free"));
+        let mut utilization = points("system.memory.utilization", "1", Kind::Gauge, 0);
+        if memory.total_memory > 0 {
+            let total = memory.total_memory as f64;
+            utilization.double(memory.used_memory as f64 / total, state("used")).double(memory.free_memory as f64 / total, state("free"));
+        }
+
+        let state = |state| vec![string("system.paging.state", state)];
+        let mut paging = points("system.paging.usage", "By", Kind::UpDownCounter, boot);
+        paging.int(memory.used_swap, state("used")).int(memory.free_swap, state("free"));
+        let mut paging_utilization = points("system.paging.utilization", "1", Kind::Gauge, 0);
+        if memory.total_swap > 0 {
+            let total = memory.total_swap as f64;
+            paging_utilization.double(memory.used_swap as f64 / total, state("used")).double(memory.free_swap as f64 / total, state("free"));
+        }
+        metrics.extend([usage, utilization, paging, paging_utilization]);
+    }
+    if let Some(cpus) = &snapshot.cpus {
+        let mut utilization = points("system.cpu.utilization", "1", Kind::Gauge, 0);
+        let mut frequency = points("system.cpu.frequency", "Hz", Kind::Gauge, 0);
+        for (number, cpu) in cpus.iter().enumerate() {
+            let cpu_number = || vec![attribute("system.cpu.logical_number", any_value::Value::IntValue(number as i64))];
+            utilization.double(cpu.percent / 100.0, cpu_number());
+            frequency.int(cpu.frequency.saturating_mul(1_000_000), cpu_number());
+        }
+        metrics.extend([utilization, frequency]);
+    }
+    if let Some(disks) = &snapshot.disks {
+        let mut usage = points("system.filesystem.usage", "By", Kind::UpDownCounter, boot);
+        let mut utilization = points("system.filesystem.utilization", "1", Kind::Gauge, 0);
+        for disk in disks {
+            let attributes = |state: Option<&str>| {
+                let mut attributes = vec![
+                    string("system.device", &disk.device_name),
+                    string("system.filesystem.mountpoint", &disk.mount_point),
+                    string("system.filesystem.type", &disk.file_system),
+                ];
+                attributes.extend(state.map(|state| string("system.filesystem.state", state)));
+                attributes
+            };
+            let used = disk.total_space.saturating_sub(disk.available_space);
+            usage.int(used, attributes(Some("used"))).int(disk.available_space, attributes(Some("free")));
+            if disk.total_space > 0 {
+                utilization.double(used as f64 / disk.total_space as f64, attributes(None));
+            }
+        }
+        metrics.extend([usage, utilization]);
+    }
+    if let Some(networks) = &snapshot.networks {
+        // Counted since boot, as the traffic since the previous refresh also depends on the requests to the API
+        let mut io = points("system.network.io", "By", Kind::Counter, boot);
+        for network in networks {
+            let attributes = |direction| vec![string("network.interface.name", &network.interface_name), string("network.io.direction", direction)];
+            io.int(network.total_received, attributes("receive")).int(network.total_transmitted, attributes("transmit"));
+        }
+        metrics.push(io);
+    }
+    if let Some(load) = &snapshot.load_average {
+        for (name, value) in [("system.cpu.load_average.1m", load.one), ("system.cpu.load_average.5m", load.five), ("system.cpu.load_average.15m", load.fifteen)] {
+            let mut load = points(name, "{thread}", Kind::Gauge, 0);
+            load.double(value, Vec::new());
+            metrics.push(load);
+        }
+    }
+    if let Some(temperatures) = &snapshot.temperatures {
+        let mut temperature = points("hw.temperature", "Cel", Kind::Gauge, 0);
+        for sensor in temperatures {
+            temperature.double(sensor.temperature, vec![string("hw.id", &sensor.name), string("hw.type", "temperature")]);
+        }
+        metrics.push(temperature);
+    }
+    if let Some(boot_time) = snapshot.boot_time {
+        let mut uptime = points("system.uptime", "s", Kind::Gauge, 0);
+        uptime.int(snapshot.timestamp.saturating_sub(boot_time), Vec::new());
+        metrics.push(uptime);
+    }
+
+    let metrics = metrics.into_iter().filter(|points| !points.points.is_empty()).map(Points::metric).collect();
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Some(resource(snapshot)),
+            scope_metrics: vec![ScopeMetrics {
+                scope: Some(InstrumentationScope {
+                    name: env!("CARGO_PKG_NAME").to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    ..Default::default()
+                }),
+                metrics,
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    }
+}
+
+enum Transport {
+    Grpc(MetricsServiceClient<Channel>),
+    Http { client: reqwest::Client, url: String },
+}
+
+/// Exports samples to an OpenTelemetry collector.
+struct Exporter {
+    otlp: Otlp,
+    transport: Transport,
+    /// Whether the last export failed, to only report failures when they start
+    failing: bool,
+}
+
+impl Exporter {
+    fn new(otlp: Otlp) -> Result<Exporter, String> {
+        let transport = match otlp.protocol {
+            OtlpProtocol::Grpc => {
+                let mut endpoint = Channel::from_shared(otlp.endpoint.clone()).map_err(|e| e.to_string())?.timeout(TIMEOUT);
+                if otlp.endpoint.starts_with("https:") {
+                    endpoint = endpoint.tls_config(ClientTlsConfig::new()).map_err(|e| e.to_string())?;
+                }
+                Transport::Grpc(MetricsServiceClient::new(endpoint.connect_lazy()))
+            }
+            OtlpProtocol::HttpProtobuf => {
+                let endpoint = otlp.endpoint.trim_end_matches('/');
+                let url = if endpoint.ends_with("/v1/metrics") { endpoint.to_string() } else { format!("{}/v1/metrics", endpoint) };
+                let client = reqwest::Client::builder().timeout(TIMEOUT).build().map_err(|e| e.to_string())?;
+                Transport::Http { client, url }
+            }
+        };
+        Ok(Exporter { otlp, transport, failing: false })
+    }
+
+    async fn send(&mut self, request: ExportMetricsServiceRequest) -> Result<(), String> {
+        match &mut self.transport {
+            Transport::Grpc(client) => {
+                let mut request = tonic::Request::new(request);
+                for (name, value) in &self.otlp.headers {
+                    let name = MetadataKey::from_bytes(name.as_bytes()).map_err(|e| e.to_string())?;
+                    let value: MetadataValue<_> = value.parse().map_err(|_| format!("Invalid value of the {} header", name))?;
+                    request.metadata_mut().insert(name, value);
+                }
+                client.export(request).await.map(|_| ()).map_err(|status| status.to_string())
+            }
+            Transport::Http { client, url } => {
+                let mut request = client.post(url.as_str()).header(CONTENT_TYPE, "application/x-protobuf").body(request.encode_to_vec());
+                for (name, value) in &self.otlp.headers {
+                    request = request.header(name, value);
+                }
+                let response = request.send().await.map_err(|e| e.to_string())?;
+                match response.status() {
+                    status if status.is_success() => Ok(()),
+                    status => Err(status.to_string()),
+                }
+            }
+        }
+    }
+
+    async fn push(&mut self, snapshot: &Snapshot) {
+        match self.send(request(snapshot)).await {
+            Ok(()) if self.failing => {
+                eprintln!("Exporting to the OpenTelemetry collector at {} again", self.otlp.endpoint);
+                self.failing = false;
+            }
+            Err(e) if !self.failing => {
+                eprintln!("Cannot export to the OpenTelemetry collector at {}, retrying with every sample: {}", self.otlp.endpoint, e);
+                self.failing = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Exports the background sampler's snapshots over OTLP as they are taken.
+pub(crate) async fn run(state: AppState, otlp: Otlp) {
+    let mut exporter = match Exporter::new(otlp) {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            state.exporters.set_failing("otlp", true);
+            return eprintln!("Cannot export over OTLP: {}", e);
+        }
+    };
+    let mut snapshots = Snapshots::new(&state);
+    state.exporters.set_failing("otlp", false);
+    loop {
+        exporter.push(&snapshots.next().await).await;
+        state.exporters.set_failing("otlp", exporter.failing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use hyper::{Body, Request, Response, Server};
+    use hyper::service::{make_service_fn, service_fn};
+    use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceResponse;
+    use opentelemetry_proto::tonic::collector::metrics::v1::metrics_service_server::{MetricsService, MetricsServiceServer};
+    use crate::collector::fixture;
+
+    fn snapshot() -> Snapshot {
+        serde_json::from_str(fixture::HOST).unwrap()
+    }
+
+    fn metric<'a>(request: &'a ExportMetricsServiceRequest, name: &str) -> &'a Metric {
+        request.resource_metrics[0].scope_metrics[0].metrics.iter().find(|metric| metric.name == name).unwrap()
+    }
+
+    // Values of the data points of `metric`, with the value of their attribute `key`
+    fn values(metric: &Metric, key: &str) -> Vec<(String, number_data_point::Value)> {
+        let points = match metric.data.as_ref().unwrap() {
+            metric::Data::Gauge(gauge) => &gauge.data_points,
+            metric::Data::Sum(sum) => &sum.data_points,
+            _ => unreachable!(),
+        };
+        points.iter().map(|point| {
+            let attribute = point.attributes.iter().find(|attribute| attribute.key == key).and_then(|attribute| attribute.value.clone());
+            let attribute = match attribute.and_then(|value| value.value) {
+                Some(any_value::Value::StringValue(value)) => value,
+                Some(any_value::Value::IntValue(value)) => value.to_string(),
+                _ => String::new(),
+            };
+            (attribute, point.value.clone().unwrap())
+        }).collect()
+    }
+
+    #[test]
+    fn test_otlp_metrics() {
+        use number_data_point::Value::{AsDouble, AsInt};
+
+        let request = request(&snapshot());
+        let resource = request.resource_metrics[0].resource.as_ref().unwrap();
+        let attributes: Vec<_> = resource.attributes.iter().map(|attribute| &attribute.key[..]).collect();
+        assert_eq!(attributes, ["service.name", "service.version", "host.name", "os.name", "os.version", "os.description", "os.type"]);
+        assert_eq!(resource.attributes[2], string("host.name", "fixture-host"));
+        assert_eq!(resource.attributes[3], string("os.name", "debian"));
+
+        let usage = metric(&request, "system.memory.usage");
+        assert_eq!(usage.unit, "By");
+        assert_eq!(values(usage, "system.memory.state"), [("used".to_string(), AsInt(4294967296)), ("free".to_string(), AsInt(4294967296))]);
+        let Some(metric::Data::Sum(sum)) = &usage.data else { panic!("Not a sum") };
+        assert!(!sum.is_monotonic);
+        assert_eq!(sum.data_points[0].start_time_unix_nano, 1699990000000000000);
+        assert_eq!(sum.data_points[0].time_unix_nano, 1700000000000000000);
+
+        let utilization = values(metric(&request, "system.cpu.utilization"), "system.cpu.logical_number");
+        assert_eq!(utilization.len(), 4);
+        assert_eq!(utilization[0], ("0".to_string(), AsDouble(0.125)));
+        assert_eq!(values(metric(&request, "system.cpu.frequency"), "system.cpu.logical_number")[2], ("2".to_string(), AsInt(3600000000)));
+
+        let filesystem = values(metric(&request, "system.filesystem.usage"), "system.device");
+        assert_eq!(filesystem[1], ("/dev/nvme0n1p2".to_string(), AsInt(107374182400)));
+        let filesystem = values(metric(&request, "system.filesystem.usage"), "system.filesystem.mountpoint");
+        assert_eq!(filesystem[3], ("/boot/efi".to_string(), AsInt(268435456)));
+
+        let io = metric(&request, "system.network.io");
+        assert_eq!(values(io, "network.io.direction")[0], ("receive".to_string(), AsInt(10737418240)));
+        let Some(metric::Data::Sum(sum)) = &io.data else { panic!("Not a sum") };
+        assert!(sum.is_monotonic);
+        assert_eq!(sum.aggregation_temporality, AggregationTemporality::Cumulative as i32);
+        assert_eq!(sum.data_points[0].start_time_unix_nano, 1699990000000000000);
+
+        assert_eq!(values(metric(&request, "system.cpu.load_average.1m"), ""), [(String::new(), AsDouble(1.25))]);
+        assert_eq!(values(metric(&request, "hw.temperature"), "hw.id")[0], ("coretemp Package id 0".to_string(), AsDouble(54.0)));
+        assert_eq!(values(metric(&request, "system.uptime"), ""), [(String::new(), AsInt(10000))]);
+    }
+
+    #[tokio::test]
+    async fn test_otlp_http() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let requests = received.clone();
+        let service = make_service_fn(move |_| {
+            let requests = requests.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let requests = requests.clone();
+                    async move {
+                        let path = req.uri().path().to_string();
+                        let content_type = req.headers()[CONTENT_TYPE].to_str().unwrap().to_string();
+                        let api_key = req.headers()["x-api-key"].to_str().unwrap().to_string();
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let request = ExportMetricsServiceRequest::decode(body).expect("Invalid protobuf body");
+                        requests.lock().unwrap().push((path, content_type, api_key, request));
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let otlp = Otlp {
+            endpoint: format!("http://{}/", addr),
+            protocol: OtlpProtocol::HttpProtobuf,
+            headers: vec![("x-api-key".to_string(), "secret".to_string())],
+        };
+        let mut exporter = Exporter::new(otlp).unwrap();
+        exporter.push(&snapshot()).await;
+        assert!(!exporter.failing);
+
+        let requests = received.lock().unwrap().clone();
+        assert_eq!(requests.len(), 1);
+        let (path, content_type, api_key, request) = &requests[0];
+        assert_eq!((&path[..], &content_type[..], &api_key[..]), ("/v1/metrics", "application/x-protobuf", "secret"));
+        assert_eq!(*request, super::request(&snapshot()));
+    }
+
+    // Stand-in for a collector receiving OTLP over gRPC
+    struct Collector(Arc<Mutex<Vec<(String, ExportMetricsServiceRequest)>>>);
+
+    #[tonic::async_trait]
+    impl MetricsService for Collector {
+        async fn export(&self, request: tonic::Request<ExportMetricsServiceRequest>) -> Result<tonic::Response<ExportMetricsServiceResponse>, tonic::Status> {
+            let api_key = request.metadata().get("x-api-key").unwrap().to_str().unwrap().to_string();
+            self.0.lock().unwrap().push((api_key, request.into_inner()));
+            Ok(tonic::Response::new(ExportMetricsServiceResponse::default()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_otlp_grpc() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let collector = Collector(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(MetricsServiceServer::new(collector))
+                .serve_with_incoming(incoming)
+                .await
+                .unwrap();
+        });
+
+        let otlp = Otlp {
+            endpoint: format!("http://{}", addr),
+            protocol: OtlpProtocol::Grpc,
+            headers: vec![("x-api-key".to_string(), "secret".to_string())],
+        };
+        let mut exporter = Exporter::new(otlp).unwrap();
+        exporter.push(&snapshot()).await;
+        let mut later = snapshot();
+        later.timestamp += 5;
+        exporter.push(&later).await;
+        assert!(!exporter.failing);
+
+        let requests = received.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|(api_key, _)| api_key == "secret"));
+        assert_eq!(requests[1].1, super::request(&later));
+    }
+}
@@ -0,0 +1,424 @@
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use sysinfo::{System, SystemExt};
+
+use crate::collector::{Collector, ProcessUsage};
+use crate::model::{CpuInfo, DiskInfo, LoadAverage, MemoryInfo, NetworkInfo, SystemInfo, Temperature, UserInfo};
+
+// File systems that hold no user data, as skipped by sysinfo
+const IGNORED_FILE_SYSTEMS: &[&str] = &[
+    "rootfs", "sysfs", "proc", "tmpfs", "devtmpfs", "cgroup", "cgroup2", "pstore", "squashfs", "rpc_pipefs",
+    "iso9660", "nfs4", "nfs",
+];
+
+/// Jiffies a CPU spent busy and in total, from `/proc/stat`.
+#[derive(Clone, Copy, Default)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+/// Collector reading the kernel's files below a root directory, such as `/host` when the
+/// host's `/proc`, `/sys` and `/etc` are mounted there in a container.
+///
+/// Namespaced files are read as seen by the host's init process, `/proc/1/mounts` rather
+/// than `/proc/mounts`, so that the container's own mounts and interfaces are left out.
+/// The server's own process is still read through sysinfo.
+pub struct ProcfsCollector {
+    root: PathBuf,
+    memory: MemoryInfo,
+    cpus: Vec<CpuInfo>,
+    cpu_times: Vec<CpuTimes>,
+    temperatures: Vec<Temperature>,
+    system_info: SystemInfo,
+    disks: Vec<DiskInfo>,
+    users: Vec<UserInfo>,
+    networks: Vec<NetworkInfo>,
+    // Bytes received and transmitted per interface at the previous refresh
+    network_totals: BTreeMap<String, (u64, u64)>,
+    load_average: LoadAverage,
+    boot_time: u64,
+    own_process: System,
+}
+
+impl ProcfsCollector {
+    pub fn new(root: impl Into<PathBuf>) -> ProcfsCollector {
+        ProcfsCollector {
+            root: root.into(),
+            memory: MemoryInfo::default(),
+            cpus: Vec::new(),
+            cpu_times: Vec::new(),
+            temperatures: Vec::new(),
+            system_info: SystemInfo::default(),
+            disks: Vec::new(),
+            users: Vec::new(),
+            networks: Vec::new(),
+            network_totals: BTreeMap::new(),
+            load_average: LoadAverage::default(),
+            boot_time: 0,
+            own_process: System::new(),
+        }
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    // Missing and unreadable files read as empty, like sysinfo reports unknown values
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.path(path)).unwrap_or_default()
+    }
+
+    fn refresh_memory(&mut self) {
+        // Values in kB
+        let meminfo = self.read("/proc/meminfo");
+        let fields: BTreeMap<&str, u64> = meminfo.lines().filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key, value.trim().trim_end_matches("kB").trim().parse::<u64>().ok()? * 1024))
+        }).collect();
+        let field = |key| fields.get(key).copied().unwrap_or_default();
+        self.memory = MemoryInfo {
+            available_memory: field("MemAvailable"),
+            free_memory: field("MemFree"),
+            free_swap: field("SwapFree"),
+            total_memory: field("MemTotal"),
+            total_swap: field("SwapTotal"),
+            used_memory: field("MemTotal").saturating_sub(field("MemAvailable")),
+            used_swap: field("SwapTotal").saturating_sub(field("SwapFree")),
+        };
+    }
+
+    fn refresh_cpus(&mut self) {
+        let stat = self.read("/proc/stat");
+        let times: Vec<CpuTimes> = stat.lines()
+            .filter(|line| line.starts_with("cpu") && !line.starts_with("cpu "))
+            .map(|line| cpu_times(line.split_whitespace().skip(1).filter_map(|value| value.parse().ok()).collect()))
+            .collect();
+        // `cpu MHz` of /proc/cpuinfo, for CPUs without cpufreq
+        let cpuinfo = self.read("/proc/cpuinfo");
+        let cpuinfo_mhz: Vec<u64> = cpuinfo.lines()
+            .filter_map(|line| line.strip_prefix("cpu MHz")?.split(':').nth(1)?.trim().parse::<f64>().ok())
+            .map(|mhz| mhz as u64)
+            .collect();
+
+        self.cpus = times.iter().enumerate().map(|(i, times)| {
+            let previous = self.cpu_times.get(i).copied().unwrap_or(*times);
+            let frequency = self.read(&format!("/sys/devices/system/cpu/cpu{}/cpufreq/scaling_cur_freq", i))
+                .trim()
+                .parse::<u64>()
+                .map(|khz| khz / 1000)
+                .ok()
+                .or_else(|| cpuinfo_mhz.get(i).copied())
+                .unwrap_or_default();
+            CpuInfo {
+                cpu_num: format!("cpu{}", i),
+                percent: cpu_usage(previous, *times),
+                frequency,
+            }
+        }).collect();
+        self.cpu_times = times;
+    }
+
+    fn refresh_temperatures(&mut self) {
+        let mut temperatures = Vec::new();
+        let mut hwmons: Vec<PathBuf> = fs::read_dir(self.path("/sys/class/hwmon"))
+            .map(|entries| entries.filter_map(|entry| Some(entry.ok()?.path())).collect())
+            .unwrap_or_default();
+        hwmons.sort();
+        for hwmon in hwmons {
+            let name = fs::read_to_string(hwmon.join("name")).unwrap_or_default();
+            let mut inputs: Vec<String> = fs::read_dir(&hwmon)
+                .map(|entries| entries.filter_map(|entry| entry.ok()?.file_name().into_string().ok()).collect())
+                .unwrap_or_default();
+            inputs.retain(|file| file.starts_with("temp") && file.ends_with("_input"));
+            inputs.sort();
+            for input in inputs {
+                let Some(millidegrees) = fs::read_to_string(hwmon.join(&input)).ok().and_then(|value| value.trim().parse::<f64>().ok()) else {
+                    continue;
+                };
+                let label = fs::read_to_string(hwmon.join(input.replace("_input", "_label"))).unwrap_or_default();
+                let label = [name.trim(), label.trim()].iter().filter(|part| !part.is_empty()).copied().collect::<Vec<_>>().join(" ");
+                if !label.is_empty() {
+                    temperatures.push(Temperature { name: label, temperature: millidegrees / 1000.0 });
+                }
+            }
+        }
+        self.temperatures = temperatures;
+    }
+
+    fn refresh_system(&mut self) {
+        let os_release = self.read("/etc/os-release");
+        let os_release: BTreeMap<&str, &str> = os_release.lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key, value.trim().trim_matches('"')))
+            .collect();
+        let version = os_release.get("VERSION_ID").copied().unwrap_or_default();
+        let name = os_release.get("NAME").copied().unwrap_or_default();
+        let or_unknown = |value: &str| if value.is_empty() { "N/A".to_string() } else { value.to_string() };
+
+        // /etc/hostname is the host's, /proc/sys/kernel/hostname the container's when namespaced
+        let host_name = Some(self.read("/etc/hostname"))
+            .filter(|host_name| !host_name.trim().is_empty())
+            .unwrap_or_else(|| self.read("/proc/sys/kernel/hostname"));
+
+        self.system_info = SystemInfo {
+            distribution_id: os_release.get("ID").map_or_else(|| std::env::consts::OS.to_string(), |id| id.to_string()),
+            host_name: or_unknown(host_name.trim()),
+            kernel_version: or_unknown(self.read("/proc/sys/kernel/osrelease").trim()),
+            long_os_version: format!("Linux {} {}", version, name),
+            os_version: or_unknown(version),
+        };
+
+        let loadavg = self.read("/proc/loadavg");
+        let mut loads = loadavg.split_whitespace().map(|value| value.parse::<f64>().unwrap_or_default());
+        self.load_average = LoadAverage {
+            one: loads.next().unwrap_or_default(),
+            five: loads.next().unwrap_or_default(),
+            fifteen: loads.next().unwrap_or_default(),
+        };
+
+        self.boot_time = self.read("/proc/stat").lines()
+            .find_map(|line| line.strip_prefix("btime ")?.trim().parse().ok())
+            .unwrap_or_default();
+    }
+
+    fn refresh_disks(&mut self) {
+        let mounts = self.read("/proc/1/mounts");
+        self.disks = mounts.lines().filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (device, mount_point, file_system) = (fields.next()?, unescape(fields.next()?), fields.next()?);
+            let ignored = IGNORED_FILE_SYSTEMS.contains(&file_system)
+                || mount_point.starts_with("/sys")
+                || mount_point.starts_with("/proc")
+                || (mount_point.starts_with("/run") && !mount_point.starts_with("/run/media"))
+                || device.starts_with("sunrpc");
+            if ignored {
+                return None;
+            }
+            let (total_space, available_space) = space(&self.path(&mount_point))?;
+            Some(DiskInfo {
+                device_name: device.to_string(),
+                file_system: file_system.to_string(),
+                mount_point,
+                total_space,
+                available_space,
+            })
+        }).collect();
+    }
+
+    fn refresh_users(&mut self) {
+        let groups = self.read("/etc/group");
+        // Name, id and members of every group
+        let groups: Vec<(&str, &str, Vec<&str>)> = groups.lines().filter_map(|line| {
+            let mut fields = line.split(':');
+            let (name, _, gid) = (fields.next()?, fields.next()?, fields.next()?);
+            let members = fields.next().unwrap_or_default().split(',').filter(|member| !member.is_empty()).collect();
+            Some((name, gid, members))
+        }).collect();
+
+        let passwd = self.read("/etc/passwd");
+        self.users = passwd.lines().filter_map(|line| {
+            let mut fields = line.split(':');
+            let (name, _, _, gid) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+            let group = groups.iter()
+                .filter(|(_, group_id, members)| *group_id == gid || members.contains(&name))
+                .map(|(group, _, _)| group.to_string())
+                .collect();
+            Some(UserInfo { name: name.to_string(), group })
+        }).collect();
+    }
+
+    fn refresh_networks(&mut self) {
+        let dev = self.read("/proc/1/net/dev");
+        let mut totals = BTreeMap::new();
+        // Two header lines, then `interface: rx_bytes ... tx_bytes ...`
+        for line in dev.lines().skip(2) {
+            let Some((interface, counters)) = line.split_once(':') else { continue };
+            let counters: Vec<u64> = counters.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+            if let (Some(received), Some(transmitted)) = (counters.first(), counters.get(8)) {
+                totals.insert(interface.trim().to_string(), (*received, *transmitted));
+            }
+        }
+
+        // Traffic since the previous refresh, as reported by sysinfo
+        self.networks = totals.iter().map(|(interface, (received, transmitted))| {
+            let (previous_received, previous_transmitted) = self.network_totals.get(interface).copied().unwrap_or((*received, *transmitted));
+            NetworkInfo {
+                interface_name: interface.clone(),
+                data_received: received.saturating_sub(previous_received),
+                data_transmitted: transmitted.saturating_sub(previous_transmitted),
+                total_received: *received,
+                total_transmitted: *transmitted,
+            }
+        }).collect();
+        self.network_totals = totals;
+    }
+}
+
+fn cpu_times(values: Vec<u64>) -> CpuTimes {
+    // user nice system idle iowait irq softirq steal, guest time being included in user
+    let value = |i: usize| values.get(i).copied().unwrap_or_default();
+    let idle = value(3) + value(4);
+    let busy = value(0) + value(1) + value(2) + value(5) + value(6) + value(7);
+    CpuTimes { busy, total: busy + idle }
+}
+
+fn cpu_usage(previous: CpuTimes, current: CpuTimes) -> f64 {
+    let total = current.total.saturating_sub(previous.total);
+    if total == 0 {
+        return 0.0;
+    }
+    current.busy.saturating_sub(previous.busy) as f64 / total as f64 * 100.0
+}
+
+// Octal escapes of /proc/*/mounts
+fn unescape(path: &str) -> String {
+    path.replace("\\040", " ").replace("\\011", "\t").replace("\\012", "\n").replace("\\134", "\\")
+}
+
+// Total and available bytes of the file system mounted at `path`
+#[allow(clippy::unnecessary_cast)] // The statvfs fields are narrower on 32-bit targets
+fn space(path: &Path) -> Option<(u64, u64)> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: statvfs only writes into the provided struct
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let stat = unsafe { stat.assume_init() };
+    let fragment = stat.f_frsize as u64;
+    Some((stat.f_blocks as u64 * fragment, stat.f_bavail as u64 * fragment))
+}
+
+impl Collector for ProcfsCollector {
+    fn refresh(&mut self, subsystem: &str) {
+        match subsystem {
+            "memory" => self.refresh_memory(),
+            "cpus" => self.refresh_cpus(),
+            "temperatures" => self.refresh_temperatures(),
+            "disks" => self.refresh_disks(),
+            "networks" => self.refresh_networks(),
+            "users" => self.refresh_users(),
+            "system" => self.refresh_system(),
+            "self" => self.own_process.refresh("self"),
+            _ => unreachable!("unknown subsystem {}", subsystem),
+        }
+    }
+
+    fn host_name(&self) -> Option<String> {
+        Some(self.system_info.host_name.clone()).filter(|host_name| !host_name.is_empty())
+    }
+
+    fn memory(&self) -> MemoryInfo {
+        self.memory.clone()
+    }
+
+    fn cpus(&self) -> Vec<CpuInfo> {
+        self.cpus.clone()
+    }
+
+    fn temperatures(&self) -> Vec<Temperature> {
+        self.temperatures.clone()
+    }
+
+    fn system_info(&self) -> SystemInfo {
+        self.system_info.clone()
+    }
+
+    fn disks(&self) -> Vec<DiskInfo> {
+        self.disks.clone()
+    }
+
+    fn users(&self) -> Vec<UserInfo> {
+        self.users.clone()
+    }
+
+    fn networks(&self) -> Vec<NetworkInfo> {
+        self.networks.clone()
+    }
+
+    fn load_average(&self) -> LoadAverage {
+        self.load_average.clone()
+    }
+
+    fn boot_time(&self) -> u64 {
+        self.boot_time
+    }
+
+    fn own_process(&self) -> Option<ProcessUsage> {
+        self.own_process.own_process()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SUBSYSTEMS;
+
+    fn fixture() -> ProcfsCollector {
+        let mut collector = ProcfsCollector::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src/fixtures/host-root"));
+        for subsystem in SUBSYSTEMS {
+            collector.refresh(subsystem);
+        }
+        collector
+    }
+
+    #[test]
+    fn test_procfs_collector() {
+        let collector = fixture();
+        assert_eq!(collector.memory(), MemoryInfo {
+            available_memory: 12_884_901_888,
+            free_memory: 4_294_967_296,
+            free_swap: 1_073_741_824,
+            total_memory: 17_179_869_184,
+            total_swap: 2_147_483_648,
+            used_memory: 4_294_967_296,
+            used_swap: 1_073_741_824,
+        });
+        assert_eq!(collector.cpus(), vec![
+            CpuInfo { cpu_num: "cpu0".to_string(), frequency: 2400, percent: 0.0 },
+            CpuInfo { cpu_num: "cpu1".to_string(), frequency: 3600, percent: 0.0 },
+        ]);
+        assert_eq!(collector.temperatures(), vec![
+            Temperature { name: "coretemp Package id 0".to_string(), temperature: 54.0 },
+            Temperature { name: "nvme Composite".to_string(), temperature: 38.5 },
+        ]);
+        assert_eq!(collector.system_info(), SystemInfo {
+            distribution_id: "debian".to_string(),
+            host_name: "fixture-host".to_string(),
+            kernel_version: "6.1.0-13-amd64".to_string(),
+            long_os_version: "Linux 12 Debian GNU/Linux".to_string(),
+            os_version: "12".to_string(),
+        });
+        assert_eq!(collector.load_average(), LoadAverage { one: 1.25, five: 0.75, fifteen: 0.5 });
+        assert_eq!(collector.boot_time(), 1_699_990_000);
+        assert_eq!(collector.users(), vec![
+            UserInfo { name: "root".to_string(), group: vec!["root".to_string()] },
+            UserInfo { name: "alice".to_string(), group: vec!["wheel".to_string(), "users".to_string()] },
+        ]);
+        // No traffic yet, the counters being read once
+        assert_eq!(collector.networks().iter().map(|network| network.interface_name.as_str()).collect::<Vec<_>>(), ["eth0", "lo"]);
+        assert!(collector.networks().iter().all(|network| network.data_received == 0 && network.data_transmitted == 0));
+        assert_eq!((collector.networks()[0].total_received, collector.networks()[0].total_transmitted), (1048576, 524288));
+
+        // Pseudo file systems are skipped, the space is that of the directory the fixture is in
+        let disks = collector.disks();
+        assert_eq!(disks.iter().map(|disk| (disk.device_name.as_str(), disk.file_system.as_str(), disk.mount_point.as_str())).collect::<Vec<_>>(), [
+            ("/dev/nvme0n1p2", "ext4", "/"),
+            ("/dev/nvme0n1p1", "vfat", "/boot/efi"),
+        ]);
+        assert!(disks.iter().all(|disk| disk.available_space <= disk.total_space));
+    }
+
+    #[test]
+    fn test_cpu_usage() {
+        let previous = cpu_times(vec![100, 0, 50, 800, 50, 0, 0, 0]);
+        let current = cpu_times(vec![160, 0, 90, 880, 70, 0, 0, 0]);
+        assert_eq!(cpu_usage(previous, current), 50.0);
+        assert_eq!(cpu_usage(current, current), 0.0);
+    }
+}
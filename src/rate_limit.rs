@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hyper::{Body, Request, Response};
+use hyper::header::{HeaderValue, RETRY_AFTER};
+use hyper::http::StatusCode;
+
+use crate::config::RateLimit;
+use crate::encoding::error_response;
+
+// Number of clients tracked at most. New clients are limited while every bucket is in use
+const MAX_CLIENTS: usize = 10_000;
+
+// Shortest time between two sweeps of a full map for buckets that have refilled
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Address of the peer a request came from, attached to requests by the server. Embedders
+/// serving requests through their own listener can attach it to keep per-IP rate limits;
+/// requests without one all share a single bucket.
+#[derive(Clone, Copy)]
+pub struct ClientAddr(pub SocketAddr);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Buckets {
+    clients: HashMap<String, Bucket>,
+    // When a full map may next be swept, so that a flood of new clients costs no sweep per request
+    next_sweep: Instant,
+}
+
+/// Token buckets of the clients seen so far.
+pub(crate) struct RateLimiter {
+    limit: Option<RateLimit>,
+    buckets: Mutex<Buckets>,
+}
+
+// Clients are told apart by their IP address, not by bearer token as first planned. The server
+// checks no tokens, so a client sending a new one with every request would never run out.
+fn client(req: &Request<Body>) -> String {
+    match req.extensions().get::<ClientAddr>() {
+        Some(ClientAddr(addr)) => addr.ip().to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: Option<RateLimit>) -> RateLimiter {
+        RateLimiter { limit, buckets: Mutex::new(Buckets { clients: HashMap::new(), next_sweep: Instant::now() }) }
+    }
+
+    // Takes a token from the bucket of `client`, or tells how long until one is available
+    fn acquire(&self, client: String, now: Instant) -> Result<(), Duration> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let rate = limit.per_second as f64;
+        let burst = limit.burst as f64;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets { clients, next_sweep } = &mut *buckets;
+        // Seconds until `bucket` has refilled completely, when it is no different from a new one
+        let refilled_in = |bucket: &Bucket| (burst - bucket.tokens) / rate - now.duration_since(bucket.updated).as_secs_f64();
+        if clients.len() >= MAX_CLIENTS && !clients.contains_key(&client) {
+            if now >= *next_sweep {
+                clients.retain(|_, bucket| refilled_in(bucket) > 0.0);
+                let first_refilled = clients.values().map(refilled_in).fold(f64::INFINITY, f64::min);
+                *next_sweep = now + Duration::from_secs_f64(first_refilled.min(3600.0)).max(SWEEP_INTERVAL);
+            }
+            if clients.len() >= MAX_CLIENTS {
+                return Err(next_sweep.duration_since(now));
+            }
+        }
+        let bucket = clients.entry(client).or_insert(Bucket { tokens: burst, updated: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+
+    /// Answers `req` with `429 Too Many Requests` when its client has run out of tokens.
+    pub(crate) fn check(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        let retry_after = self.acquire(client(req), Instant::now()).err()?;
+        // Retry-After is in whole seconds, rounded up so that the retry succeeds
+        let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        let mut response = error_response(StatusCode::TOO_MANY_REQUESTS, format!("Rate limit exceeded, retry in {} seconds", secs));
+        response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(secs));
+        Some(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sysinfo::{System, SystemExt};
+    use crate::collector::fixture;
+    use crate::config::Config;
+    use crate::model::ApiError;
+    use crate::AppState;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(Some(RateLimit { per_second: 2, burst: 3 }));
+        let start = Instant::now();
+
+        // A full bucket allows a burst, then a token every half second
+        for _ in 0..3 {
+            assert!(limiter.acquire("a".to_string(), start).is_ok());
+        }
+        assert_eq!(limiter.acquire("a".to_string(), start), Err(Duration::from_millis(500)));
+        assert!(limiter.acquire("b".to_string(), start).is_ok());
+        assert!(limiter.acquire("a".to_string(), start + Duration::from_millis(500)).is_ok());
+        assert!(limiter.acquire("a".to_string(), start + Duration::from_millis(600)).is_err());
+
+        // and never more than the burst after a pause
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.acquire("a".to_string(), later).is_ok());
+        }
+        assert!(limiter.acquire("a".to_string(), later).is_err());
+
+        let unlimited = RateLimiter::new(None);
+        for _ in 0..100 {
+            assert!(unlimited.acquire("a".to_string(), start).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_client_cap() {
+        let limiter = RateLimiter::new(Some(RateLimit { per_second: 1, burst: 1 }));
+        let start = Instant::now();
+        for client in 0..MAX_CLIENTS {
+            assert!(limiter.acquire(client.to_string(), start).is_ok());
+        }
+
+        // New clients wait for a bucket to refill while every one is in use
+        assert_eq!(limiter.acquire("new".to_string(), start), Err(Duration::from_secs(1)));
+        assert_eq!(limiter.acquire("new".to_string(), start + Duration::from_millis(500)), Err(Duration::from_millis(500)));
+        assert!(limiter.acquire("new".to_string(), start + Duration::from_secs(1)).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().clients.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let addr = rate_limit_test_server();
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/memory", addr);
+        for _ in 0..2 {
+            let response = client.get(&url).send().await.expect("Failed to send request");
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+        }
+        let response = client.get(&url).send().await.expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "1");
+        let error: ApiError = response.json().await.expect("Failed to parse response as an error");
+        assert_eq!(error.error, "too_many_requests");
+
+        // A new bearer token does not make a new client, and probes are never limited
+        let response = client.get(&url).bearer_auth("rotated").send().await.expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        for probe in ["/healthz", "/v1/healthz", "/readyz", "/v1/readyz"] {
+            let response = client.get(format!("http://{}{}", addr, probe)).send().await.expect("Failed to send request");
+            assert_ne!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS, "{} is rate limited", probe);
+        }
+
+        // Unknown paths are answered with 404 before the limit is checked
+        let response = client.get(format!("http://{}/no/such/path", addr)).send().await.expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    fn rate_limit_test_server() -> SocketAddr {
+        let config = Config {
+            rate_limit: Some(RateLimit { per_second: 1, burst: 2 }),
+            ..Config::default()
+        };
+        fixture::serve(AppState::new(Box::new(System::new_all()), config))
+    }
+}
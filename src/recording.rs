@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::collector::{Collector, FixtureCollector, ProcessUsage};
+use crate::model::{CpuInfo, DiskInfo, LoadAverage, MemoryInfo, NetworkInfo, Snapshot, SystemInfo, Temperature, UserInfo};
+
+/// Appends the sampler's snapshots to a file, one JSON document per line.
+pub(crate) struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Starts a new recording, replacing the file at `path`.
+    pub(crate) fn create(path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder { file: BufWriter::new(File::create(path)?) })
+    }
+
+    pub(crate) fn record(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        serde_json::to_writer(&mut self.file, snapshot)?;
+        self.file.write_all(b"\n")?;
+        // Every line is complete on disk should the server be killed
+        self.file.flush()
+    }
+}
+
+/// Snapshots recorded with `--record`, and how fast to replay them.
+#[derive(Clone)]
+pub struct Replay {
+    snapshots: Arc<Vec<Snapshot>>,
+    /// 2 replays twice as fast as recorded
+    speed: f64,
+}
+
+impl Replay {
+    /// Reads the recording at `path`.
+    pub fn open(path: &Path, speed: f64) -> io::Result<Replay> {
+        let mut snapshots = Vec::new();
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let snapshot = serde_json::from_str(&line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e)))?;
+            snapshots.push(snapshot);
+        }
+        Replay::new(snapshots, speed)
+    }
+
+    pub fn new(snapshots: Vec<Snapshot>, speed: f64) -> io::Result<Replay> {
+        if snapshots.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the recording has no snapshots"));
+        }
+        Ok(Replay { snapshots: Arc::new(snapshots), speed })
+    }
+
+    // Length of one pass through the recording, the last snapshot lasting as long as the one before
+    fn period(&self) -> u64 {
+        let first = self.snapshots[0].timestamp;
+        let last = self.snapshots[self.snapshots.len() - 1].timestamp;
+        let last_interval = match self.snapshots.len() {
+            1 => 1,
+            n => last.saturating_sub(self.snapshots[n - 2].timestamp).max(1),
+        };
+        last.saturating_sub(first) + last_interval
+    }
+
+    /// Index of the snapshot due `elapsed` after the replay started, starting over at the end.
+    fn position(&self, elapsed: Duration) -> usize {
+        let recorded = (elapsed.as_secs_f64() * self.speed) as u64 % self.period();
+        let first = self.snapshots[0].timestamp;
+        self.snapshots.iter().rposition(|snapshot| snapshot.timestamp.saturating_sub(first) <= recorded).unwrap_or_default()
+    }
+}
+
+/// Collector serving recorded snapshots at their recorded pace, sped up by the replay's speed.
+/// Subsystems move on to the snapshot that is due when they are refreshed.
+pub struct ReplayCollector {
+    replay: Replay,
+    started: Instant,
+    position: usize,
+    current: FixtureCollector,
+}
+
+impl ReplayCollector {
+    pub fn new(replay: Replay) -> ReplayCollector {
+        let current = FixtureCollector::new(replay.snapshots[0].clone());
+        ReplayCollector { replay, started: Instant::now(), position: 0, current }
+    }
+}
+
+impl Collector for ReplayCollector {
+    fn refresh(&mut self, _subsystem: &str) {
+        let position = self.replay.position(self.started.elapsed());
+        if position != self.position {
+            self.position = position;
+            self.current = FixtureCollector::new(self.replay.snapshots[position].clone());
+        }
+    }
+
+    fn host_name(&self) -> Option<String> {
+        self.current.host_name()
+    }
+
+    fn memory(&self) -> MemoryInfo {
+        self.current.memory()
+    }
+
+    fn cpus(&self) -> Vec<CpuInfo> {
+        self.current.cpus()
+    }
+
+    fn temperatures(&self) -> Vec<Temperature> {
+        self.current.temperatures()
+    }
+
+    fn system_info(&self) -> SystemInfo {
+        self.current.system_info()
+    }
+
+    fn disks(&self) -> Vec<DiskInfo> {
+        self.current.disks()
+    }
+
+    fn users(&self) -> Vec<UserInfo> {
+        self.current.users()
+    }
+
+    fn networks(&self) -> Vec<NetworkInfo> {
+        self.current.networks()
+    }
+
+    fn load_average(&self) -> LoadAverage {
+        self.current.load_average()
+    }
+
+    fn boot_time(&self) -> u64 {
+        self.current.boot_time()
+    }
+
+    // The replaying server's own process is not part of the recording
+    fn own_process(&self) -> Option<ProcessUsage> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::fixture;
+
+    fn snapshot(timestamp: u64, used_memory: u64) -> Snapshot {
+        let mut snapshot: Snapshot = serde_json::from_str(fixture::HOST).unwrap();
+        snapshot.timestamp = timestamp;
+        snapshot.memory.as_mut().unwrap().used_memory = used_memory;
+        snapshot
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!("sysinfo-recording-{}.ndjson", std::process::id()));
+        let mut recorder = Recorder::create(&path).expect("Failed to create recording");
+        for (timestamp, used_memory) in [(1000, 1), (1005, 2), (1010, 3)] {
+            recorder.record(&snapshot(timestamp, used_memory)).expect("Failed to record");
+        }
+        drop(recorder);
+
+        let replay = Replay::open(&path, 1.0).expect("Failed to read recording");
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(*replay.snapshots, [snapshot(1000, 1), snapshot(1005, 2), snapshot(1010, 3)]);
+
+        // Each snapshot is served for as long as it lasted, starting over after the last one
+        assert_eq!(replay.period(), 15);
+        let position = |secs| replay.position(Duration::from_secs(secs));
+        assert_eq!([0, 4, 5, 9, 10, 14, 15, 21].map(position), [0, 0, 1, 1, 2, 2, 0, 1]);
+
+        let fast = Replay { speed: 5.0, ..replay };
+        assert_eq!(fast.position(Duration::from_secs(1)), 1);
+        assert_eq!(fast.position(Duration::from_secs(2)), 2);
+    }
+
+    #[test]
+    fn test_replay_collector() {
+        let replay = Replay::new(vec![snapshot(1000, 1), snapshot(1005, 2)], 1.0).unwrap();
+        let mut collector = ReplayCollector::new(replay);
+        collector.refresh("memory");
+        assert_eq!(collector.memory().used_memory, 1);
+        assert_eq!(collector.host_name().as_deref(), Some("fixture-host"));
+
+        assert!(Replay::new(Vec::new(), 1.0).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::time::SystemTime;
+
+use crate::{snapshot, AppState};
+use crate::collector::Collector;
+use crate::config::MinRefreshInterval;
+use crate::recording::Recorder;
+use crate::self_stats::time_refresh;
+
+/// Subsystems refreshed by the background sampler, named as in `/self`.
+pub(crate) const SUBSYSTEMS: [&str; 7] = ["memory", "cpus", "temperatures", "disks", "networks", "users", "system"];
+
+/// The collector together with the time each of its subsystems was last refreshed.
+pub(crate) struct Sampled {
+    collector: Box<dyn Collector>,
+    refreshed: BTreeMap<&'static str, SystemTime>,
+    min_refresh_interval: MinRefreshInterval,
+}
+
+impl Sampled {
+    pub(crate) fn new(collector: Box<dyn Collector>, min_refresh_interval: MinRefreshInterval) -> Sampled {
+        Sampled { collector, refreshed: BTreeMap::new(), min_refresh_interval }
+    }
+
+    /// Refreshes `subsystem` on behalf of a request, unless it was sampled less than
+    /// its minimum refresh interval ago, in which case the cached sample is kept.
+    pub(crate) fn refresh(&mut self, subsystem: &'static str) {
+        let age = self.refreshed(subsystem).map(|sampled| SystemTime::now().duration_since(sampled).unwrap_or_default());
+        if age.is_none_or(|age| age >= self.min_refresh_interval.of(subsystem)) {
+            self.resample(subsystem);
+        }
+    }
+
+    /// Refreshes `subsystem` on the collector, recording its duration and when it was sampled.
+    pub(crate) fn resample(&mut self, subsystem: &'static str) {
+        time_refresh(subsystem, || self.collector.refresh(subsystem));
+        self.refreshed.insert(subsystem, SystemTime::now());
+    }
+
+    /// When `subsystem` was last refreshed, if ever.
+    pub(crate) fn refreshed(&self, subsystem: &str) -> Option<SystemTime> {
+        self.refreshed.get(subsystem).copied()
+    }
+}
+
+impl Deref for Sampled {
+    type Target = dyn Collector;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.collector
+    }
+}
+
+/// Periodically refreshes every subsystem, evaluating the alert rules against the snapshots and
+/// notifying the webhooks of the alerts that change, keeping them for `/diff`, and recording them
+/// when asked to.
+pub(crate) async fn run(state: AppState) {
+    let mut recorder = state.config.record.as_ref().and_then(|path| {
+        Recorder::create(path).map_err(|e| eprintln!("Cannot record to {}: {}", path.display(), e)).ok()
+    });
+    let mut interval = tokio::time::interval(state.config.sample_interval);
+    loop {
+        interval.tick().await;
+        for subsystem in SUBSYSTEMS {
+            // Lock per subsystem so that requests are not held up by a full sweep
+            state.system.lock().unwrap().resample(subsystem);
+        }
+        let snapshot = snapshot::read(&state.system.lock().unwrap(), &snapshot::SUBSYSTEMS);
+        if let Some(active) = &mut recorder {
+            if let Err(e) = active.record(&snapshot) {
+                eprintln!("Recording stopped: {}", e);
+                recorder = None;
+            }
+        }
+        let alerts = {
+            let mut alerting = state.alerts.lock().unwrap();
+            alerting.evaluate(&snapshot);
+            alerting.alerts()
+        };
+        state.webhooks.notify(&snapshot.hostname, &alerts, snapshot.timestamp);
+        state.history.lock().unwrap().push(snapshot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use std::time::Duration;
+    use super::*;
+    use crate::collector::FixtureCollector;
+    use crate::config::Config;
+    use crate::model::Snapshot;
+
+    #[test]
+    fn test_min_refresh_interval() {
+        let mut min_refresh_interval = Config::default().min_refresh_interval;
+        min_refresh_interval.subsystems.insert("disks".to_string(), Duration::ZERO);
+        let collector = FixtureCollector::new(Snapshot::default());
+        let mut sampled = Sampled::new(Box::new(collector), min_refresh_interval);
+        assert!(sampled.refreshed("memory").is_none());
+
+        // Requests within the interval are served the cached sample
+        sampled.refresh("memory");
+        let first = sampled.refreshed("memory").expect("memory was not sampled");
+        sleep(Duration::from_millis(10));
+        sampled.refresh("memory");
+        assert_eq!(sampled.refreshed("memory"), Some(first));
+
+        // unlike the sampler and subsystems without an interval
+        sampled.resample("memory");
+        assert!(sampled.refreshed("memory") > Some(first));
+        sampled.refresh("disks");
+        let first = sampled.refreshed("disks");
+        sleep(Duration::from_millis(10));
+        sampled.refresh("disks");
+        assert!(sampled.refreshed("disks") > first);
+    }
+}
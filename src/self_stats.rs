@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::{Body, Response};
+use hyper::http::StatusCode;
+
+use crate::encoding::{respond, Format};
+use crate::model::{self, Bucket, CpuTime, SelfInfo};
+use crate::sampler::Sampled;
+
+// Upper bounds (in seconds) of the latency histogram buckets
+const BUCKETS: [f64; 12] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+static STATS: LazyLock<Mutex<SelfStats>> = LazyLock::new(|| Mutex::new(SelfStats::default()));
+
+#[derive(Default)]
+struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+    last: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        for (count, bound) in self.counts.iter_mut().zip(BUCKETS) {
+            if secs <= bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += secs;
+        self.last = secs;
+    }
+
+    fn to_model(&self) -> model::Histogram {
+        let buckets = self.counts.iter().zip(BUCKETS).map(|(count, bound)| {
+            Bucket { le: bound, count: *count }
+        }).collect();
+        model::Histogram {
+            count: self.count,
+            sum: self.sum,
+            last: self.last,
+            buckets,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SelfStats {
+    requests: BTreeMap<String, Histogram>,
+    refreshes: BTreeMap<&'static str, Histogram>,
+}
+
+/// Records a served request and its latency under the given route label.
+pub(crate) fn record_request(route: &str, elapsed: Duration) {
+    let mut stats = STATS.lock().unwrap();
+    stats.requests.entry(route.to_string()).or_default().observe(elapsed);
+}
+
+/// Runs `refresh` and records how long it took for `subsystem`.
+pub(crate) fn time_refresh<T>(subsystem: &'static str, refresh: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = refresh();
+    let elapsed = started.elapsed();
+    STATS.lock().unwrap().refreshes.entry(subsystem).or_default().observe(elapsed);
+    result
+}
+
+#[cfg(unix)]
+fn cpu_time() -> Option<CpuTime> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage only writes into the provided struct
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let usage = unsafe { usage.assume_init() };
+    let seconds = |tv: libc::timeval| tv.tv_sec as f64 + tv.tv_usec as f64 / 1_000_000.0;
+    Some(CpuTime {
+        user: seconds(usage.ru_utime),
+        system: seconds(usage.ru_stime),
+    })
+}
+
+#[cfg(not(unix))]
+fn cpu_time() -> Option<CpuTime> {
+    None
+}
+
+// Number of entries in a /proc/self directory, only available on Linux
+fn count_proc_entries(dir: &str) -> Option<u64> {
+    if cfg!(target_os = "linux") {
+        std::fs::read_dir(dir).ok().map(|entries| entries.count() as u64)
+    } else {
+        None
+    }
+}
+
+pub(crate) async fn handle_self(system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
+    let mut system = system.lock().unwrap();
+    system.refresh("self");
+    let process = system.own_process();
+
+    let stats = STATS.lock().unwrap();
+    let self_info = SelfInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        pid: std::process::id(),
+        uptime: process.as_ref().map(|p| p.run_time),
+        memory_rss: process.as_ref().map(|p| p.memory),
+        cpu_usage: process.as_ref().map(|p| p.cpu_usage),
+        cpu_time: cpu_time(),
+        open_fds: count_proc_entries("/proc/self/fd"),
+        threads: count_proc_entries("/proc/self/task"),
+        requests: stats.requests.iter().map(|(route, h)| (route.clone(), h.to_model())).collect(),
+        refreshes: stats.refreshes.iter().map(|(subsystem, h)| (subsystem.to_string(), h.to_model())).collect(),
+    };
+
+    Ok(respond(format, StatusCode::OK, &self_info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sysinfo::{System, SystemExt};
+    use std::net::SocketAddr;
+    use crate::collector::fixture;
+    use crate::config::Config;
+    use crate::AppState;
+
+    #[tokio::test]
+    async fn test_self_endpoint() {
+        let addr = self_test_server();
+
+        // Serve one regular request so that it shows up in the statistics
+        reqwest::get(&format!("http://{}/memory", addr))
+            .await
+            .expect("Failed to send request");
+
+        let response: serde_json::Value = reqwest::get(&format!("http://{}/self", addr))
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse response as JSON");
+
+        assert!(response.is_object());
+        assert_eq!(response["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(response["pid"].as_u64(), Some(std::process::id() as u64));
+        assert!(response["memory_rss"].as_u64().expect("`memory_rss` is not an integer") > 0);
+        assert!(response["uptime"].is_number());
+
+        #[cfg(target_os = "linux")]
+        {
+            assert!(response["open_fds"].as_u64().expect("`open_fds` is not an integer") > 0);
+            assert!(response["threads"].as_u64().expect("`threads` is not an integer") > 0);
+        }
+
+        let memory = &response["requests"]["/memory"];
+        assert!(memory["count"].as_u64().expect("`count` is not an integer") >= 1);
+        let buckets = memory["buckets"].as_array().expect("`buckets` is not an array");
+        assert_eq!(buckets.len(), BUCKETS.len());
+
+        // Preflights of unknown paths and of the files of the docs do not add labels of their own
+        let client = reqwest::Client::new();
+        for path in ["/no/such/path", "/docs/no-such-file"] {
+            client.request(reqwest::Method::OPTIONS, format!("http://{}{}", addr, path))
+                .send()
+                .await
+                .expect("Failed to send request");
+        }
+        let requests = &STATS.lock().unwrap().requests;
+        assert!(!requests.contains_key("/no/such/path") && !requests.contains_key("/docs/no-such-file"));
+        assert!(requests.contains_key("not_found") && requests.contains_key("/docs"));
+
+        let refresh = &response["refreshes"]["memory"];
+        assert!(refresh["count"].as_u64().expect("`count` is not an integer") >= 1);
+        assert!(refresh["sum"].as_f64().expect("`sum` is not a float") >= 0.0);
+    }
+
+    fn self_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
+    }
+}
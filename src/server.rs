@@ -0,0 +1,360 @@
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::future::{ready, Future, Ready};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::{Body, Request, Response};
+use hyper::header::LOCATION;
+use hyper::http::StatusCode;
+use hyper::http::uri::{PathAndQuery, Uri};
+use hyper::server::conn::AddrStream;
+use hyper::service::Service;
+use sysinfo::{System, SystemExt};
+
+use crate::collector::Collector;
+use crate::config::Config;
+use crate::encoding::error_response;
+use crate::rate_limit::ClientAddr;
+use crate::recording::ReplayCollector;
+use crate::{handle_request, not_found, sampler, AppState};
+
+/// Groups of routes that can be enabled on a [`SysinfoServer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Endpoint {
+    /// `/memory`, also below `/v1` and `/v2` like the other resources
+    Memory,
+    Cpus,
+    Temperatures,
+    /// `/sysinfo`
+    Sysinfo,
+    Disks,
+    Users,
+    Networks,
+    LoadAverage,
+    BootTime,
+    Snapshot,
+    /// `/diff` over the background sampler's snapshots
+    Diff,
+    /// `/alerts` of the configured rules
+    Alerts,
+    /// `/self`, the server's own statistics
+    SelfStats,
+    /// `/healthz` and `/readyz`
+    Health,
+    /// `/openapi.json`, and `/docs` with the `docs-ui` feature
+    OpenApi,
+    /// The HTML dashboard at `/`
+    Dashboard,
+}
+
+impl Endpoint {
+    pub const ALL: [Endpoint; 16] = [
+        Endpoint::Memory,
+        Endpoint::Cpus,
+        Endpoint::Temperatures,
+        Endpoint::Sysinfo,
+        Endpoint::Disks,
+        Endpoint::Users,
+        Endpoint::Networks,
+        Endpoint::LoadAverage,
+        Endpoint::BootTime,
+        Endpoint::Snapshot,
+        Endpoint::Diff,
+        Endpoint::Alerts,
+        Endpoint::SelfStats,
+        Endpoint::Health,
+        Endpoint::OpenApi,
+        Endpoint::Dashboard,
+    ];
+
+    /// The endpoint serving `path`, without its `/v1` or `/v2` prefix.
+    pub(crate) fn of_path(path: &str) -> Option<Endpoint> {
+        let endpoint = match path {
+            "/memory" => Endpoint::Memory,
+            "/cpus" => Endpoint::Cpus,
+            "/temperatures" => Endpoint::Temperatures,
+            "/sysinfo" => Endpoint::Sysinfo,
+            "/disks" => Endpoint::Disks,
+            "/users" => Endpoint::Users,
+            "/networks" => Endpoint::Networks,
+            "/load_average" => Endpoint::LoadAverage,
+            "/boot_time" => Endpoint::BootTime,
+            "/snapshot" => Endpoint::Snapshot,
+            "/diff" => Endpoint::Diff,
+            "/alerts" => Endpoint::Alerts,
+            "/self" => Endpoint::SelfStats,
+            "/healthz" | "/readyz" => Endpoint::Health,
+            "/openapi.json" => Endpoint::OpenApi,
+            path if path == "/docs" || path.starts_with("/docs/") => Endpoint::OpenApi,
+            "/" => Endpoint::Dashboard,
+            _ => return None,
+        };
+        Some(endpoint)
+    }
+}
+
+/// Builds a [`SysinfoServer`].
+pub struct SysinfoServerBuilder {
+    config: Config,
+    collector: Option<Box<dyn Collector>>,
+    endpoints: BTreeSet<Endpoint>,
+    prefix: String,
+    sampler: bool,
+}
+
+impl SysinfoServerBuilder {
+    /// Settings of the server, [`Config::default`] unless set. `addr` is left to the caller.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Where the samples come from. Unless set, the [`Config::replay`] recording or the files
+    /// below [`Config::host_root`] when either is set, or else the host through sysinfo.
+    pub fn collector(mut self, collector: impl Collector + 'static) -> Self {
+        self.collector = Some(Box::new(collector));
+        self
+    }
+
+    /// Serves `endpoint`. Every endpoint is served when none is enabled.
+    pub fn enable(mut self, endpoint: Endpoint) -> Self {
+        self.endpoints.insert(endpoint);
+        self
+    }
+
+    /// Serves the routes below `prefix`, such as `/sysinfo`, answering 404 outside of it.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        if !self.prefix.is_empty() && !self.prefix.starts_with('/') {
+            self.prefix.insert(0, '/');
+        }
+        self
+    }
+
+    /// Whether to refresh every subsystem in the background, on by default. Without the
+    /// sampler subsystems are only refreshed by requests, and `/readyz` reports the ones
+    /// nobody asked for as stale.
+    pub fn sampler(mut self, sampler: bool) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Builds the server, starting its sampler, the delivery of its webhook notifications and
+    /// its exporters.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the sampler is on and this is not called from a Tokio runtime.
+    pub fn build(self) -> SysinfoServer {
+        let collector = self.collector.unwrap_or_else(|| default_collector(&self.config));
+        let mut state = AppState::new(collector, self.config);
+        if !self.endpoints.is_empty() {
+            state.endpoints = Arc::new(self.endpoints);
+        }
+        if self.sampler {
+            tokio::spawn(sampler::run(state.clone()));
+            if !state.webhooks.is_empty() {
+                tokio::spawn(state.webhooks.clone().run());
+            }
+            if let Some(influx) = &state.config.influx {
+                tokio::spawn(crate::influx::run(state.clone(), influx.clone()));
+            }
+            if let Some(graphite) = &state.config.graphite {
+                tokio::spawn(crate::graphite::run(state.clone(), graphite.clone()));
+            }
+            if let Some(statsd) = &state.config.statsd {
+                tokio::spawn(crate::statsd::run(state.clone(), statsd.clone()));
+            }
+            if let Some(otlp) = &state.config.otlp {
+                tokio::spawn(crate::otlp::run(state.clone(), otlp.clone()));
+            }
+            if let Some(mqtt) = &state.config.mqtt {
+                tokio::spawn(crate::mqtt::run(state.clone(), mqtt.clone()));
+            }
+        }
+        SysinfoServer {
+            state,
+            prefix: self.prefix.into(),
+            client_addr: None,
+        }
+    }
+}
+
+fn default_collector(config: &Config) -> Box<dyn Collector> {
+    if let Some(replay) = &config.replay {
+        return Box::new(ReplayCollector::new(replay.clone()));
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(root) = &config.host_root {
+        return Box::new(crate::procfs::ProcfsCollector::new(root));
+    }
+    Box::new(System::new_all())
+}
+
+/// The sysinfo routes as a tower `Service`, to serve on their own or embed in another service.
+#[derive(Clone)]
+pub struct SysinfoServer {
+    state: AppState,
+    prefix: Arc<str>,
+    client_addr: Option<SocketAddr>,
+}
+
+impl SysinfoServer {
+    pub fn builder() -> SysinfoServerBuilder {
+        SysinfoServerBuilder {
+            config: Config::default(),
+            collector: None,
+            endpoints: BTreeSet::new(),
+            prefix: String::new(),
+            sampler: true,
+        }
+    }
+
+    /// A service making a copy of the server for every connection, for `hyper::Server::serve`.
+    pub fn into_make_service(self) -> IntoMakeService {
+        IntoMakeService { server: self }
+    }
+
+    // Rewrites the request to the path below the prefix, or else responds to it
+    fn strip_prefix(&self, req: &mut Request<Body>) -> Option<Response<Body>> {
+        if self.prefix.is_empty() {
+            return None;
+        }
+        let path = match req.uri().path().strip_prefix(&*self.prefix) {
+            Some(path) if path.starts_with('/') => path.to_string(),
+            // Relative links, such as the dashboard's, only resolve below a trailing slash
+            Some("") => {
+                let response = Response::builder()
+                    .status(StatusCode::MOVED_PERMANENTLY)
+                    .header(LOCATION, format!("{}/", self.prefix))
+                    .body(Body::empty())
+                    .unwrap();
+                return Some(response);
+            }
+            _ => return Some(not_found()),
+        };
+
+        let path_and_query = match req.uri().query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+        let mut parts = req.uri().clone().into_parts();
+        // Valid as a part of a valid URI
+        parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).unwrap());
+        *req.uri_mut() = Uri::from_parts(parts).unwrap();
+        None
+    }
+}
+
+impl Service<Request<Body>> for SysinfoServer {
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<Body>, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        if let Some(addr) = self.client_addr {
+            req.extensions_mut().insert(ClientAddr(addr));
+        }
+        if let Some(response) = self.strip_prefix(&mut req) {
+            return Box::pin(ready(Ok(response)));
+        }
+        let state = self.state.clone();
+        Box::pin(async move {
+            Ok(handle_request(req, state).await
+                .unwrap_or_else(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())))
+        })
+    }
+}
+
+/// Makes a [`SysinfoServer`] for every connection, knowing the address of the client.
+pub struct IntoMakeService {
+    server: SysinfoServer,
+}
+
+impl<'a> Service<&'a AddrStream> for IntoMakeService {
+    type Response = SysinfoServer;
+    type Error = Infallible;
+    type Future = Ready<Result<SysinfoServer, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, conn: &'a AddrStream) -> Self::Future {
+        let mut server = self.server.clone();
+        server.client_addr = Some(conn.remote_addr());
+        ready(Ok(server))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Server;
+    use crate::collector::{fixture, FixtureCollector};
+
+    #[tokio::test]
+    async fn test_embedded_server() {
+        let server = SysinfoServer::builder()
+            .collector(FixtureCollector::from_json(fixture::HOST).expect("Invalid fixture"))
+            .enable(Endpoint::Memory)
+            .enable(Endpoint::Snapshot)
+            .enable(Endpoint::Dashboard)
+            .prefix("/sysinfo/")
+            .sampler(false)
+            .build();
+        // Start the server in a background task, on a port the OS picks
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(server.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(async {
+            if let Err(e) = server.await {
+                eprintln!("server error: {}", e);
+            }
+        });
+
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let get = |path: &str| client.get(format!("http://{}{}", addr, path)).send();
+
+        for path in ["/sysinfo/memory", "/sysinfo/v1/memory", "/sysinfo/v2/memory", "/sysinfo/"] {
+            let response = get(path).await.expect("Failed to send request");
+            assert_eq!(response.status(), reqwest::StatusCode::OK, "{}", path);
+        }
+
+        // The query is kept when the prefix is stripped
+        let snapshot: serde_json::Value = get("/sysinfo/snapshot?include=memory").await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert!(snapshot.get("memory").is_some());
+        assert!(snapshot.get("cpus").is_none());
+
+        let response = get("/sysinfo").await.expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()["location"], "/sysinfo/");
+
+        // Routes outside of the prefix and endpoints that are not enabled are not served
+        for path in ["/memory", "/sysinfoo/memory", "/sysinfo/cpus", "/sysinfo/v2/cpus", "/sysinfo/healthz"] {
+            let response = get(path).await.expect("Failed to send request");
+            assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_endpoint_of_path() {
+        assert_eq!(Endpoint::of_path("/load_average"), Some(Endpoint::LoadAverage));
+        assert_eq!(Endpoint::of_path("/readyz"), Some(Endpoint::Health));
+        assert_eq!(Endpoint::of_path("/docs/index.html"), Some(Endpoint::OpenApi));
+        assert_eq!(Endpoint::of_path("/processes"), None);
+    }
+}
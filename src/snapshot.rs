@@ -0,0 +1,154 @@
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::{Body, Response};
+use hyper::http::StatusCode;
+
+use crate::conditional::stamp;
+use crate::encoding::{error_response, respond, Format};
+use crate::model::Snapshot;
+use crate::sampler::Sampled;
+
+/// Subsystems that can be selected with `?include=`, named as the snapshot fields.
+pub(crate) const SUBSYSTEMS: [&str; 9] = [
+    "memory", "cpus", "temperatures", "sysinfo", "disks", "users", "networks", "load_average", "boot_time",
+];
+
+// Subsystem of the sampler that `include`d subsystem is refreshed as
+fn sampled_as(subsystem: &'static str) -> &'static str {
+    match subsystem {
+        "sysinfo" | "load_average" | "boot_time" => "system",
+        subsystem => subsystem,
+    }
+}
+
+// When the newest of the `include`d subsystems was sampled
+fn sampled_at(system: &Sampled, include: &[&'static str]) -> Option<SystemTime> {
+    include.iter()
+        .filter_map(|subsystem| system.refreshed(sampled_as(subsystem)))
+        .max()
+}
+
+/// Samples the `include`d subsystems while holding the system, stamped with a single timestamp.
+pub(crate) fn collect(system: &mut Sampled, include: &[&'static str]) -> Snapshot {
+    for subsystem in include {
+        system.refresh(sampled_as(subsystem));
+    }
+    read(system, include)
+}
+
+/// The `include`d subsystems as last sampled, without refreshing them.
+pub(crate) fn read(system: &Sampled, include: &[&'static str]) -> Snapshot {
+    let included = |subsystem: &str| include.contains(&subsystem);
+    Snapshot {
+        timestamp: sampled_at(system, include)
+            .and_then(|sampled| sampled.duration_since(UNIX_EPOCH).ok())
+            .map_or_else(crate::unix_timestamp, |since_epoch| since_epoch.as_secs()),
+        hostname: system.host_name().unwrap_or_else(|| "N/A".to_string()),
+        memory: included("memory").then(|| system.memory()),
+        cpus: included("cpus").then(|| system.cpus()),
+        temperatures: included("temperatures").then(|| system.temperatures()),
+        sysinfo: included("sysinfo").then(|| system.system_info()),
+        disks: included("disks").then(|| system.disks()),
+        users: included("users").then(|| system.users()),
+        networks: included("networks").then(|| system.networks()),
+        load_average: included("load_average").then(|| system.load_average()),
+        boot_time: included("boot_time").then(|| system.boot_time()),
+    }
+}
+
+// Subsystems listed in the comma separated `include` query parameters, all of them by default
+fn parse_include(query: Option<&str>) -> Result<Vec<&'static str>, String> {
+    let mut include = Vec::new();
+    for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        if key != "include" {
+            continue;
+        }
+        for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match SUBSYSTEMS.iter().find(|subsystem| **subsystem == name) {
+                Some(subsystem) => include.push(*subsystem),
+                None => return Err(format!("Unknown subsystem `{}`, expected one of {}", name, SUBSYSTEMS.join(", "))),
+            }
+        }
+    }
+    if include.is_empty() {
+        include.extend(SUBSYSTEMS);
+    }
+    Ok(include)
+}
+
+pub(crate) async fn handle_snapshot(query: Option<&str>, system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
+    let include = match parse_include(query) {
+        Ok(include) => include,
+        Err(message) => return Ok(error_response(StatusCode::BAD_REQUEST, message)),
+    };
+
+    let mut system = system.lock().unwrap();
+    let snapshot = collect(&mut system, &include);
+
+    Ok(stamp(respond(format, StatusCode::OK, &snapshot), sampled_at(&system, &include)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sysinfo::{System, SystemExt};
+    use std::net::SocketAddr;
+    use crate::config::Config;
+    use crate::model::ApiError;
+    use crate::AppState;
+    use crate::collector::fixture;
+
+    #[tokio::test]
+    async fn test_snapshot_endpoint() {
+        let addr = snapshot_test_server();
+
+        let response: serde_json::Value = reqwest::get(&format!("http://{}/snapshot", addr))
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse response as JSON");
+
+        assert!(response["timestamp"].is_u64());
+        assert!(response["hostname"].is_string());
+        for subsystem in SUBSYSTEMS {
+            assert!(response.get(subsystem).is_some(), "`{}` is missing", subsystem);
+        }
+        let snapshot: Snapshot = serde_json::from_value(response).expect("Failed to parse response as a snapshot");
+        let memory = snapshot.memory.expect("No memory in snapshot");
+        assert!(memory.used_memory <= memory.total_memory);
+
+        let snapshot: Snapshot = reqwest::get(&format!("http://{}/snapshot?include=memory,disks", addr))
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse response as a snapshot");
+        assert!(snapshot.memory.is_some());
+        assert!(snapshot.disks.is_some());
+        assert!(snapshot.cpus.is_none());
+        assert!(snapshot.boot_time.is_none());
+
+        let response = reqwest::get(&format!("http://{}/snapshot?include=memory,bogus", addr))
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        let error: ApiError = response.json().await.expect("Failed to parse response as an error");
+        assert!(error.message.contains("bogus"));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_fixture() {
+        let (status, mut response) = fixture::get(&fixture::state(), "/snapshot").await;
+        assert_eq!(status, StatusCode::OK);
+        // Only the time of the sample differs from the captured snapshot
+        let expected: serde_json::Value = serde_json::from_str(fixture::HOST).unwrap();
+        response["timestamp"] = expected["timestamp"].clone();
+        assert_eq!(response, expected);
+    }
+
+    fn snapshot_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
+    }
+}
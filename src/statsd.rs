@@ -0,0 +1,65 @@
+use tokio::net::UdpSocket;
+
+use crate::config::Statsd;
+use crate::export::{batches, udp_socket, Snapshots, DATAGRAM_SIZE};
+use crate::graphite::metrics;
+use crate::model::Snapshot;
+use crate::AppState;
+
+/// Every sample of `snapshot` as a StatsD gauge, named as in Graphite.
+pub(crate) fn gauges(snapshot: &Snapshot, prefix: &str) -> Vec<String> {
+    metrics(snapshot, prefix).into_iter().map(|(name, value)| format!("{}:{}|g", name, value)).collect()
+}
+
+async fn push(socket: &mut Option<UdpSocket>, statsd: &Statsd, snapshot: &Snapshot) -> std::io::Result<()> {
+    if socket.is_none() {
+        *socket = Some(udp_socket(&statsd.addr).await?);
+    }
+    for datagram in batches(&gauges(snapshot, &statsd.prefix), usize::MAX, DATAGRAM_SIZE) {
+        socket.as_ref().unwrap().send(datagram.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Sends the background sampler's snapshots to StatsD as they are taken.
+pub(crate) async fn run(state: AppState, statsd: Statsd) {
+    let mut socket = None;
+    let mut snapshots = Snapshots::new(&state);
+    state.exporters.set_failing("statsd", false);
+    loop {
+        let snapshot = snapshots.next().await;
+        let pushed = push(&mut socket, &statsd, &snapshot).await;
+        if let Err(e) = &pushed {
+            // Such as the address resolving elsewhere, or an ICMP error of the last datagram
+            eprintln!("Cannot send to StatsD at {}: {}", statsd.addr, e);
+            socket = None;
+        }
+        state.exporters.set_failing("statsd", pushed.is_err());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::fixture;
+
+    #[tokio::test]
+    async fn test_statsd() {
+        let snapshot: Snapshot = serde_json::from_str(fixture::HOST).unwrap();
+        let statsd_server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let statsd = Statsd { addr: statsd_server.local_addr().unwrap().to_string(), prefix: "sysinfo".to_string() };
+        let mut socket = None;
+        push(&mut socket, &statsd, &snapshot).await.expect("Failed to send");
+
+        let mut received = Vec::new();
+        let mut datagram = [0; 65536];
+        while received.len() < 30 {
+            let size = statsd_server.recv(&mut datagram).await.unwrap();
+            assert!(size <= DATAGRAM_SIZE);
+            received.extend(String::from_utf8_lossy(&datagram[..size]).lines().map(str::to_string));
+        }
+        assert_eq!(received, gauges(&snapshot, "sysinfo"));
+        assert_eq!(received[0], "sysinfo.fixture-host.memory.available_memory:12884901888|g");
+        assert_eq!(received[12], "sysinfo.fixture-host.cpus.cpu2.frequency:3600|g");
+    }
+}
@@ -1,65 +1,64 @@
 use std::sync::{Arc, Mutex};
 use hyper::{Body, Response};
 use hyper::http::StatusCode;
-use serde_json::json;
-use sysinfo::{ComponentExt, System, SystemExt};
+use sysinfo::{ComponentExt, SystemExt, System};
 
-pub(crate) async fn handle_temperatures(system: Arc<Mutex<System>>) -> Result<Response<Body>, hyper::Error> {
-    let mut system = system.lock().unwrap();
-    system.refresh_components();
-    let temperatures: Vec<_> = system.components().iter().filter_map(|component| {
+use crate::conditional::stamp;
+use crate::encoding::{respond, Format};
+use crate::model::{Temperature, Temperatures};
+use crate::sampler::Sampled;
+
+/// Units of the `Temperature` fields, reported by the `/v2` API.
+pub(crate) const UNITS: &[(&str, &str)] = &[
+    ("temperature", "celsius"),
+];
+
+/// Reads the temperature sensors from sysinfo, for its collector.
+pub(crate) fn read(system: &System) -> Vec<Temperature> {
+    system.components().iter().filter_map(|component| {
         let label = component.label();
         let temperature = component.temperature();
 
         if label.is_empty() {
             None
         } else {
-            Some(json!({
-                "name": label,
-                "temperature": temperature,
-            }))
+            Some(Temperature {
+                name: label.to_string(),
+                temperature: temperature as f64,
+            })
         }
-    }).collect();
-
+    }).collect()
+}
 
-    let body_data = json!({
-        "temperature_info": temperatures
-    });
+pub(crate) fn collect(system: &mut Sampled) -> Vec<Temperature> {
+    system.refresh("temperatures");
+    system.temperatures()
+}
 
-    let response = match Response::builder()
-        .header("Content-Type", "application/json")
-        .body(Body::from(json!(body_data).to_string())) {
-        Ok(res) => res,
-        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap(),
+pub(crate) async fn handle_temperatures(system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
+    let mut system = system.lock().unwrap();
+    let body_data = Temperatures {
+        temperature_info: collect(&mut system)
     };
-    Ok(response)
+
+    Ok(stamp(respond(format, StatusCode::OK, &body_data), system.refreshed("temperatures")))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::convert::Infallible;
     use super::*;
+    use sysinfo::System;
     use std::net::SocketAddr;
-    use hyper::Server;
-    use hyper::service::{make_service_fn, service_fn};
-    use reqwest;
-    use tokio;
-    use crate::handle_request;
-
-    const TEST_SERVER_ADDR: &str = "127.0.0.1:8088"; // Use a different port for testing
+    use crate::config::Config;
+    use crate::AppState;
+    use serde_json::json;
+    use crate::collector::fixture;
 
     #[tokio::test]
     async fn test_temperatures_endpoint() {
-        // Start the server in a background task
-        tokio::spawn(async {
-            let addr: SocketAddr = TEST_SERVER_ADDR.parse().expect("Invalid socket address");
-            temperatures_test_server(addr).await;
-        });
+        let addr = temperatures_test_server();
 
-        // Give the server a moment to start
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-        let response: serde_json::Value = reqwest::get(&format!("http://{}/temperatures", TEST_SERVER_ADDR))
+        let response: serde_json::Value = reqwest::get(&format!("http://{}/temperatures", addr))
             .await
             .expect("Failed to send request")
             .json()
@@ -84,20 +83,18 @@ mod tests {
             assert!(temperature_value > -100.0 && temperature_value < 200.0);
         }
     }
-    async fn temperatures_test_server(addr: SocketAddr) {
-        let system = Arc::new(Mutex::new(System::new_all()));
-
-        let test_service_load_avg = make_service_fn(move |_| {
-            let system = system.clone();
-            async {
-                Ok::<_, Infallible>(service_fn(move |req| handle_request(req, system.clone())))
-            }
-        });
 
-        let server = Server::bind(&addr).serve(test_service_load_avg);
+    #[tokio::test]
+    async fn test_temperatures_fixture() {
+        let (status, response) = fixture::get(&fixture::state(), "/temperatures").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, json!({"temperature_info": [
+            {"name": "coretemp Package id 0", "temperature": 54.0},
+            {"name": "nvme Composite", "temperature": 38.5},
+        ]}));
+    }
 
-        if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
-        }
+    fn temperatures_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
     }
 }
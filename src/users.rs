@@ -3,53 +3,50 @@ use std::sync::{Arc, Mutex};
 use hyper::{Body, Response};
 use hyper::http::StatusCode;
 
-use serde_json::json;
-use sysinfo::{System, SystemExt, UserExt};
-pub(crate) async fn handle_users(system: Arc<Mutex<System>>) -> Result<Response<Body>, hyper::Error> {
+use sysinfo::{SystemExt, UserExt, System};
+
+use crate::conditional::stamp;
+use crate::encoding::{respond, Format};
+use crate::model::UserInfo;
+use crate::sampler::Sampled;
+
+/// Reads the users from sysinfo, for its collector.
+pub(crate) fn read(system: &System) -> Vec<UserInfo> {
+    system.users().iter().map(|user| {
+        UserInfo {
+            name: user.name().to_string(),
+            group: user.groups().to_vec(),
+        }
+    }).collect()
+}
+
+pub(crate) fn collect(system: &mut Sampled) -> Vec<UserInfo> {
+    system.refresh("users");
+    system.users()
+}
+
+pub(crate) async fn handle_users(system: Arc<Mutex<Sampled>>, format: Format) -> Result<Response<Body>, hyper::Error> {
     let mut system = system.lock().unwrap();
-    system.refresh_users_list();
-    let users: Vec<_> = system.users().iter().map(|user| {
-        json!({
-            "name": user.name(),
-            "group": user.groups()
-        })
-    }).collect();
-
-
-    let response = match Response::builder()
-        .header("Content-Type", "application/json")
-        .body(Body::from(json!(users).to_string())) {
-        Ok(res) => res,
-        Err(_) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap(),
-    };
-    Ok(response)
+    let users = collect(&mut system);
+
+    Ok(stamp(respond(format, StatusCode::OK, &users), system.refreshed("users")))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::convert::Infallible;
     use super::*;
+    use sysinfo::System;
     use std::net::SocketAddr;
-    use hyper::Server;
-    use hyper::service::{make_service_fn, service_fn};
-    use reqwest;
-    use tokio;
-    use crate::handle_request;
-
-    const TEST_SERVER_ADDR: &str = "127.0.0.1:8089"; // Use a different port for testing
+    use crate::config::Config;
+    use crate::AppState;
+    use serde_json::json;
+    use crate::collector::fixture;
 
     #[tokio::test]
     async fn test_users_endpoint() {
-        // Start the server in a background task
-        tokio::spawn(async {
-            let addr: SocketAddr = TEST_SERVER_ADDR.parse().expect("Invalid socket address");
-            users_test_server(addr).await;
-        });
+        let addr = users_test_server();
 
-        // Give the server a moment to start
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-        let response: serde_json::Value = reqwest::get(&format!("http://{}/users", TEST_SERVER_ADDR))
+        let response: serde_json::Value = reqwest::get(&format!("http://{}/users", addr))
             .await
             .expect("Failed to send request")
             .json()
@@ -74,20 +71,17 @@ mod tests {
         }
     }
 
-    async fn users_test_server(addr: SocketAddr) {
-        let system = Arc::new(Mutex::new(System::new_all()));
-
-        let test_service_load_avg = make_service_fn(move |_| {
-            let system = system.clone();
-            async {
-                Ok::<_, Infallible>(service_fn(move |req| handle_request(req, system.clone())))
-            }
-        });
-
-        let server = Server::bind(&addr).serve(test_service_load_avg);
+    #[tokio::test]
+    async fn test_users_fixture() {
+        let (status, response) = fixture::get(&fixture::state(), "/users").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, json!([
+            {"group": ["root"], "name": "root"},
+            {"group": ["users", "wheel"], "name": "alice"},
+        ]));
+    }
 
-        if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
-        }
+    fn users_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
     }
 }
@@ -0,0 +1,183 @@
+use std::time::UNIX_EPOCH;
+
+use hyper::{Body, Method, Response};
+use hyper::http::StatusCode;
+
+use serde::Serialize;
+use crate::AppState;
+use crate::conditional::stamp;
+use crate::encoding::{error_response, respond, Format};
+use crate::model::Envelope;
+use crate::sampler::Sampled;
+use crate::{boot_time, cpus, disks, hostinfo, load_avg, memory, networks, temperatures, users};
+
+fn envelope<T: Serialize>(
+    system: &mut Sampled,
+    subsystem: &str,
+    collect: fn(&mut Sampled) -> T,
+    units: &[(&str, &str)],
+    format: Format,
+) -> Response<Body> {
+    let data = collect(system);
+    let sampled_at = system.refreshed(subsystem);
+    let envelope = Envelope {
+        timestamp: sampled_at
+            .and_then(|sampled| sampled.duration_since(UNIX_EPOCH).ok())
+            .map_or_else(crate::unix_timestamp, |since_epoch| since_epoch.as_secs()),
+        hostname: system.host_name().unwrap_or_else(|| "N/A".to_string()),
+        data,
+        units: units.iter().map(|(field, unit)| (field.to_string(), unit.to_string())).collect(),
+    };
+    stamp(respond(format, StatusCode::OK, &envelope), sampled_at)
+}
+
+// Function responding with the enveloped resource at `path`, if there is one
+fn resource(path: &str) -> Option<fn(&mut Sampled, Format) -> Response<Body>> {
+    let render: fn(&mut Sampled, Format) -> Response<Body> = match path {
+        "/memory" => |system, format| envelope(system, "memory", memory::collect, memory::UNITS, format),
+        "/temperatures" => |system, format| envelope(system, "temperatures", temperatures::collect, temperatures::UNITS, format),
+        "/sysinfo" => |system, format| envelope(system, "system", hostinfo::collect, &[], format),
+        "/disks" => |system, format| envelope(system, "disks", disks::collect, disks::UNITS, format),
+        "/cpus" => |system, format| envelope(system, "cpus", cpus::collect, cpus::UNITS, format),
+        "/users" => |system, format| envelope(system, "users", users::collect, &[], format),
+        "/networks" => |system, format| envelope(system, "networks", networks::collect, networks::UNITS, format),
+        "/load_average" => |system, format| envelope(system, "system", load_avg::collect, &[], format),
+        "/boot_time" => |system, format| envelope(system, "system", boot_time::collect, boot_time::UNITS, format),
+        _ => return None,
+    };
+    Some(render)
+}
+
+/// Whether `/v2{path}` is a resource.
+pub(crate) fn serves(path: &str) -> bool {
+    resource(path).is_some()
+}
+
+pub(crate) fn not_found(path: &str) -> Response<Body> {
+    error_response(StatusCode::NOT_FOUND, format!("No resource at /v2{}", path))
+}
+
+/// Serves `/v2{path}`.
+pub(crate) async fn handle_v2(method: &Method, path: &str, state: AppState, format: Format) -> Result<Response<Body>, hyper::Error> {
+    let render = match resource(path) {
+        Some(render) => render,
+        None => return Ok(not_found(path)),
+    };
+    if method != Method::GET {
+        return Ok(error_response(StatusCode::METHOD_NOT_ALLOWED, format!("/v2{} only supports GET", path)));
+    }
+
+    Ok(render(&mut state.system.lock().unwrap(), format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sysinfo::{System, SystemExt};
+    use std::net::SocketAddr;
+    use crate::config::Config;
+    use crate::model::{ApiError, DiskInfo, MemoryInfo};
+    use serde_json::json;
+    use crate::collector::fixture;
+
+    #[tokio::test]
+    async fn test_v2_endpoints() {
+        let addr = v2_test_server();
+
+        let memory: Envelope<MemoryInfo> = reqwest::get(&format!("http://{}/v2/memory", addr))
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse response as a memory envelope");
+        assert!(memory.timestamp > 0);
+        assert!(!memory.hostname.is_empty());
+        assert!(memory.data.used_memory <= memory.data.total_memory);
+        assert_eq!(memory.units.len(), memory::UNITS.len());
+        assert!(memory.units.values().all(|unit| unit == "bytes"));
+
+        let disks: Envelope<Vec<DiskInfo>> = reqwest::get(&format!("http://{}/v2/disks", addr))
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse response as a disks envelope");
+        assert_eq!(disks.units["total_space"], "bytes");
+
+        // Every resource is served as an envelope around an object or a list
+        for path in ["/temperatures", "/sysinfo", "/cpus", "/users", "/networks", "/load_average", "/boot_time"] {
+            let response: serde_json::Value = reqwest::get(&format!("http://{}/v2{}", addr, path))
+                .await
+                .expect("Failed to send request")
+                .json()
+                .await
+                .expect("Failed to parse response as JSON");
+            assert!(response["timestamp"].is_u64());
+            assert!(response["hostname"].is_string());
+            assert!(response["units"].is_object());
+            assert!(response["data"].is_object() || response["data"].is_array());
+        }
+        let sysinfo: serde_json::Value = reqwest::get(&format!("http://{}/v2/sysinfo", addr))
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse response as JSON");
+        assert!(sysinfo["data"]["kernel_version"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_v2_errors_and_v1_aliases() {
+        let addr = v2_test_server();
+
+        let response = reqwest::get(&format!("http://{}/v2/nothing", addr))
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        let error: ApiError = response.json().await.expect("Failed to parse response as an error");
+        assert_eq!(error.status, 404);
+        assert_eq!(error.error, "not_found");
+
+        let response = reqwest::Client::new().post(format!("http://{}/v2/memory", addr))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::METHOD_NOT_ALLOWED);
+        let error: ApiError = response.json().await.expect("Failed to parse response as an error");
+        assert_eq!(error.error, "method_not_allowed");
+
+        // The sysinfo-http compatible routes are also served below /v1
+        let memory: Vec<MemoryInfo> = reqwest::get(&format!("http://{}/v1/memory", addr))
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .expect("Failed to parse response as legacy memory");
+        assert_eq!(memory.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_v2_fixture() {
+        let (status, response) = fixture::get(&fixture::state(), "/v2/load_average").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["hostname"], "fixture-host");
+        assert_eq!(response["data"], json!({"fifteen": 0.5, "five": 0.75, "one": 1.25}));
+        assert_eq!(response["units"], json!({}));
+    }
+
+    #[tokio::test]
+    async fn test_version_prefixes() {
+        let state = fixture::state();
+        // A version is a whole path segment
+        for path in ["/v2memory", "/v1memory", "/v2", "/v1"] {
+            let req = hyper::Request::get(path).body(Body::empty()).unwrap();
+            assert_eq!(fixture::send(&state, req).await.status(), StatusCode::NOT_FOUND, "{}", path);
+        }
+        assert_eq!(fixture::get(&state, "/v1/memory").await.0, StatusCode::OK);
+        assert_eq!(fixture::get(&state, "/v2/memory").await.0, StatusCode::OK);
+    }
+
+    fn v2_test_server() -> SocketAddr {
+        fixture::serve(AppState::new(Box::new(System::new_all()), Config::default()))
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tokio::sync::Notify;
+
+use crate::model::{Alert, AlertState};
+use crate::unix_timestamp;
+
+/// Delivery attempts of a notification before it is dropped, the last one about 34 minutes after the first.
+const MAX_ATTEMPTS: u32 = 12;
+/// Seconds between the posts of the firing alerts to Alertmanager, which resolves those not posted
+/// again within its `resolve_timeout`, 5 minutes by default.
+const ALERTMANAGER_REPEAT: u64 = 60;
+
+/// Payload sent to a webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// `{"hostname", "alerts"}`, the alerts as listed by `/alerts`
+    Json,
+    /// A Slack incoming webhook message
+    Slack,
+    /// Alerts for the Alertmanager `/api/v2/alerts` API
+    Alertmanager,
+}
+
+/// URL notified when alerts fire or are resolved, written `[FORMAT=]URL` with the format
+/// `json` (the default), `slack` or `alertmanager`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    pub url: String,
+    pub format: WebhookFormat,
+}
+
+impl FromStr for Webhook {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Webhook, String> {
+        let (format, url) = match value.split_once('=') {
+            Some(("json", url)) => (WebhookFormat::Json, url),
+            Some(("slack", url)) => (WebhookFormat::Slack, url),
+            Some(("alertmanager", url)) => (WebhookFormat::Alertmanager, url),
+            // `=` within the URL's query
+            _ => (WebhookFormat::Json, value),
+        };
+        match url.parse::<hyper::Uri>() {
+            Ok(uri) if matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some() => {
+                Ok(Webhook { url: url.to_string(), format })
+            }
+            _ => Err(format!("'{}' is not an http or https URL", url)),
+        }
+    }
+}
+
+// `secs` since the epoch as an RFC 3339 UTC time, the civil date computed as in
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn rfc3339(secs: u64) -> String {
+    let (days, time) = ((secs / 86400) as i64, secs % 86400);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+fn instance(alert: &Alert) -> String {
+    alert.instance.as_ref().map(|instance| format!(" ({})", instance)).unwrap_or_default()
+}
+
+// `rule` or `rule (instance)`, telling alerts apart across restarts
+fn key(alert: &Alert) -> String {
+    format!("{}{}", alert.rule, instance(alert))
+}
+
+impl WebhookFormat {
+    /// Body notifying of `alerts`, which started firing or were resolved on `hostname`.
+    fn payload(self, hostname: &str, alerts: &[Alert]) -> Value {
+        match self {
+            WebhookFormat::Json => json!({ "hostname": hostname, "alerts": alerts }),
+            WebhookFormat::Slack => {
+                let lines: Vec<String> = alerts.iter().map(|alert| {
+                    let state = if alert.state == AlertState::Resolved { "RESOLVED" } else { "FIRING" };
+                    format!("[{}] {} on {}{}: {}, value {} (threshold {})",
+                            state, alert.rule, hostname, instance(alert), alert.expression, alert.value, alert.threshold)
+                }).collect();
+                json!({ "text": lines.join("\n") })
+            }
+            WebhookFormat::Alertmanager => alerts.iter().map(|alert| {
+                let mut labels = Map::new();
+                labels.insert("alertname".to_string(), json!(alert.rule));
+                labels.insert("host".to_string(), json!(hostname));
+                if let Some(instance) = &alert.instance {
+                    labels.insert("instance".to_string(), json!(instance));
+                }
+                let mut body = json!({
+                    "labels": labels,
+                    "annotations": {
+                        "expression": alert.expression,
+                        "value": alert.value.to_string(),
+                        "threshold": alert.threshold.to_string(),
+                    },
+                    "startsAt": rfc3339(alert.fired_at.unwrap_or(alert.active_since)),
+                });
+                if let Some(resolved_at) = alert.resolved_at {
+                    body["endsAt"] = json!(rfc3339(resolved_at));
+                }
+                body
+            }).collect(),
+        }
+    }
+}
+
+/// Notification waiting to be delivered to a webhook.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Delivery {
+    id: u64,
+    url: String,
+    body: Value,
+    attempts: u32,
+    /// When to try again, in seconds since the epoch
+    next_attempt: u64,
+    /// Whether it only posts the firing alerts to Alertmanager again, superseded by the next such post
+    #[serde(default)]
+    repeat: bool,
+}
+
+/// Notifications not delivered yet, and the alerts webhooks were told are firing. Persisted
+/// to survive restarts when the server is given a file for it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Outbox {
+    next_id: u64,
+    /// Every alert last notified as firing, by `rule` or `rule (instance)`
+    firing: BTreeMap<String, Alert>,
+    deliveries: VecDeque<Delivery>,
+    /// When the firing alerts were last posted to Alertmanager, in seconds since the epoch
+    #[serde(skip)]
+    repeated_at: u64,
+}
+
+/// Notifies the configured webhooks of alerts that start firing or are resolved, retrying
+/// failed deliveries with exponential backoff.
+pub(crate) struct Webhooks {
+    webhooks: Vec<Webhook>,
+    path: Option<PathBuf>,
+    outbox: Mutex<Outbox>,
+    wake: Notify,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    /// Notifies `webhooks`, resuming the deliveries left in the outbox file at `path`, if any.
+    pub(crate) fn new(webhooks: Vec<Webhook>, path: Option<PathBuf>) -> Webhooks {
+        let outbox = path.as_deref().and_then(|path| match std::fs::read(path) {
+            Ok(outbox) => serde_json::from_slice(&outbox)
+                .map_err(|e| eprintln!("Ignoring the webhook outbox {}: {}", path.display(), e))
+                .ok(),
+            Err(_) => None,
+        });
+        let client = reqwest::Client::builder().timeout(Duration::from_secs(10)).build().expect("Failed to create HTTP client");
+        Webhooks { webhooks, path, outbox: Mutex::new(outbox.unwrap_or_default()), wake: Notify::new(), client }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.webhooks.is_empty()
+    }
+
+    /// Queues one notification per webhook of the alerts that started firing or were resolved
+    /// since the last call, `alerts` being all the current ones as of `now`. Alerts notified as
+    /// firing that are neither firing nor pending any longer are notified as resolved, also those
+    /// that cleared while the server was down. Alertmanager is also posted the firing alerts again
+    /// every [`ALERTMANAGER_REPEAT`] seconds.
+    pub(crate) fn notify(&self, hostname: &str, alerts: &[Alert], now: u64) {
+        if self.webhooks.is_empty() {
+            return;
+        }
+        let mut outbox = self.outbox.lock().unwrap();
+        let mut changed = Vec::new();
+        for alert in alerts.iter().filter(|alert| alert.state == AlertState::Firing) {
+            if let Entry::Vacant(entry) = outbox.firing.entry(key(alert)) {
+                entry.insert(alert.clone());
+                changed.push(alert.clone());
+            }
+        }
+        let active: BTreeSet<String> = alerts.iter().filter(|alert| alert.state != AlertState::Resolved).map(key).collect();
+        let cleared: Vec<String> = outbox.firing.keys().filter(|key| !active.contains(*key)).cloned().collect();
+        for cleared in cleared {
+            let fired = outbox.firing.remove(&cleared).unwrap();
+            // Alerts that cleared while the server was down are not known to the alert rules any more
+            let resolved = alerts.iter().find(|alert| alert.state == AlertState::Resolved && key(alert) == cleared).cloned()
+                .unwrap_or(Alert { state: AlertState::Resolved, resolved_at: Some(now), ..fired });
+            changed.push(resolved);
+        }
+        let repeat = now >= outbox.repeated_at + ALERTMANAGER_REPEAT;
+        if repeat {
+            outbox.repeated_at = now;
+        }
+        let mut queued = false;
+        for webhook in &self.webhooks {
+            let alerts: Vec<Alert> = match webhook.format {
+                WebhookFormat::Alertmanager if repeat => alerts.iter()
+                    .filter(|alert| alert.state == AlertState::Firing)
+                    .chain(changed.iter().filter(|alert| alert.state == AlertState::Resolved))
+                    .cloned()
+                    .collect(),
+                _ => changed.clone(),
+            };
+            if alerts.is_empty() {
+                continue;
+            }
+            let repeat = changed.is_empty();
+            if repeat {
+                outbox.deliveries.retain(|delivery| !(delivery.repeat && delivery.url == webhook.url));
+            }
+            let id = outbox.next_id;
+            outbox.next_id += 1;
+            outbox.deliveries.push_back(Delivery {
+                id,
+                url: webhook.url.clone(),
+                body: webhook.format.payload(hostname, &alerts),
+                attempts: 0,
+                next_attempt: 0,
+                repeat,
+            });
+            queued = true;
+        }
+        if queued {
+            self.save(&outbox);
+            self.wake.notify_one();
+        }
+    }
+
+    fn save(&self, outbox: &Outbox) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        // Written aside and renamed, never leaving a partial file behind
+        let temporary = path.with_extension("tmp");
+        let saved = serde_json::to_vec(outbox).map_err(std::io::Error::from)
+            .and_then(|outbox| std::fs::write(&temporary, outbox))
+            .and_then(|_| std::fs::rename(&temporary, path));
+        if let Err(e) = saved {
+            eprintln!("Cannot save the webhook outbox to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Attempts the deliveries that are due, returning how long until the next one is.
+    async fn deliver_due(&self) -> Option<Duration> {
+        let now = unix_timestamp();
+        let due: Vec<Delivery> = self.outbox.lock().unwrap().deliveries.iter()
+            .filter(|delivery| delivery.next_attempt <= now)
+            .cloned()
+            .collect();
+
+        let mut results = Vec::new();
+        for delivery in due {
+            let result = self.client.post(&delivery.url).json(&delivery.body).send().await
+                .and_then(|response| response.error_for_status());
+            results.push((delivery.id, result.map(|_| ())));
+        }
+
+        let mut outbox = self.outbox.lock().unwrap();
+        if !results.is_empty() {
+            let now = unix_timestamp();
+            outbox.deliveries.retain_mut(|delivery| {
+                let Some((_, result)) = results.iter().find(|(id, _)| *id == delivery.id) else {
+                    return true;
+                };
+                let Err(e) = result else {
+                    return false;
+                };
+                delivery.attempts += 1;
+                if delivery.attempts >= MAX_ATTEMPTS {
+                    eprintln!("Dropping notification to {} after {} attempts: {}", delivery.url, delivery.attempts, e);
+                    return false;
+                }
+                // 1s, 2s, 4s, ... up to about 17 minutes
+                delivery.next_attempt = now + (1 << (delivery.attempts - 1));
+                true
+            });
+            self.save(&outbox);
+        }
+        let next = outbox.deliveries.iter().map(|delivery| delivery.next_attempt).min()?;
+        Some(Duration::from_secs(next.saturating_sub(unix_timestamp())))
+    }
+
+    /// Delivers the notifications as they are queued, until the server stops.
+    pub(crate) async fn run(self: Arc<Self>) {
+        loop {
+            match self.deliver_due().await {
+                Some(wait) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(wait) => {}
+                        _ = self.wake.notified() => {}
+                    }
+                }
+                None => self.wake.notified().await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use hyper::{Body, Request, Response, Server, StatusCode};
+    use hyper::service::{make_service_fn, service_fn};
+
+
+    fn alert(state: AlertState) -> Alert {
+        Alert {
+            rule: "hot".to_string(),
+            expression: "temperature[*] > 50".to_string(),
+            instance: Some("coretemp Package id 0".to_string()),
+            state,
+            value: 54.0,
+            threshold: 50.0,
+            active_since: 1700000000,
+            fired_at: Some(1700000000),
+            resolved_at: (state == AlertState::Resolved).then_some(1700000600),
+        }
+    }
+
+    #[test]
+    fn test_webhook_payloads() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(1700000000), "2023-11-14T22:13:20Z");
+        assert_eq!(rfc3339(951782400), "2000-02-29T00:00:00Z");
+
+        assert_eq!("slack=https://hooks.slack.com/services/T0/B0/x".parse(), Ok(Webhook {
+            url: "https://hooks.slack.com/services/T0/B0/x".to_string(),
+            format: WebhookFormat::Slack,
+        }));
+        assert_eq!("http://127.0.0.1:9000/hook?token=a".parse::<Webhook>().map(|webhook| webhook.format), Ok(WebhookFormat::Json));
+        assert!("teams=https://example.com".parse::<Webhook>().is_err());
+        assert!("/hook".parse::<Webhook>().is_err());
+
+        let alerts = [alert(AlertState::Firing), alert(AlertState::Resolved)];
+        assert_eq!(WebhookFormat::Json.payload("fixture-host", &alerts[..1])["alerts"][0]["rule"], "hot");
+        assert_eq!(WebhookFormat::Slack.payload("fixture-host", &alerts), json!({
+            "text": "[FIRING] hot on fixture-host (coretemp Package id 0): temperature[*] > 50, value 54 (threshold 50)\n\
+                     [RESOLVED] hot on fixture-host (coretemp Package id 0): temperature[*] > 50, value 54 (threshold 50)",
+        }));
+        assert_eq!(WebhookFormat::Alertmanager.payload("fixture-host", &alerts[1..]), json!([{
+            "labels": { "alertname": "hot", "host": "fixture-host", "instance": "coretemp Package id 0" },
+            "annotations": { "expression": "temperature[*] > 50", "value": "54", "threshold": "50" },
+            "startsAt": "2023-11-14T22:13:20Z",
+            "endsAt": "2023-11-14T22:23:20Z",
+        }]));
+    }
+
+    // Waits for the outbox to empty, the first retry being due a second after a failure
+    async fn delivered(webhooks: &Webhooks) {
+        for _ in 0..100 {
+            if webhooks.outbox.lock().unwrap().deliveries.is_empty() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Notifications not delivered");
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery() {
+        // Stand-in for the webhook, failing its first request
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let requests = received.clone();
+        let service = make_service_fn(move |_| {
+            let requests = requests.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let requests = requests.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let mut requests = requests.lock().unwrap();
+                        requests.push(serde_json::from_slice::<Value>(&body).unwrap());
+                        let status = if requests.len() == 1 { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+                        Ok::<_, Infallible>(Response::builder().status(status).body(Body::empty()).unwrap())
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let path = std::env::temp_dir().join(format!("sysinfo-outbox-{}.json", std::process::id()));
+        let webhooks = vec![format!("http://{}/hook", addr).parse().unwrap()];
+
+        // Queued notifications survive a restart, and alerts are only notified once per state
+        let before_restart = Webhooks::new(webhooks.clone(), Some(path.clone()));
+        before_restart.notify("fixture-host", &[alert(AlertState::Firing)], 1700000000);
+        before_restart.notify("fixture-host", &[alert(AlertState::Firing)], 1700000005);
+        drop(before_restart);
+        let webhooks = Arc::new(Webhooks::new(webhooks, Some(path.clone())));
+        // Pending again after the restart, as the rule's duration starts over
+        webhooks.notify("fixture-host", &[alert(AlertState::Pending)], 1700000010);
+        webhooks.notify("fixture-host", &[alert(AlertState::Firing)], 1700000015);
+        assert_eq!(webhooks.outbox.lock().unwrap().deliveries.len(), 1);
+
+        tokio::spawn(webhooks.clone().run());
+        delivered(&webhooks).await;
+        let requests = received.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request["alerts"][0]["state"] == "firing"));
+        assert!(webhooks.outbox.lock().unwrap().deliveries.is_empty());
+
+        // Resolving is notified once, and only for alerts notified as firing
+        webhooks.notify("fixture-host", &[alert(AlertState::Resolved)], 1700000600);
+        webhooks.notify("fixture-host", &[alert(AlertState::Resolved)], 1700000605);
+        delivered(&webhooks).await;
+        let requests = received.lock().unwrap().clone();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2]["alerts"][0]["state"], "resolved");
+
+        let outbox: Outbox = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(outbox.firing.is_empty() && outbox.deliveries.is_empty());
+    }
+
+    #[test]
+    fn test_webhook_restart_after_clearing() {
+        let path = std::env::temp_dir().join(format!("sysinfo-outbox-cleared-{}.json", std::process::id()));
+        let webhooks = vec!["http://127.0.0.1:9/hook".parse().unwrap()];
+        let before_restart = Webhooks::new(webhooks.clone(), Some(path.clone()));
+        before_restart.notify("fixture-host", &[alert(AlertState::Firing)], 1700000000);
+        drop(before_restart);
+
+        // The condition cleared while the server was down, so the rules know of no alert
+        let webhooks = Webhooks::new(webhooks, Some(path.clone()));
+        webhooks.notify("fixture-host", &[], 1700000900);
+        {
+            let outbox = webhooks.outbox.lock().unwrap();
+            assert!(outbox.firing.is_empty());
+            let resolved = &outbox.deliveries[1].body["alerts"][0];
+            assert_eq!((&resolved["state"], &resolved["resolved_at"]), (&json!("resolved"), &json!(1700000900)));
+        }
+
+        // so the next time it fires is notified
+        webhooks.notify("fixture-host", &[alert(AlertState::Firing)], 1700001000);
+        let outbox = webhooks.outbox.lock().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(outbox.deliveries.len(), 3);
+        assert_eq!(outbox.deliveries[2].body["alerts"][0]["state"], "firing");
+    }
+
+    #[test]
+    fn test_alertmanager_repeat() {
+        let webhooks = Webhooks::new(vec!["alertmanager=http://127.0.0.1:9/api/v2/alerts".parse().unwrap()], None);
+        let bodies = || -> Vec<Value> {
+            webhooks.outbox.lock().unwrap().deliveries.iter().map(|delivery| delivery.body.clone()).collect()
+        };
+        webhooks.notify("fixture-host", &[alert(AlertState::Firing)], 1700000000);
+        webhooks.notify("fixture-host", &[alert(AlertState::Firing)], 1700000005);
+        assert_eq!(bodies().len(), 1);
+
+        // Firing alerts are posted again before Alertmanager resolves them, replacing undelivered repeats
+        webhooks.notify("fixture-host", &[alert(AlertState::Firing)], 1700000060);
+        webhooks.notify("fixture-host", &[alert(AlertState::Firing)], 1700000120);
+        let bodies = bodies();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[1], bodies[0]);
+        assert!(webhooks.outbox.lock().unwrap().deliveries[1].repeat);
+
+        // but not once resolved
+        webhooks.notify("fixture-host", &[alert(AlertState::Resolved)], 1700000600);
+        webhooks.notify("fixture-host", &[alert(AlertState::Resolved)], 1700000700);
+        let outbox = webhooks.outbox.lock().unwrap();
+        assert_eq!(outbox.deliveries.len(), 3);
+        assert_eq!(outbox.deliveries[2].body[0]["endsAt"], "2023-11-14T22:23:20Z");
+    }
+}
@@ -0,0 +1,270 @@
+//! Command-line client of sysinfo_server_rust for shell scripts.
+//!
+//! Exits with 0 when every host answered successfully, 2 when a host could not be
+//! reached and 3 when a host answered with an error status.
+
+use std::process::exit;
+use std::thread;
+use std::time::Duration;
+
+use clap::{App, Arg};
+use serde_json::{Map, Value};
+
+const EXIT_UNREACHABLE: i32 = 2;
+const EXIT_ERROR_STATUS: i32 = 3;
+
+/// The answer of one host.
+enum Outcome {
+    Ok(Value),
+    /// The host answered with an error status, and maybe an error body
+    ErrorStatus(u16, Value),
+    Unreachable(String),
+}
+
+impl Outcome {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Ok(_) => 0,
+            Outcome::ErrorStatus(..) => EXIT_ERROR_STATUS,
+            Outcome::Unreachable(_) => EXIT_UNREACHABLE,
+        }
+    }
+}
+
+// `host:port` is taken to mean plain HTTP
+fn base_url(host: &str) -> String {
+    let url = if host.contains("://") { host.to_string() } else { format!("http://{}", host) };
+    url.trim_end_matches('/').to_string()
+}
+
+// `memory`, `/v2/memory` and `snapshot?include=cpus` all name endpoints
+fn endpoint_url(host: &str, endpoint: &str) -> String {
+    format!("{}/{}", base_url(host), endpoint.trim_start_matches('/'))
+}
+
+fn fetch(client: &reqwest::blocking::Client, url: &str, token: Option<&str>) -> Outcome {
+    let mut request = client.get(url).header("accept", "application/json");
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = match request.send() {
+        Ok(response) => response,
+        Err(e) => return Outcome::Unreachable(e.to_string()),
+    };
+    let status = response.status();
+    let text = match response.text() {
+        Ok(text) => text,
+        Err(e) => return Outcome::Unreachable(e.to_string()),
+    };
+    // Some errors, such as unknown legacy paths, are plain text
+    let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+    if status.is_success() {
+        Outcome::Ok(body)
+    } else {
+        Outcome::ErrorStatus(status.as_u16(), body)
+    }
+}
+
+// Rows of a response: a list, the only list of a wrapper object such as `cpu_info`,
+// the `data` of a `/v2` envelope, or else the response itself as a single row
+fn rows(value: &Value) -> Vec<Value> {
+    match value {
+        Value::Array(rows) => rows.clone(),
+        Value::Object(object) if object.len() == 1 && object.values().all(Value::is_array) => {
+            object.values().next().and_then(Value::as_array).cloned().unwrap_or_default()
+        }
+        Value::Object(object) if object.contains_key("data") && object.contains_key("units") => rows(&object["data"]),
+        value => vec![value.clone()],
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(text) => text.clone(),
+        Value::Array(items) if items.iter().all(Value::is_string) => {
+            items.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(",")
+        }
+        value => value.to_string(),
+    }
+}
+
+/// Lays out the rows of every host's response as one aligned table, numbers right aligned.
+fn table(responses: &[(String, Value)]) -> String {
+    let fan_out = responses.len() > 1;
+    let mut columns: Vec<String> = if fan_out { vec!["host".to_string()] } else { Vec::new() };
+    let mut records: Vec<Map<String, Value>> = Vec::new();
+    for (host, response) in responses {
+        for row in rows(response) {
+            let mut record = Map::new();
+            if fan_out {
+                record.insert("host".to_string(), Value::String(host.clone()));
+            }
+            match row {
+                Value::Object(object) => record.extend(object),
+                value => { record.insert("value".to_string(), value); }
+            }
+            for key in record.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+            records.push(record);
+        }
+    }
+
+    let cells: Vec<Vec<(String, bool)>> = records.iter().map(|record| {
+        columns.iter().map(|column| {
+            let value = record.get(column).unwrap_or(&Value::Null);
+            (cell(value), value.is_number())
+        }).collect()
+    }).collect();
+    let widths: Vec<usize> = columns.iter().enumerate().map(|(i, column)| {
+        cells.iter().map(|row| row[i].0.chars().count()).chain([column.chars().count()]).max().unwrap_or_default()
+    }).collect();
+
+    let mut lines = vec![columns.iter().zip(&widths).map(|(column, width)| format!("{:<width$}", column.to_uppercase(), width = width)).collect::<Vec<_>>()];
+    for row in cells {
+        lines.push(row.iter().zip(&widths).map(|((text, numeric), width)| {
+            if *numeric { format!("{:>width$}", text, width = width) } else { format!("{:<width$}", text, width = width) }
+        }).collect());
+    }
+    lines.iter().map(|line| line.join("  ").trim_end().to_string()).collect::<Vec<_>>().join("\n")
+}
+
+fn main() {
+    let matches = App::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Queries sysinfo_server_rust servers")
+        .after_help("Exits with 2 when a host cannot be reached and 3 when a host answers with an error status.")
+        .arg(Arg::with_name("endpoint")
+            .help("Endpoint to query, e.g. memory, /v2/disks or \"snapshot?include=cpus\"")
+            .required(true))
+        .arg(Arg::with_name("host")
+            .long("host")
+            .short("H")
+            .value_name("HOST")
+            .help("Server to query, as a URL or host:port. Repeat or separate with commas to query several")
+            .default_value("127.0.0.1:5000")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .short("o")
+            .value_name("FORMAT")
+            .help("Output format; json prints the response, or an object keyed by host when there are several")
+            .possible_values(&["table", "json"])
+            .default_value("table"))
+        .arg(Arg::with_name("token")
+            .long("token")
+            .value_name("TOKEN")
+            .help("Bearer token sent with every request"))
+        .arg(Arg::with_name("timeout")
+            .long("timeout")
+            .value_name("SECONDS")
+            .help("Timeout of each request")
+            .default_value("10")
+            .validator(|value| match value.parse::<u64>() {
+                Ok(secs) if secs > 0 => Ok(()),
+                _ => Err(format!("'{}' is not a positive number of seconds", value)),
+            }))
+        .get_matches();
+
+    let endpoint = matches.value_of("endpoint").unwrap();
+    let hosts: Vec<String> = matches.values_of("host").unwrap()
+        .flat_map(|hosts| hosts.split(','))
+        .map(str::trim)
+        .filter(|host| !host.is_empty())
+        .map(str::to_string)
+        .collect();
+    let token = matches.value_of("token").map(str::to_string);
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(matches.value_of("timeout").unwrap().parse().unwrap()))
+        .build()
+        .expect("Failed to build HTTP client");
+
+    // Every host is queried at once
+    let outcomes: Vec<(String, Outcome)> = thread::scope(|scope| {
+        let handles: Vec<_> = hosts.iter().map(|host| {
+            let (client, token) = (&client, token.as_deref());
+            scope.spawn(move || fetch(client, &endpoint_url(host, endpoint), token))
+        }).collect();
+        hosts.iter().cloned().zip(handles.into_iter().map(|handle| handle.join().unwrap())).collect()
+    });
+
+    let mut exit_code = 0;
+    let mut responses = Vec::new();
+    for (host, outcome) in outcomes {
+        match &outcome {
+            Outcome::Ok(body) => responses.push((host, body.clone())),
+            Outcome::ErrorStatus(status, body) => eprintln!("{}: HTTP {}: {}", host, status, cell(body.get("message").unwrap_or(body))),
+            Outcome::Unreachable(error) => eprintln!("{}: {}", host, error),
+        }
+        exit_code = exit_code.max(outcome.exit_code());
+    }
+
+    match matches.value_of("output") {
+        Some("json") if hosts.len() == 1 => {
+            if let Some((_, body)) = responses.first() {
+                println!("{}", serde_json::to_string_pretty(body).unwrap());
+            }
+        }
+        Some("json") => {
+            let by_host: Map<String, Value> = responses.into_iter().collect();
+            println!("{}", serde_json::to_string_pretty(&by_host).unwrap());
+        }
+        _ if !responses.is_empty() => println!("{}", table(&responses)),
+        _ => {}
+    }
+    exit(exit_code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_endpoint_url() {
+        assert_eq!(endpoint_url("10.0.0.1:5000", "memory"), "http://10.0.0.1:5000/memory");
+        assert_eq!(endpoint_url("https://box.example/", "/v2/disks"), "https://box.example/v2/disks");
+    }
+
+    #[test]
+    fn test_rows() {
+        assert_eq!(rows(&json!([{"a": 1}, {"a": 2}])).len(), 2);
+        assert_eq!(rows(&json!({"cpu_info": [{"a": 1}]})), vec![json!({"a": 1})]);
+        assert_eq!(rows(&json!({"timestamp": 1, "hostname": "h", "data": {"a": 1}, "units": {}})), vec![json!({"a": 1})]);
+        assert_eq!(rows(&json!({"status": "ok"})), vec![json!({"status": "ok"})]);
+    }
+
+    #[test]
+    fn test_table() {
+        let response = json!([
+            {"device_name": "/dev/sda1", "total_space": 1000},
+            {"device_name": "/dev/nvme0n1p2", "total_space": 25},
+        ]);
+        assert_eq!(table(&[("a".to_string(), response.clone())]), [
+            "DEVICE_NAME     TOTAL_SPACE",
+            "/dev/sda1              1000",
+            "/dev/nvme0n1p2           25",
+        ].join("\n"));
+
+        // Fanned out responses are told apart by a host column
+        let users = json!([{"group": ["wheel", "users"], "name": "root"}]);
+        assert_eq!(table(&[("a".to_string(), users.clone()), ("b".to_string(), users)]), [
+            "HOST  GROUP        NAME",
+            "a     wheel,users  root",
+            "b     wheel,users  root",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_unreachable_host() {
+        let client = reqwest::blocking::Client::new();
+        // Nothing listens on the discard port
+        let outcome = fetch(&client, &endpoint_url("127.0.0.1:9", "memory"), None);
+        assert!(matches!(outcome, Outcome::Unreachable(_)));
+        assert_eq!(outcome.exit_code(), EXIT_UNREACHABLE);
+    }
+}
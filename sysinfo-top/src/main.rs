@@ -0,0 +1,397 @@
+//! Live terminal dashboard of one or more sysinfo_server_rust servers.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use clap::{App, Arg};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, LineGauge, Paragraph, Row, Sparkline, Table, Tabs};
+use ratatui::{DefaultTerminal, Frame};
+
+use sysinfo_server_model::{DiskInfo, Snapshot};
+
+// Traffic samples kept per interface for the sparklines
+const HISTORY: usize = 120;
+
+const DISK_COLUMNS: [&str; 5] = ["Device", "File system", "Used", "Total", "Use"];
+
+/// What is known about one server.
+#[derive(Default)]
+struct Server {
+    url: String,
+    snapshot: Option<Snapshot>,
+    /// Bytes per second received and transmitted by each interface, oldest first
+    traffic: BTreeMap<String, VecDeque<(u64, u64)>>,
+    error: Option<String>,
+}
+
+impl Server {
+    fn update(&mut self, snapshot: Snapshot) {
+        // Rates from the counters since the interfaces came up, as any request resets the traffic since the previous refresh
+        if let Some(previous) = self.snapshot.as_ref().filter(|previous| previous.timestamp != snapshot.timestamp) {
+            let elapsed = snapshot.timestamp.saturating_sub(previous.timestamp).max(1);
+            for network in snapshot.networks.iter().flatten() {
+                let Some(before) = previous.networks.iter().flatten().find(|before| before.interface_name == network.interface_name) else {
+                    continue;
+                };
+                let history = self.traffic.entry(network.interface_name.clone()).or_default();
+                if history.len() == HISTORY {
+                    history.pop_front();
+                }
+                history.push_back((
+                    network.total_received.saturating_sub(before.total_received) / elapsed,
+                    network.total_transmitted.saturating_sub(before.total_transmitted) / elapsed,
+                ));
+            }
+        }
+        self.snapshot = Some(snapshot);
+        self.error = None;
+    }
+}
+
+struct Dashboard {
+    servers: Vec<Arc<Mutex<Server>>>,
+    selected: usize,
+    /// Index into `DISK_COLUMNS` the disk table is sorted by
+    disk_sort: usize,
+    descending: bool,
+}
+
+// `host:port` is taken to mean plain HTTP
+fn base_url(server: &str) -> String {
+    let url = if server.contains("://") { server.to_string() } else { format!("http://{}", server) };
+    url.trim_end_matches('/').to_string()
+}
+
+fn poll(server: Arc<Mutex<Server>>, interval: Duration) {
+    let url = format!("{}/snapshot", server.lock().unwrap().url);
+    let client = reqwest::blocking::Client::builder()
+        .timeout(interval.max(Duration::from_secs(5)))
+        .build()
+        .expect("Failed to build HTTP client");
+    loop {
+        let result = client.get(&url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json::<Snapshot>());
+        match result {
+            Ok(snapshot) => server.lock().unwrap().update(snapshot),
+            Err(e) => server.lock().unwrap().error = Some(e.to_string()),
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn bytes(value: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut value = value as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", value) } else { format!("{:.1} {}", value, UNITS[unit]) }
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { (part as f64 / total as f64).clamp(0.0, 1.0) }
+}
+
+fn color(ratio: f64) -> Color {
+    if ratio >= 0.9 { Color::Red } else if ratio >= 0.7 { Color::Yellow } else { Color::Green }
+}
+
+fn sort_disks(disks: &mut [DiskInfo], column: usize, descending: bool) {
+    let used = |disk: &DiskInfo| disk.total_space.saturating_sub(disk.available_space);
+    disks.sort_by(|a, b| {
+        let ordering = match column {
+            0 => a.device_name.cmp(&b.device_name),
+            1 => a.file_system.cmp(&b.file_system),
+            2 => used(a).cmp(&used(b)),
+            3 => a.total_space.cmp(&b.total_space),
+            _ => ratio(used(a), a.total_space).total_cmp(&ratio(used(b), b.total_space)),
+        };
+        if descending { ordering.reverse() } else { ordering }
+    });
+}
+
+fn draw_cpus(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let block = Block::default().borders(Borders::ALL).title(" CPU ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let cpus = snapshot.cpus.as_deref().unwrap_or_default();
+    let rows = (inner.height as usize).max(1);
+    let columns = cpus.len().div_ceil(rows).max(1);
+    let column_areas = Layout::horizontal(vec![Constraint::Ratio(1, columns as u32); columns]).split(inner);
+    for (column, chunk) in cpus.chunks(rows).enumerate() {
+        let row_areas = Layout::vertical(vec![Constraint::Length(1); chunk.len()]).split(column_areas[column]);
+        for (cpu, row_area) in chunk.iter().zip(row_areas.iter()) {
+            let ratio = (cpu.percent / 100.0).clamp(0.0, 1.0);
+            let gauge = LineGauge::default()
+                .ratio(ratio)
+                .label(format!("{:>6} {:5.1}%", cpu.cpu_num, cpu.percent))
+                .filled_style(Style::default().fg(color(ratio)));
+            frame.render_widget(gauge, *row_area);
+        }
+    }
+}
+
+fn draw_memory(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let block = Block::default().borders(Borders::ALL).title(" Memory ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let memory = match &snapshot.memory {
+        Some(memory) => memory,
+        None => return,
+    };
+    let gauges = [
+        ("Memory", memory.used_memory, memory.total_memory),
+        ("Swap", memory.used_swap, memory.total_swap),
+    ];
+    let row_areas = Layout::vertical([Constraint::Length(1); 2]).split(inner);
+    for ((name, used, total), row_area) in gauges.into_iter().zip(row_areas.iter()) {
+        let ratio = ratio(used, total);
+        let gauge = LineGauge::default()
+            .ratio(ratio)
+            .label(format!("{:<6} {:>10} / {:>10}", name, bytes(used), bytes(total)))
+            .filled_style(Style::default().fg(color(ratio)));
+        frame.render_widget(gauge, *row_area);
+    }
+}
+
+fn draw_load(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let text = match &snapshot.load_average {
+        Some(load) => format!("{:.2}  {:.2}  {:.2}   (1, 5, 15 min)", load.one, load.five, load.fifteen),
+        None => "N/A".to_string(),
+    };
+    let temperatures = snapshot.temperatures.iter().flatten()
+        .map(|temperature| format!("{} {:.0}°C", temperature.name, temperature.temperature))
+        .collect::<Vec<_>>()
+        .join("  ");
+    let lines = vec![Line::from(text), Line::from(temperatures)];
+    frame.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" Load / temperatures ")), area);
+}
+
+fn draw_disks(frame: &mut Frame, area: Rect, snapshot: &Snapshot, app: &Dashboard) {
+    let mut disks = snapshot.disks.clone().unwrap_or_default();
+    sort_disks(&mut disks, app.disk_sort, app.descending);
+
+    let header = Row::new(DISK_COLUMNS.iter().enumerate().map(|(i, name)| {
+        let style = Style::default().add_modifier(Modifier::BOLD);
+        if i == app.disk_sort {
+            Cell::from(format!("{}{}", name, if app.descending { " ▼" } else { " ▲" })).style(style.fg(Color::Cyan))
+        } else {
+            Cell::from(*name).style(style)
+        }
+    }));
+    let rows = disks.iter().map(|disk| {
+        let used = disk.total_space.saturating_sub(disk.available_space);
+        let ratio = ratio(used, disk.total_space);
+        Row::new(vec![
+            Cell::from(disk.device_name.clone()),
+            Cell::from(disk.file_system.clone()),
+            Cell::from(bytes(used)),
+            Cell::from(bytes(disk.total_space)),
+            Cell::from(format!("{:.0}%", ratio * 100.0)).style(Style::default().fg(color(ratio))),
+        ])
+    });
+    let widths = [Constraint::Fill(3), Constraint::Fill(1), Constraint::Length(11), Constraint::Length(11), Constraint::Length(5)];
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(" Disks (s: sort, r: reverse) "));
+    frame.render_widget(table, area);
+}
+
+fn draw_networks(frame: &mut Frame, area: Rect, server: &Server) {
+    let block = Block::default().borders(Borders::ALL).title(" Network ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    // Busiest interfaces first, two lines each
+    let mut interfaces: Vec<_> = server.traffic.iter().collect();
+    interfaces.sort_by_key(|(_, history)| std::cmp::Reverse(history.iter().map(|(rx, tx)| rx + tx).sum::<u64>()));
+    interfaces.truncate((inner.height / 2) as usize);
+    if interfaces.is_empty() {
+        frame.render_widget(Paragraph::new("Waiting for a second sample…"), inner);
+        return;
+    }
+
+    let areas = Layout::vertical(vec![Constraint::Length(2); interfaces.len()]).split(inner);
+    for ((name, history), area) in interfaces.into_iter().zip(areas.iter()) {
+        let [label_area, sparkline_area] = Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas(*area);
+        let (rx, tx) = history.back().copied().unwrap_or_default();
+        let label = format!("{}  ↓ {}/s  ↑ {}/s", name, bytes(rx), bytes(tx));
+        frame.render_widget(Paragraph::new(label), label_area);
+
+        // The newest samples are the rightmost ones that fit
+        let data: Vec<u64> = history.iter().map(|(rx, tx)| rx + tx).collect();
+        let data = &data[data.len().saturating_sub(sparkline_area.width as usize)..];
+        frame.render_widget(Sparkline::default().data(data).style(Style::default().fg(Color::Cyan)), sparkline_area);
+    }
+}
+
+fn draw(frame: &mut Frame, app: &Dashboard) {
+    let [tabs_area, body_area, help_area] = Layout::vertical([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1)])
+        .areas(frame.area());
+
+    let titles: Vec<String> = app.servers.iter().map(|server| {
+        let server = server.lock().unwrap();
+        match (&server.snapshot, &server.error) {
+            (_, Some(_)) => format!("{} (down)", server.url),
+            (Some(snapshot), None) => snapshot.hostname.clone(),
+            (None, None) => server.url.clone(),
+        }
+    }).collect();
+    let tabs = Tabs::new(titles)
+        .select(app.selected)
+        .highlight_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+        .block(Block::default().borders(Borders::ALL).title(" sysinfo-top "));
+    frame.render_widget(tabs, tabs_area);
+    frame.render_widget(Paragraph::new("q: quit  tab/←/→: switch server  s: sort disks  r: reverse"), help_area);
+
+    let server = app.servers[app.selected].lock().unwrap();
+    let snapshot = match (&server.snapshot, &server.error) {
+        (Some(snapshot), None) => snapshot,
+        (_, error) => {
+            let message = match error {
+                Some(error) => format!("{}: {}", server.url, error),
+                None => format!("Connecting to {}…", server.url),
+            };
+            frame.render_widget(Paragraph::new(message), body_area);
+            return;
+        }
+    };
+
+    let [left, right] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(body_area);
+    let cpu_rows = snapshot.cpus.as_ref().map_or(1, Vec::len) as u16;
+    let [cpus_area, memory_area, load_area] = Layout::vertical([
+        Constraint::Max(cpu_rows + 2),
+        Constraint::Length(4),
+        Constraint::Length(4),
+    ]).areas(left);
+    let [disks_area, networks_area] = Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(right);
+
+    draw_cpus(frame, cpus_area, snapshot);
+    draw_memory(frame, memory_area, snapshot);
+    draw_load(frame, load_area, snapshot);
+    draw_disks(frame, disks_area, snapshot, app);
+    draw_networks(frame, networks_area, &server);
+}
+
+fn run(terminal: &mut DefaultTerminal, app: &mut Dashboard) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| draw(frame, app))?;
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let servers = app.servers.len();
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Tab | KeyCode::Right => app.selected = (app.selected + 1) % servers,
+                KeyCode::BackTab | KeyCode::Left => app.selected = (app.selected + servers - 1) % servers,
+                KeyCode::Char('s') => app.disk_sort = (app.disk_sort + 1) % DISK_COLUMNS.len(),
+                KeyCode::Char('r') => app.descending = !app.descending,
+                _ => {}
+            }
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let matches = App::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Live dashboard of sysinfo_server_rust servers")
+        .arg(Arg::with_name("server")
+            .help("Servers to watch, as URLs or host:port")
+            .default_value("127.0.0.1:5000")
+            .multiple(true))
+        .arg(Arg::with_name("interval")
+            .long("interval")
+            .value_name("SECONDS")
+            .help("How often every server is polled")
+            .default_value("2")
+            .validator(|value| match value.parse::<u64>() {
+                Ok(secs) if secs > 0 => Ok(()),
+                _ => Err(format!("'{}' is not a positive number of seconds", value)),
+            }))
+        .get_matches();
+    let interval = Duration::from_secs(matches.value_of("interval").unwrap().parse().unwrap());
+
+    let servers: Vec<_> = matches.values_of("server").unwrap().map(|server| {
+        let server = Arc::new(Mutex::new(Server { url: base_url(server), ..Server::default() }));
+        let polled = server.clone();
+        thread::spawn(move || poll(polled, interval));
+        server
+    }).collect();
+
+    let mut app = Dashboard { servers, selected: 0, disk_sort: 0, descending: false };
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sysinfo_server_model::NetworkInfo;
+
+    fn disk(device_name: &str, available_space: u64, total_space: u64) -> DiskInfo {
+        DiskInfo { available_space, device_name: device_name.to_string(), file_system: "ext4".to_string(), mount_point: "/".to_string(), total_space }
+    }
+
+    fn snapshot(timestamp: u64, total_received: u64) -> Snapshot {
+        Snapshot {
+            timestamp,
+            hostname: "host".to_string(),
+            memory: None,
+            cpus: None,
+            temperatures: None,
+            sysinfo: None,
+            disks: None,
+            users: None,
+            networks: Some(vec![NetworkInfo { data_received: 0, data_transmitted: 0, interface_name: "eth0".to_string(), total_received, total_transmitted: 0 }]),
+            load_average: None,
+            boot_time: None,
+        }
+    }
+
+    #[test]
+    fn test_base_url() {
+        assert_eq!(base_url("10.0.0.1:5000"), "http://10.0.0.1:5000");
+        assert_eq!(base_url("https://box.example/sysinfo/"), "https://box.example/sysinfo");
+    }
+
+    #[test]
+    fn test_sort_disks() {
+        let mut disks = vec![disk("sdb", 10, 100), disk("sda", 90, 100), disk("sdc", 0, 1000)];
+        sort_disks(&mut disks, 0, false);
+        assert_eq!(disks.iter().map(|d| d.device_name.as_str()).collect::<Vec<_>>(), ["sda", "sdb", "sdc"]);
+        sort_disks(&mut disks, 4, true);
+        assert_eq!(disks.iter().map(|d| d.device_name.as_str()).collect::<Vec<_>>(), ["sdc", "sdb", "sda"]);
+    }
+
+    #[test]
+    fn test_traffic_rates() {
+        let mut server = Server::default();
+        server.update(snapshot(100, 1000));
+        assert!(server.traffic.is_empty());
+
+        server.update(snapshot(102, 5000));
+        // The same sample served twice adds nothing
+        server.update(snapshot(102, 5000));
+        assert_eq!(server.traffic["eth0"], [(2000, 0)]);
+    }
+}
@@ -0,0 +1,423 @@
+//! Typed async client of sysinfo_server_rust.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), sysinfo_server_client::Error> {
+//! let client = sysinfo_server_client::Client::builder("http://10.0.0.1:5000")
+//!     .bearer_token("dashboard")
+//!     .retries(3)
+//!     .build()?;
+//! let memory = client.memory().await?;
+//! println!("{} uses {} bytes", memory.hostname, memory.data.used_memory);
+//!
+//! let mut samples = client.subscribe(&["cpus"], std::time::Duration::from_secs(5));
+//! while let Ok(snapshot) = samples.next().await {
+//!     println!("{:?}", snapshot.cpus);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::header::{HeaderValue, ETAG, IF_NONE_MATCH, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+pub use sysinfo_server_model as model;
+use sysinfo_server_model::{
+    ApiError, BootTime, CpuInfo, DiskInfo, Envelope, Health, LoadAverage, MemoryInfo, NetworkInfo, Readiness,
+    SelfInfo, Snapshot, SystemInfo, Temperature, UserInfo,
+};
+
+/// Failure of a request.
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent or its response could not be read
+    Http(reqwest::Error),
+    /// The server answered with an error status, with its error body when it sent one
+    Status { status: StatusCode, error: Option<ApiError> },
+    /// The client could not be built, e.g. because of a malformed certificate
+    Config(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "request failed: {}", e),
+            Error::Status { status, error: Some(error) } => write!(f, "server answered {}: {}", status, error.message),
+            Error::Status { status, error: None } => write!(f, "server answered {}", status),
+            Error::Config(message) => write!(f, "invalid client configuration: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+/// Settings of a [`Client`].
+pub struct ClientBuilder {
+    base_url: String,
+    bearer_token: Option<String>,
+    timeout: Duration,
+    retries: u32,
+    backoff: Duration,
+    root_certificates: Vec<Vec<u8>>,
+    accept_invalid_certs: bool,
+}
+
+impl ClientBuilder {
+    /// Sends `token` as a bearer token with every request.
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    /// Timeout of each attempt of a request, 10 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many times a request is retried after connection errors, timeouts,
+    /// `429 Too Many Requests` and `502`/`503`/`504` answers. None by default.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Delay before the first retry, doubled for every further one. 200 ms by default.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Trusts the PEM encoded certificate `pem` in addition to the system's roots.
+    pub fn root_certificate(mut self, pem: &[u8]) -> Self {
+        self.root_certificates.push(pem.to_vec());
+        self
+    }
+
+    /// Skips verification of the server's certificate. Only meant for testing.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let mut http = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .danger_accept_invalid_certs(self.accept_invalid_certs);
+        for pem in &self.root_certificates {
+            let certificate = reqwest::Certificate::from_pem(pem).map_err(|e| Error::Config(e.to_string()))?;
+            http = http.add_root_certificate(certificate);
+        }
+        Ok(Client {
+            http: http.build().map_err(|e| Error::Config(e.to_string()))?,
+            base_url: self.base_url.trim_end_matches('/').to_string(),
+            bearer_token: self.bearer_token,
+            retries: self.retries,
+            backoff: self.backoff,
+        })
+    }
+}
+
+/// Client of one server, cheap to clone.
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    bearer_token: Option<String>,
+    retries: u32,
+    backoff: Duration,
+}
+
+// Answers worth retrying, as the server or a proxy in front of it may recover
+fn is_transient(status: StatusCode) -> bool {
+    matches!(status, StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT)
+}
+
+impl Client {
+    /// Starts building a client of the server at `base_url`, e.g. `http://10.0.0.1:5000`
+    /// or the prefix the server is mounted under.
+    pub fn builder(base_url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.into(),
+            bearer_token: None,
+            timeout: Duration::from_secs(10),
+            retries: 0,
+            backoff: Duration::from_millis(200),
+            root_certificates: Vec::new(),
+            accept_invalid_certs: false,
+        }
+    }
+
+    /// A client of the server at `base_url` with the default settings.
+    pub fn new(base_url: impl Into<String>) -> Result<Client, Error> {
+        Client::builder(base_url).build()
+    }
+
+    // GETs `path`, retrying transient failures. `None` when the server answers
+    // `304 Not Modified` to `etag`.
+    async fn send(&self, path: &str, etag: Option<&HeaderValue>) -> Result<Option<reqwest::Response>, Error> {
+        let mut attempt = 0;
+        loop {
+            let mut request = self.http.get(format!("{}{}", self.base_url, path));
+            if let Some(token) = &self.bearer_token {
+                request = request.bearer_auth(token);
+            }
+            if let Some(etag) = etag {
+                request = request.header(IF_NONE_MATCH, etag.clone());
+            }
+
+            let backoff = self.backoff * 2_u32.saturating_pow(attempt);
+            let retry_after = match request.send().await {
+                Ok(response) if response.status() == StatusCode::NOT_MODIFIED => return Ok(None),
+                Ok(response) if response.status().is_success() => return Ok(Some(response)),
+                Ok(response) if is_transient(response.status()) && attempt < self.retries => {
+                    let retry_after = response.headers().get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|secs| secs.parse().ok())
+                        .map(Duration::from_secs);
+                    retry_after.unwrap_or_default().max(backoff)
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    return Err(Error::Status { status, error: response.json().await.ok() });
+                }
+                Err(e) if (e.is_connect() || e.is_timeout()) && attempt < self.retries => backoff,
+                Err(e) => return Err(e.into()),
+            };
+            tokio::time::sleep(retry_after).await;
+            attempt += 1;
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        match self.send(path, None).await? {
+            Some(response) => Ok(response.json().await?),
+            // Without a validator sent, such as from a misbehaving proxy
+            None => Err(Error::Status { status: StatusCode::NOT_MODIFIED, error: None }),
+        }
+    }
+
+    pub async fn memory(&self) -> Result<Envelope<MemoryInfo>, Error> {
+        self.get("/v2/memory").await
+    }
+
+    pub async fn cpus(&self) -> Result<Envelope<Vec<CpuInfo>>, Error> {
+        self.get("/v2/cpus").await
+    }
+
+    pub async fn temperatures(&self) -> Result<Envelope<Vec<Temperature>>, Error> {
+        self.get("/v2/temperatures").await
+    }
+
+    pub async fn sysinfo(&self) -> Result<Envelope<SystemInfo>, Error> {
+        self.get("/v2/sysinfo").await
+    }
+
+    pub async fn disks(&self) -> Result<Envelope<Vec<DiskInfo>>, Error> {
+        self.get("/v2/disks").await
+    }
+
+    pub async fn users(&self) -> Result<Envelope<Vec<UserInfo>>, Error> {
+        self.get("/v2/users").await
+    }
+
+    pub async fn networks(&self) -> Result<Envelope<Vec<NetworkInfo>>, Error> {
+        self.get("/v2/networks").await
+    }
+
+    pub async fn load_average(&self) -> Result<Envelope<LoadAverage>, Error> {
+        self.get("/v2/load_average").await
+    }
+
+    pub async fn boot_time(&self) -> Result<Envelope<BootTime>, Error> {
+        self.get("/v2/boot_time").await
+    }
+
+    /// The `include`d subsystems sampled at once, all of them when `include` is empty.
+    pub async fn snapshot(&self, include: &[&str]) -> Result<Snapshot, Error> {
+        self.get(&snapshot_path(include)).await
+    }
+
+    /// Statistics of the server process itself.
+    pub async fn self_info(&self) -> Result<SelfInfo, Error> {
+        self.get("/self").await
+    }
+
+    pub async fn health(&self) -> Result<Health, Error> {
+        self.get("/healthz").await
+    }
+
+    /// Readiness of the server. A server that is not ready answers with
+    /// `503 Service Unavailable`, which is returned as its [`Readiness`] as well.
+    pub async fn readiness(&self) -> Result<Readiness, Error> {
+        let mut request = self.http.get(format!("{}/readyz", self.base_url));
+        if let Some(token) = &self.bearer_token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        match response.status() {
+            StatusCode::OK | StatusCode::SERVICE_UNAVAILABLE => Ok(response.json().await?),
+            status => Err(Error::Status { status, error: response.json().await.ok() }),
+        }
+    }
+
+    /// Subscribes to the snapshots of the `include`d subsystems, polling every `interval`.
+    /// Polls are conditional, so each new sample is delivered once.
+    pub fn subscribe(&self, include: &[&str], interval: Duration) -> Subscription {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        Subscription { client: self.clone(), path: snapshot_path(include), interval, etag: None }
+    }
+}
+
+fn snapshot_path(include: &[&str]) -> String {
+    if include.is_empty() {
+        "/snapshot".to_string()
+    } else {
+        format!("/snapshot?include={}", include.join(","))
+    }
+}
+
+/// Snapshots delivered as the server takes new samples, see [`Client::subscribe`].
+pub struct Subscription {
+    client: Client,
+    path: String,
+    interval: tokio::time::Interval,
+    etag: Option<HeaderValue>,
+}
+
+impl Subscription {
+    /// Waits for the next sample. Errors do not end the subscription, the
+    /// following call polls again.
+    pub async fn next(&mut self) -> Result<Snapshot, Error> {
+        loop {
+            self.interval.tick().await;
+            if let Some(response) = self.client.send(&self.path, self.etag.as_ref()).await? {
+                self.etag = response.headers().get(ETAG).cloned();
+                return Ok(response.json().await?);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use super::*;
+    use hyper::{Body, Request, Response, Server};
+    use hyper::service::{make_service_fn, service_fn};
+    use serde_json::json;
+
+    // Answers like the server would: every second request to /v2/memory is rate limited,
+    // /snapshot changes its sample every other poll, and /healthz is not modified though not asked
+    fn fake_server() -> SocketAddr {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let make_service = make_service_fn(move |_| {
+            let requests = requests.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let count = requests.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        let json = |status: u16, body: serde_json::Value| Response::builder()
+                            .status(status)
+                            .header("content-type", "application/json")
+                            .body(Body::from(body.to_string()))
+                            .unwrap();
+                        let response = match req.uri().path() {
+                            "/v2/memory" if count.is_multiple_of(2) => {
+                                let mut response = json(429, json!({"status": 429, "error": "too_many_requests", "message": "slow down"}));
+                                response.headers_mut().insert("retry-after", HeaderValue::from_static("0"));
+                                response
+                            }
+                            "/v2/memory" => json(200, json!({
+                                "timestamp": 1, "hostname": "box", "units": {"total_memory": "bytes"},
+                                "data": {"available_memory": 1, "free_memory": 2, "free_swap": 3, "total_memory": 4,
+                                         "total_swap": 5, "used_memory": 6, "used_swap": 7},
+                            })),
+                            "/snapshot" => {
+                                let etag = format!("\"{}\"", count / 2);
+                                if req.headers().get("if-none-match").is_some_and(|tag| *tag == *etag) {
+                                    Response::builder().status(304).body(Body::empty()).unwrap()
+                                } else {
+                                    let mut response = json(200, json!({"timestamp": count / 2, "hostname": "box"}));
+                                    response.headers_mut().insert("etag", HeaderValue::from_str(&etag).unwrap());
+                                    response
+                                }
+                            }
+                            "/healthz" => Response::builder().status(304).body(Body::empty()).unwrap(),
+                            _ => json(404, json!({"status": 404, "error": "not_found", "message": "No resource"})),
+                        };
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_client() {
+        let addr = fake_server();
+
+        // Rate limited requests are retried
+        let client = Client::builder(format!("http://{}/", addr)).retries(2).backoff(Duration::from_millis(10)).build().unwrap();
+        let memory = client.memory().await.expect("Failed to get memory");
+        assert_eq!(memory.hostname, "box");
+        assert_eq!(memory.data.used_memory, 6);
+
+        let without_retries = Client::new(format!("http://{}", addr)).unwrap();
+        match without_retries.memory().await {
+            Err(Error::Status { status, error: Some(error) }) => {
+                assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+                assert_eq!(error.message, "slow down");
+            }
+            result => panic!("Unexpected {:?}", result.map(|memory| memory.data)),
+        }
+
+        match client.disks().await {
+            Err(Error::Status { status, .. }) => assert_eq!(status, StatusCode::NOT_FOUND),
+            result => panic!("Unexpected {:?}", result.map(|disks| disks.data)),
+        }
+        match client.health().await {
+            Err(Error::Status { status, error: None }) => assert_eq!(status, StatusCode::NOT_MODIFIED),
+            result => panic!("Unexpected {:?}", result),
+        }
+
+        // Unchanged samples are skipped by subscriptions
+        let mut subscription = client.subscribe(&["memory"], Duration::from_millis(10));
+        let first = subscription.next().await.expect("Failed to get a snapshot");
+        let second = subscription.next().await.expect("Failed to get a snapshot");
+        assert!(second.timestamp > first.timestamp);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server() {
+        let client = Client::builder("http://127.0.0.1:9").retries(1).backoff(Duration::from_millis(10)).build().unwrap();
+        match client.health().await {
+            Err(Error::Http(e)) => assert!(e.is_connect()),
+            result => panic!("Unexpected {:?}", result),
+        }
+    }
+}
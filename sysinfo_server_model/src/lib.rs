@@ -0,0 +1,464 @@
+//! Response bodies of every endpoint, shared by the server and its clients.
+//!
+//! Fields of the sysinfo-http compatible resources are declared in alphabetical order
+//! so that serializing a model produces exactly the bytes of the legacy responses. Disks
+//! and networks have gained fields since, which their `V1` models leave out.
+
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Element of the `/memory` response array. All values are in bytes.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct MemoryInfo {
+    pub available_memory: u64,
+    pub free_memory: u64,
+    pub free_swap: u64,
+    pub total_memory: u64,
+    pub total_swap: u64,
+    pub used_memory: u64,
+    pub used_swap: u64,
+}
+
+/// Body of `/cpus`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Cpus {
+    pub cpu_info: Vec<CpuInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CpuInfo {
+    /// `cpu` followed by the index of the CPU
+    pub cpu_num: String,
+    /// Frequency in MHz
+    pub frequency: u64,
+    /// Usage in percent
+    pub percent: f64,
+}
+
+/// Body of `/temperatures`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Temperatures {
+    pub temperature_info: Vec<Temperature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Temperature {
+    pub name: String,
+    /// Temperature in degrees Celsius
+    pub temperature: f64,
+}
+
+/// Element of the `/sysinfo` response array. Unknown values are reported as `N/A`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SystemInfo {
+    pub distribution_id: String,
+    pub host_name: String,
+    pub kernel_version: String,
+    pub long_os_version: String,
+    pub os_version: String,
+}
+
+/// Element of the `/v2/disks` data and of the disks of `/snapshot`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DiskInfo {
+    /// Available space in bytes
+    pub available_space: u64,
+    pub device_name: String,
+    pub file_system: String,
+    /// Directory the disk is mounted on
+    #[serde(default)]
+    pub mount_point: String,
+    /// Total space in bytes
+    pub total_space: u64,
+}
+
+/// Element of the `/disks` response array, unprefixed and below `/v1`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DiskInfoV1 {
+    /// Available space in bytes
+    pub available_space: u64,
+    pub device_name: String,
+    pub file_system: String,
+    /// Total space in bytes
+    pub total_space: u64,
+}
+
+impl From<DiskInfo> for DiskInfoV1 {
+    fn from(disk: DiskInfo) -> DiskInfoV1 {
+        DiskInfoV1 {
+            available_space: disk.available_space,
+            device_name: disk.device_name,
+            file_system: disk.file_system,
+            total_space: disk.total_space,
+        }
+    }
+}
+
+/// Element of the `/users` response array.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct UserInfo {
+    /// Names of the groups the user belongs to
+    pub group: Vec<String>,
+    pub name: String,
+}
+
+/// Element of the `/v2/networks` data and of the networks of `/snapshot`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NetworkInfo {
+    /// Bytes received since the previous refresh
+    pub data_received: u64,
+    /// Bytes transmitted since the previous refresh
+    pub data_transmitted: u64,
+    pub interface_name: String,
+    /// Bytes received since the interface came up, which unlike `data_received` does not depend on
+    /// when others refreshed it
+    #[serde(default)]
+    pub total_received: u64,
+    /// Bytes transmitted since the interface came up
+    #[serde(default)]
+    pub total_transmitted: u64,
+}
+
+/// Element of the `/networks` response array, unprefixed and below `/v1`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NetworkInfoV1 {
+    /// Bytes received since the previous refresh
+    pub data_received: u64,
+    /// Bytes transmitted since the previous refresh
+    pub data_transmitted: u64,
+    pub interface_name: String,
+}
+
+impl From<NetworkInfo> for NetworkInfoV1 {
+    fn from(network: NetworkInfo) -> NetworkInfoV1 {
+        NetworkInfoV1 {
+            data_received: network.data_received,
+            data_transmitted: network.data_transmitted,
+            interface_name: network.interface_name,
+        }
+    }
+}
+
+/// Element of the `/load_average` response array.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct LoadAverage {
+    pub fifteen: f64,
+    pub five: f64,
+    pub one: f64,
+}
+
+/// Body of `/boot_time`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct BootTime {
+    /// Seconds since the Unix epoch
+    pub boot_time: u64,
+}
+
+/// Body of `/self`. Process values are `null` where the platform cannot report them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SelfInfo {
+    pub cpu_time: Option<CpuTime>,
+    /// Usage in percent
+    pub cpu_usage: Option<f64>,
+    /// Resident set size in bytes
+    pub memory_rss: Option<u64>,
+    pub open_fds: Option<u64>,
+    pub pid: u32,
+    /// Refresh durations per subsystem
+    pub refreshes: BTreeMap<String, Histogram>,
+    /// Request latencies per route
+    pub requests: BTreeMap<String, Histogram>,
+    pub threads: Option<u64>,
+    /// Seconds since the process started
+    pub uptime: Option<u64>,
+    pub version: String,
+}
+
+/// Consumed CPU time in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CpuTime {
+    pub system: f64,
+    pub user: f64,
+}
+
+/// Durations in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Histogram {
+    pub buckets: Vec<Bucket>,
+    pub count: u64,
+    pub last: f64,
+    pub sum: f64,
+}
+
+/// Number of observations less than or equal to `le`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Bucket {
+    pub count: u64,
+    pub le: f64,
+}
+
+/// Body of `/healthz`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Health {
+    pub status: String,
+}
+
+/// Body of `/readyz`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Readiness {
+    /// Maximum sample age in seconds
+    pub max_age: u64,
+    pub ready: bool,
+    /// Names of the subsystems that are not fresh
+    pub stale: Vec<String>,
+    pub subsystems: BTreeMap<String, SubsystemReadiness>,
+    /// Names of the configured exporters that failed to export the last sample
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failing: Vec<String>,
+    /// Configured exporters, such as `influxdb` or `mqtt`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub exporters: BTreeMap<String, ExporterReadiness>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SubsystemReadiness {
+    /// Seconds since the last sample, `null` if never sampled
+    pub age: Option<f64>,
+    pub stale: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ExporterReadiness {
+    pub failing: bool,
+}
+
+/// Body of every `/v2` response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Envelope<T> {
+    /// Sample time in seconds since the Unix epoch
+    pub timestamp: u64,
+    pub hostname: String,
+    pub data: T,
+    /// Unit of each numeric field of `data` that has one
+    pub units: BTreeMap<String, String>,
+}
+
+/// Body of `/snapshot`: every subsystem sampled at once. Subsystems left out
+/// with `?include=` are omitted.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Snapshot {
+    /// Sample time in seconds since the Unix epoch
+    pub timestamp: u64,
+    pub hostname: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<Vec<CpuInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperatures: Option<Vec<Temperature>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sysinfo: Option<SystemInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disks: Option<Vec<DiskInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub users: Option<Vec<UserInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub networks: Option<Vec<NetworkInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_average: Option<LoadAverage>,
+    /// Boot time in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boot_time: Option<u64>,
+}
+
+/// Body of `/diff`: what changed between two snapshots of the server's history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotDiff {
+    /// Time of the older snapshot in seconds since the Unix epoch
+    pub from: u64,
+    /// Time of the newer snapshot in seconds since the Unix epoch
+    pub to: u64,
+    pub memory: MemoryDelta,
+    pub disks: DisksDiff,
+    /// Names of the network interfaces that appeared and disappeared
+    pub networks: Changes<String>,
+    /// Names of the users added and removed
+    pub users: Changes<String>,
+    /// Fields of `/sysinfo` that changed, such as `kernel_version` or `host_name`
+    pub sysinfo: BTreeMap<String, Change>,
+}
+
+/// Change of every `/memory` value in bytes, negative when it decreased.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct MemoryDelta {
+    pub available_memory: i64,
+    pub free_memory: i64,
+    pub free_swap: i64,
+    pub total_memory: i64,
+    pub total_swap: i64,
+    pub used_memory: i64,
+    pub used_swap: i64,
+}
+
+/// Disks of a `/diff`, told apart by mount point as a device may be mounted at several.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DisksDiff {
+    pub mounted: Vec<DiskInfo>,
+    pub unmounted: Vec<DiskInfo>,
+    /// Change of the space of the disks mounted in both snapshots whose space changed, by mount point
+    pub space: BTreeMap<String, SpaceDelta>,
+}
+
+/// Change of a disk's space in bytes, negative when it decreased.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SpaceDelta {
+    pub available_space: i64,
+    pub total_space: i64,
+}
+
+/// Items added and removed between two snapshots.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Changes<T> {
+    pub added: Vec<T>,
+    pub removed: Vec<T>,
+}
+
+/// Old and new value of a changed field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Change {
+    pub from: String,
+    pub to: String,
+}
+
+/// Element of the `/alerts` response, one per rule and matching instance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Alert {
+    /// Name of the rule
+    pub rule: String,
+    /// The rule's condition, e.g. `memory.available < 5% for 2m`
+    pub expression: String,
+    /// Disk, component or CPU the condition holds for, when the rule selects one
+    pub instance: Option<String>,
+    pub state: AlertState,
+    /// Value of the metric at the last evaluation, in the metric's unit
+    pub value: f64,
+    /// Threshold the value was compared with, in the metric's unit
+    pub threshold: f64,
+    /// When the condition started to hold, in seconds since the epoch
+    pub active_since: u64,
+    /// When the alert started firing, once the rule's duration had elapsed
+    pub fired_at: Option<u64>,
+    /// When the condition stopped holding
+    pub resolved_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    /// The condition holds, but not yet for the rule's duration
+    Pending,
+    Firing,
+    /// The condition no longer holds after firing
+    Resolved,
+}
+
+/// Body of every `/v2` error response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ApiError {
+    /// HTTP status code
+    pub status: u16,
+    /// Machine readable error kind, e.g. `not_found`
+    pub error: String,
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_models_serialize_like_legacy_json() {
+        let memory = vec![MemoryInfo {
+            available_memory: 1,
+            free_memory: 2,
+            free_swap: 3,
+            total_memory: 4,
+            total_swap: 5,
+            used_memory: 6,
+            used_swap: 7,
+        }];
+        let legacy = json!([{
+            "available_memory": 1,
+            "free_memory": 2,
+            "free_swap": 3,
+            "total_memory": 4,
+            "total_swap": 5,
+            "used_memory": 6,
+            "used_swap": 7,
+        }]);
+        assert_eq!(serde_json::to_string(&memory).unwrap(), legacy.to_string());
+
+        let usage = 12.345_f32;
+        let cpus = Cpus {
+            cpu_info: vec![CpuInfo { cpu_num: "cpu0".to_string(), frequency: 2400, percent: usage as f64 }],
+        };
+        let legacy = json!({ "cpu_info": [{ "cpu_num": "cpu0", "percent": usage, "frequency": 2400 }] });
+        assert_eq!(serde_json::to_string(&cpus).unwrap(), legacy.to_string());
+
+        let disk = DiskInfo {
+            available_space: 10,
+            device_name: "/dev/sda1".to_string(),
+            file_system: "ext4".to_string(),
+            mount_point: "/".to_string(),
+            total_space: 20,
+        };
+        let legacy = json!({ "device_name": "/dev/sda1", "file_system": "ext4", "total_space": 20, "available_space": 10 });
+        assert_eq!(serde_json::to_string(&DiskInfoV1::from(disk)).unwrap(), legacy.to_string());
+
+        let network = NetworkInfo {
+            data_received: 1,
+            data_transmitted: 2,
+            interface_name: "eth0".to_string(),
+            total_received: 3,
+            total_transmitted: 4,
+        };
+        let legacy = json!({ "interface_name": "eth0", "data_received": 1, "data_transmitted": 2 });
+        assert_eq!(serde_json::to_string(&NetworkInfoV1::from(network)).unwrap(), legacy.to_string());
+
+        let users = vec![UserInfo { group: vec!["wheel".to_string()], name: "root".to_string() }];
+        let legacy = json!([{ "name": "root", "group": ["wheel"] }]);
+        assert_eq!(serde_json::to_string(&users).unwrap(), legacy.to_string());
+
+        let readiness = Readiness {
+            max_age: 30,
+            ready: false,
+            stale: vec!["memory".to_string()],
+            subsystems: BTreeMap::from([("memory".to_string(), SubsystemReadiness { age: None, stale: true })]),
+            failing: Vec::new(),
+            exporters: BTreeMap::new(),
+        };
+        let legacy = json!({
+            "ready": false,
+            "max_age": 30,
+            "stale": ["memory"],
+            "subsystems": { "memory": { "age": null, "stale": true } },
+        });
+        assert_eq!(serde_json::to_string(&readiness).unwrap(), legacy.to_string());
+    }
+
+    #[test]
+    fn test_models_round_trip() {
+        let disks = vec![DiskInfo {
+            available_space: 10,
+            device_name: "/dev/sda1".to_string(),
+            file_system: "ext4".to_string(),
+            mount_point: "/".to_string(),
+            total_space: 20,
+        }];
+        let body = serde_json::to_string(&disks).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<DiskInfo>>(&body).unwrap(), disks);
+    }
+}